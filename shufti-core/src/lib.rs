@@ -0,0 +1,563 @@
+//! # shufti-core
+//!
+//! Table construction and algorithm selection shared by the `ShuftiMatcher`
+//! derive macro (compile time) and `shufti-matcher`'s runtime builders, so
+//! both always produce the same tables.
+//!
+//! A byte `b` is accepted when
+//! `low_tab[b & 0xf] & high_tab[b >> 4] & bit_mask != 0`. Each bit of the
+//! tables is a *bucket*; a bucket's accepted bytes are every combination of
+//! its low and high nibbles, so bytes may only share a bucket when those
+//! combinations are all in the set.
+
+/// Search algorithm for a byte set, as chosen by [`select_algorithm`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// One or two byte comparisons per chunk (vermicelli).
+    CmpEq,
+    /// Shufti: two nibble lookups ANDed with a bucket mask.
+    Shufti,
+    /// Shufti over 16 buckets: two table pairs whose chunk masks are OR-ed,
+    /// for sets that need between 9 and 16 buckets.
+    WideShufti,
+    /// Truffle: an exact table for any set, for sets shufti's buckets cannot
+    /// pack.
+    Truffle,
+}
+
+impl Algorithm {
+    /// Short lowercase name, e.g. `"shufti"`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Algorithm::CmpEq => "cmpeq",
+            Algorithm::Shufti => "shufti",
+            Algorithm::WideShufti => "wide-shufti",
+            Algorithm::Truffle => "truffle",
+        }
+    }
+}
+
+impl core::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Pick the cheapest exact algorithm for `needles` (non-empty, unique):
+/// [`CmpEq`](Algorithm::CmpEq) for one or two bytes,
+/// [`Shufti`](Algorithm::Shufti) when the set packs into 8 buckets,
+/// [`WideShufti`](Algorithm::WideShufti) when it packs into `max_buckets`
+/// (16 for a kernel that runs two table pairs, 8 for one that does not), and
+/// [`Truffle`](Algorithm::Truffle) otherwise.
+pub fn select_algorithm(needles: &[u8], max_buckets: u32) -> Algorithm {
+    if needles.len() <= 2 {
+        Algorithm::CmpEq
+    } else if needles.len() <= 8 || build_shufti_buckets(needles, 8).is_some() {
+        Algorithm::Shufti
+    } else if max_buckets > 8 && build_shufti_buckets(needles, max_buckets).is_some() {
+        Algorithm::WideShufti
+    } else {
+        Algorithm::Truffle
+    }
+}
+
+/// Truffle rows: bit `h & 7` of `mask_lo[l]` (when `h < 8`) or `mask_hi[l]`
+/// (when `h >= 8`) is set when byte `h << 4 | l` is in `needles`.
+pub fn build_truffle_masks(needles: &[u8]) -> ([u8; 16], [u8; 16]) {
+    let mut mask_lo = [0u8; 16];
+    let mut mask_hi = [0u8; 16];
+    for &b in needles {
+        let rows = if b & 0x80 == 0 {
+            &mut mask_lo
+        } else {
+            &mut mask_hi
+        };
+        rows[(b & 0x0f) as usize] |= 1 << ((b >> 4) & 7);
+    }
+    (mask_lo, mask_hi)
+}
+
+/// Names accepted by [`class_bytes`].
+pub const CLASS_NAMES: [&str; 15] = [
+    "ascii",
+    "ascii_alpha",
+    "ascii_alnum",
+    "ascii_digit",
+    "ascii_hexdigit",
+    "ascii_lower",
+    "ascii_upper",
+    "ascii_whitespace",
+    "ascii_punct",
+    "ascii_graphic",
+    "control",
+    "non_ascii",
+    "json_structural",
+    "json_whitespace",
+    "uri_reserved",
+];
+
+/// The bytes of a predefined class, in increasing order; `None` for a name
+/// not in [`CLASS_NAMES`]. The `ascii_*` classes and `control` follow the
+/// `u8::is_ascii_*` methods (so `ascii_whitespace` leaves out `\x0b`),
+/// `json_structural` is `[]{}:,`, `json_whitespace` is JSON's insignificant
+/// whitespace and `uri_reserved` is RFC 3986's `:/?#[]@!$&'()*+,;=`.
+pub fn class_bytes(name: &str) -> Option<Vec<u8>> {
+    let member: fn(&u8) -> bool = match name {
+        "ascii" => u8::is_ascii,
+        "ascii_alpha" => u8::is_ascii_alphabetic,
+        "ascii_alnum" => u8::is_ascii_alphanumeric,
+        "ascii_digit" => u8::is_ascii_digit,
+        "ascii_hexdigit" => u8::is_ascii_hexdigit,
+        "ascii_lower" => u8::is_ascii_lowercase,
+        "ascii_upper" => u8::is_ascii_uppercase,
+        "ascii_whitespace" => u8::is_ascii_whitespace,
+        "ascii_punct" => u8::is_ascii_punctuation,
+        "ascii_graphic" => u8::is_ascii_graphic,
+        "control" => u8::is_ascii_control,
+        "non_ascii" => |b| !b.is_ascii(),
+        "json_structural" => |b| b"[]{}:,".contains(b),
+        "json_whitespace" => |b| b" \t\n\r".contains(b),
+        "uri_reserved" => |b| b":/?#[]@!$&'()*+,;=".contains(b),
+        _ => return None,
+    };
+    Some((0..=255u8).filter(member).collect())
+}
+
+/// One bucket per needle; `needles` must be unique and at most 8 long.
+pub fn build_shufti_tables(needles: &[u8]) -> ([u8; 16], [u8; 16], u8) {
+    debug_assert!(needles.len() <= 8);
+    let mut low_tab = [0u8; 16];
+    let mut high_tab = [0u8; 16];
+
+    for (i, &byte) in needles.iter().enumerate() {
+        let bit = 1u8 << i;
+        low_tab[(byte & 0x0f) as usize] |= bit;
+        high_tab[(byte >> 4) as usize] |= bit;
+    }
+
+    let bit_mask = (1u32 << needles.len()).wrapping_sub(1) as u8;
+    (low_tab, high_tab, bit_mask)
+}
+
+/// Bucket-sharing builder limited to 8 buckets; `None` when the set does
+/// not fit.
+pub fn build_shufti_table_slow(targets: &[u8]) -> Option<([u8; 16], [u8; 16], u8)> {
+    let (low_tab, high_tab, mask) = build_shufti_buckets(targets, 8)?;
+    Some((
+        low_tab.map(|b| b as u8),
+        high_tab.map(|b| b as u8),
+        mask as u8,
+    ))
+}
+
+/// The derive macro's rule: sets of up to 8 bytes get one bucket per byte;
+/// larger sets go through the bucket-sharing builder. `needles` must be
+/// unique; `None` when it is empty or needs more than 8 buckets.
+pub fn build_table(needles: &[u8]) -> Option<([u8; 16], [u8; 16], u8)> {
+    match needles.len() {
+        0 => None,
+        1..=8 => Some(build_shufti_tables(needles)),
+        _ => build_shufti_table_slow(needles),
+    }
+}
+
+/// [`build_table`] in a `const` context, over a membership bitmap (bit
+/// `b & 63` of word `b >> 6` set when `b` is a needle) visited in increasing
+/// byte order; produces the same tables as `build_table` on the sorted
+/// needles.
+pub const fn build_table_from_bitmap(bitmap: &[u64; 4]) -> Option<([u8; 16], [u8; 16], u8)> {
+    let Some((low, high, mask)) = build_buckets_from_bitmap(bitmap, 8) else {
+        return None;
+    };
+    let mut low_tab = [0u8; 16];
+    let mut high_tab = [0u8; 16];
+    let mut i = 0;
+    while i < 16 {
+        low_tab[i] = low[i] as u8;
+        high_tab[i] = high[i] as u8;
+        i += 1;
+    }
+    Some((low_tab, high_tab, mask as u8))
+}
+
+/// [`build_table_from_bitmap`] over up to `max_buckets` (≤ 16) buckets:
+/// one bucket per needle for sets of at most 8 bytes, otherwise each byte
+/// joins the first bucket it can share. `None` when the set is empty or does
+/// not fit.
+pub const fn build_buckets_from_bitmap(
+    bitmap: &[u64; 4],
+    max_buckets: u32,
+) -> Option<([u16; 16], [u16; 16], u16)> {
+    let mut low_tab = [0u16; 16];
+    let mut high_tab = [0u16; 16];
+    let mut len = 0;
+    let mut c = 0;
+    while c < 256 {
+        len += bitmap_contains(bitmap, c as u8) as u32;
+        c += 1;
+    }
+    if len == 0 {
+        return None;
+    }
+
+    let mut current_bit = 0;
+    let mut c = 0;
+    while c < 256 {
+        if !bitmap_contains(bitmap, c as u8) {
+            c += 1;
+            continue;
+        }
+        let (hi, lo) = (c >> 4, c & 0x0f);
+        let mut bit = current_bit;
+        if len > 8 {
+            let mut b = 0;
+            while b < current_bit {
+                if is_safe_in_bitmap(b, c as u8, bitmap, &low_tab, &high_tab) {
+                    bit = b;
+                    break;
+                }
+                b += 1;
+            }
+        }
+        if bit == current_bit {
+            if current_bit == max_buckets {
+                return None;
+            }
+            current_bit += 1;
+        }
+        low_tab[lo] |= 1 << bit;
+        high_tab[hi] |= 1 << bit;
+        c += 1;
+    }
+    Some((low_tab, high_tab, ((1u32 << current_bit) - 1) as u16))
+}
+
+const fn bitmap_contains(bitmap: &[u64; 4], b: u8) -> bool {
+    bitmap[(b >> 6) as usize] & (1 << (b & 63)) != 0
+}
+
+/// [`is_safe`] with `targets` given as a bitmap.
+const fn is_safe_in_bitmap(
+    bit_index: u32,
+    candidate: u8,
+    bitmap: &[u64; 4],
+    current_low: &[u16; 16],
+    current_high: &[u16; 16],
+) -> bool {
+    let c_hi = (candidate >> 4) as usize;
+    let c_lo = (candidate & 0x0F) as usize;
+    let bit = 1u16 << bit_index;
+
+    let mut other_hi = 0;
+    while other_hi < 16 {
+        let mut other_lo = 0;
+        while other_lo < 16 {
+            if current_high[other_hi] & bit != 0 && current_low[other_lo] & bit != 0 {
+                let ghost1 = ((c_hi << 4) | other_lo) as u8;
+                let ghost2 = ((other_hi << 4) | c_lo) as u8;
+                if !bitmap_contains(bitmap, ghost1) || !bitmap_contains(bitmap, ghost2) {
+                    return false;
+                }
+            }
+            other_lo += 1;
+        }
+        other_hi += 1;
+    }
+    true
+}
+
+/// Tables accepting a superset of `needles` (unique) in at most 8
+/// buckets: the exact [`build_table`] whenever it fits, otherwise one
+/// bucket per high nibble with the pairs of buckets that admit the fewest
+/// extra bytes merged until 8 remain. `None` when `needles` is empty.
+pub fn build_approximate_table(needles: &[u8]) -> Option<([u8; 16], [u8; 16], u8)> {
+    if needles.is_empty() {
+        return None;
+    }
+    if let Some(exact) = build_table(needles) {
+        return Some(exact);
+    }
+
+    // (high nibbles, low nibbles) of each bucket; starts exact.
+    let (low_rows, _, mask) = build_nibble_rows(needles);
+    let mut buckets: Vec<(u16, u16)> = (0..16)
+        .filter(|&h| mask & (1 << h) != 0)
+        .map(|h| {
+            let lows = (0..16)
+                .filter(|&l| low_rows[l] & (1 << h) != 0)
+                .fold(0u16, |acc, l| acc | 1 << l);
+            (1u16 << h, lows)
+        })
+        .collect();
+    // Buckets keep disjoint high nibbles, so each accepts its own bytes.
+    let accepted = |(highs, lows): (u16, u16)| highs.count_ones() * lows.count_ones();
+    while buckets.len() > 8 {
+        let mut best = (u32::MAX, 0, 0);
+        for i in 0..buckets.len() {
+            for j in i + 1..buckets.len() {
+                let (a, b) = (buckets[i], buckets[j]);
+                let extra = accepted((a.0 | b.0, a.1 | b.1)) - accepted(a) - accepted(b);
+                best = best.min((extra, i, j));
+            }
+        }
+        let (_, i, j) = best;
+        let b = buckets.remove(j);
+        buckets[i] = (buckets[i].0 | b.0, buckets[i].1 | b.1);
+    }
+
+    let mut low_tab = [0u8; 16];
+    let mut high_tab = [0u8; 16];
+    for (bit, &(highs, lows)) in buckets.iter().enumerate() {
+        for n in 0..16 {
+            if highs & (1 << n) != 0 {
+                high_tab[n] |= 1 << bit;
+            }
+            if lows & (1 << n) != 0 {
+                low_tab[n] |= 1 << bit;
+            }
+        }
+    }
+    Some((low_tab, high_tab, 0xff))
+}
+
+/// Buckets of the table [`build_table`] (and so the derive and
+/// `ShuftiTable::try_new`) builds for `needles` (unique); `None` when it does
+/// not fit in 8.
+pub fn table_buckets(needles: &[u8]) -> Option<u32> {
+    build_table(needles).map(|(_, _, mask)| mask.count_ones())
+}
+
+/// Fewest buckets either builder packs `needles` (unique) into; `None` when
+/// neither fits in 8. Can be less than [`table_buckets`], which gives small
+/// sets one bucket per byte.
+pub fn min_buckets(needles: &[u8]) -> Option<u32> {
+    let slow = build_shufti_table_slow(needles).map(|(_, _, mask)| mask.count_ones());
+    let fast = (needles.len() <= 8).then_some(needles.len() as u32);
+    match (slow, fast) {
+        (Some(s), Some(f)) => Some(s.min(f)),
+        (s, f) => s.or(f),
+    }
+}
+
+/// One bucket per high nibble: bucket `h` holds every needle `h << 4 | lo`.
+/// Represents any set exactly in at most 16 buckets.
+pub fn build_nibble_rows(needles: &[u8]) -> ([u16; 16], [u16; 16], u16) {
+    let mut low_tab = [0u16; 16];
+    let mut high_tab = [0u16; 16];
+    let mut mask = 0u16;
+    for &c in needles {
+        let bit = 1u16 << (c >> 4);
+        low_tab[(c & 0x0f) as usize] |= bit;
+        high_tab[(c >> 4) as usize] |= bit;
+        mask |= bit;
+    }
+    (low_tab, high_tab, mask)
+}
+
+/// Bucket-sharing builder over up to `max_buckets` (≤ 16) buckets.
+///
+/// Each byte joins the first bucket it can share without admitting a byte
+/// outside `targets`, or opens a new bucket.
+pub fn build_shufti_buckets(
+    targets: &[u8],
+    max_buckets: u32,
+) -> Option<([u16; 16], [u16; 16], u16)> {
+    debug_assert!(max_buckets <= 16);
+    let mut low_tab = [0u16; 16];
+    let mut high_tab = [0u16; 16];
+    let mut current_bit = 0;
+    let mut assigned_mask = 0u16;
+
+    for &c in targets {
+        if current_bit >= max_buckets {
+            return None;
+        }
+
+        let hi = (c >> 4) as usize;
+        let lo = (c & 0x0F) as usize;
+
+        let mut placed = false;
+        for b in 0..current_bit {
+            if is_safe(b, c, targets, &low_tab, &high_tab) {
+                low_tab[lo] |= 1 << b;
+                high_tab[hi] |= 1 << b;
+                placed = true;
+                break;
+            }
+        }
+
+        if !placed {
+            low_tab[lo] |= 1 << current_bit;
+            high_tab[hi] |= 1 << current_bit;
+            assigned_mask |= 1 << current_bit;
+            current_bit += 1;
+        }
+    }
+    Some((low_tab, high_tab, assigned_mask))
+}
+
+/// Whether adding `candidate` to bucket `bit_index` keeps every byte the
+/// bucket would accept inside `targets`.
+///
+/// For a bucket holding bytes `[s1, s2, s3]`, it accepts every
+/// `high << 4 | low` over their high and low nibbles; adding `candidate` is
+/// only safe when each new combination is itself in `targets`.
+fn is_safe(
+    bit_index: u32,
+    candidate: u8,
+    targets: &[u8],
+    current_low: &[u16; 16],
+    current_high: &[u16; 16],
+) -> bool {
+    let c_hi = (candidate >> 4) as usize;
+    let c_lo = (candidate & 0x0F) as usize;
+    let bit = 1u16 << bit_index;
+
+    for (other_hi, &high) in current_high.iter().enumerate() {
+        for (other_lo, &low) in current_low.iter().enumerate() {
+            if (high & bit != 0) && (low & bit != 0) {
+                let ghost1 = ((c_hi << 4) | other_lo) as u8;
+                let ghost2 = ((other_hi << 4) | c_lo) as u8;
+
+                if !targets.contains(&ghost1) || !targets.contains(&ghost2) {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether the tables accept exactly `needles`.
+    fn exact(tables: ([u16; 16], [u16; 16], u16), needles: &[u8]) -> bool {
+        let (low, high, mask) = tables;
+        (0..=255u8).all(|b| {
+            (low[(b & 0x0f) as usize] & high[(b >> 4) as usize] & mask != 0) == needles.contains(&b)
+        })
+    }
+
+    #[test]
+    fn test_digits_share_one_bucket() {
+        let (_, _, mask) = build_table(b"0123456789").unwrap();
+        assert_eq!(mask, 1);
+        assert_eq!(min_buckets(b"0123456789"), Some(1));
+        assert_eq!(min_buckets(b"\t\r\n "), Some(2));
+        assert_eq!(table_buckets(b"\t\r\n "), Some(4));
+        assert_eq!(table_buckets(b"0123456789"), Some(1));
+    }
+
+    #[test]
+    fn test_builders_are_exact() {
+        let sets: [&[u8]; 3] = [
+            b"\t\r\n",
+            b"abcdefghijklmnopqrstuvwxyz_",
+            b"{}[]:,\" \t\r\n",
+        ];
+        for set in sets {
+            let (low, high, mask) = build_table(set).unwrap();
+            let widened = (low.map(u16::from), high.map(u16::from), u16::from(mask));
+            assert!(exact(widened, set), "{set:?}");
+            assert!(exact(build_nibble_rows(set), set), "{set:?}");
+        }
+        assert_eq!(build_table(b""), None);
+    }
+
+    #[test]
+    fn test_const_builder_matches_build_table() {
+        let spread = b"\x00\x11\x22\x33\x44\x55\x66\x77\x88";
+        let sets: [&[u8]; 6] = [
+            b"\n",
+            b"\t\n\r",
+            b"\t\n\r \",0123456789:[]{}",
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz",
+            spread,
+            b"",
+        ];
+        for set in sets {
+            let mut bitmap = [0u64; 4];
+            for &b in set {
+                bitmap[(b >> 6) as usize] |= 1 << (b & 63);
+            }
+            assert_eq!(
+                build_table_from_bitmap(&bitmap),
+                build_table(set),
+                "{set:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_approximate_table() {
+        assert_eq!(build_approximate_table(b"\t\r\n"), build_table(b"\t\r\n"));
+        assert_eq!(build_approximate_table(b""), None);
+
+        let spread = b"\x00\x11\x22\x33\x44\x55\x66\x77\x88\x99";
+        let (low, high, mask) = build_approximate_table(spread).unwrap();
+        let accepted: Vec<u8> = (0..=255u8)
+            .filter(|&b| low[(b & 0x0f) as usize] & high[(b >> 4) as usize] & mask != 0)
+            .collect();
+        assert!(spread.iter().all(|b| accepted.contains(b)));
+        // Two pairs of diagonal bytes merge, each admitting two extra bytes.
+        assert_eq!(accepted.len(), spread.len() + 4);
+    }
+
+    #[test]
+    fn test_classes() {
+        for name in CLASS_NAMES {
+            let bytes = class_bytes(name).unwrap();
+            assert!(!bytes.is_empty() && bytes.is_sorted(), "{name}");
+        }
+        assert_eq!(class_bytes("ascii_whitespace").unwrap(), b"\t\n\x0c\r ");
+        assert_eq!(class_bytes("json_structural").unwrap(), b",:[]{}");
+        assert_eq!(class_bytes("uri_reserved").unwrap().len(), 18);
+        assert_eq!(class_bytes("control").unwrap().len(), 33);
+        assert_eq!(class_bytes("non_ascii").unwrap().len(), 128);
+        assert_eq!(class_bytes("digit"), None);
+    }
+
+    #[test]
+    fn test_bucket_limit() {
+        let spread = b"\x00\x11\x22\x33\x44\x55\x66\x77\x88";
+        assert_eq!(build_shufti_table_slow(spread), None);
+        assert_eq!(min_buckets(spread), None);
+        let (_, _, mask) = build_shufti_buckets(spread, 16).unwrap();
+        assert_eq!(mask.count_ones(), 9);
+    }
+
+    #[test]
+    fn test_select_algorithm() {
+        assert_eq!(select_algorithm(b"\n", 8), Algorithm::CmpEq);
+        assert_eq!(select_algorithm(b"\r\n", 8), Algorithm::CmpEq);
+        assert_eq!(select_algorithm(b"\t\r\n", 8), Algorithm::Shufti);
+        assert_eq!(
+            select_algorithm(b"abcdefghijklmnopqrstuvwxyz", 8),
+            Algorithm::Shufti
+        );
+        let spread = b"\x00\x11\x22\x33\x44\x55\x66\x77\x88";
+        assert_eq!(select_algorithm(spread, 8), Algorithm::Truffle);
+        assert_eq!(select_algorithm(spread, 16), Algorithm::WideShufti);
+        assert_eq!(Algorithm::Truffle.to_string(), "truffle");
+        assert_eq!(Algorithm::WideShufti.to_string(), "wide-shufti");
+
+        let mut bitmap = [0u64; 4];
+        for &b in spread {
+            bitmap[(b >> 6) as usize] |= 1 << (b & 63);
+        }
+        assert!(build_table_from_bitmap(&bitmap).is_none());
+        let (_, _, mask) = build_buckets_from_bitmap(&bitmap, 16).unwrap();
+        assert_eq!(mask.count_ones(), 9);
+    }
+
+    #[test]
+    fn test_truffle_masks() {
+        let (lo, hi) = build_truffle_masks(b"\x00\x7f\x80\xff");
+        assert_eq!((lo[0], lo[0x0f]), (1, 1 << 7));
+        assert_eq!((hi[0], hi[0x0f]), (1, 1 << 7));
+        assert_eq!(lo.iter().chain(&hi).map(|r| r.count_ones()).sum::<u32>(), 4);
+    }
+}
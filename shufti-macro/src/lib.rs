@@ -1,7 +1,9 @@
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
-use quote::quote;
-use syn::{DeriveInput, Lit, parse_macro_input};
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+use syn::{DeriveInput, Lit, LitInt, LitStr, Token, parse_macro_input};
 
 /// Derive macro that reads `#[shufti(set = "...")]` on a struct and implements
 /// `ShuftiMatcher` for it with tables computed at compile time.
@@ -12,6 +14,82 @@ use syn::{DeriveInput, Lit, parse_macro_input};
 /// #[shufti(set = "[]{}<>()")]
 /// pub struct BracketMatcher;
 /// ```
+///
+/// # Options
+///
+/// - `set = "..."`: the set's bytes. Escapes mean what they mean in any Rust
+///   string (`"\x1b\0"` is ESC and NUL); a non-ASCII char such as `'é'`
+///   contributes every byte of its UTF-8 encoding (`0xc3 0xa9`), and `"\x80"`
+///   and above are not valid in a string at all — use a byte string.
+/// - `set = b"..."`: a byte string set, for bytes that are not UTF-8 (e.g.
+///   `b"\x00\x7f\xff"`); its bytes are also exposed as `SET_BYTES`.
+/// - `ranges = "..."`: bytes given as ranges, added after `set` (which may
+///   then be omitted): `"a-z0-9_"`, `"0x00-0x1f0x7f"`. Endpoints are ASCII
+///   chars or `0xHH`; `\\` takes the next char literally (`"+\\-*"`), and a
+///   leading or trailing `-` is literal.
+/// - `class = "..."`: a predefined class added after `set`, e.g.
+///   `ascii_whitespace`, `ascii_digit`, `ascii_hexdigit`, `ascii_punct`,
+///   `control`, `json_structural` or `uri_reserved` (see
+///   `shufti_core::CLASS_NAMES`). May be repeated.
+/// - `dedup`: drop repeated bytes (keeping the first) instead of failing,
+///   e.g. for sets assembled from overlapping fragments or classes.
+/// - `ascii_case_insensitive`: add the other case of every ASCII letter
+///   (`"e"` also matches `E`; letters whose counterpart is already listed
+///   are left alone). The folded set can need more buckets than the
+///   original: when the set fits 8 buckets and its folded form does not,
+///   the derive fails unless `allow_fallback` (or `approximate`) is also
+///   given, and `ShuftiMatch::ALGORITHM` then reports wide-shufti or truffle.
+/// - `allow_fallback`: accept the slower kernel `ascii_case_insensitive`
+///   can force.
+/// - `negate`: match every byte *not* described by the other keys, e.g. to
+///   find the first invalid byte of an identifier. `SET` and `SET_BYTES`
+///   then list the complement, and the algorithm is chosen for it.
+/// - `approximate`: a set that does not pack into 8 buckets gets a shufti
+///   table over a superset of its bytes plus a bitmap check of each
+///   candidate (`ShuftiMatch::APPROXIMATE`), instead of the 16-bucket or
+///   truffle kernel.
+/// - `also_negated = "TypeName"`: also emit a unit struct `TypeName`, as
+///   visible as the deriving type, implementing `ShuftiMatch` for every byte
+///   *not* in the set, so "find delimiter" and "find non-delimiter" stay in
+///   sync. With `negate`, the sibling matches the listed bytes instead.
+/// - `name = "..."`: the attribute describes a separate named set rather
+///   than the type's own. `name = "ws"` generates inherent `find_first_ws`,
+///   `find_iter_ws`, `count_ws`, `contains_ws` and `const fn is_ws`, each
+///   backed by a private matcher built from that attribute's other keys;
+///   attributes sharing a name are merged. A type with only named sets does
+///   not implement `ShuftiMatch` itself:
+///
+///   ```rust,ignore
+///   #[derive(ShuftiMatcher)]
+///   #[shufti(name = "ws", set = "\t\r\n ")]
+///   #[shufti(name = "digits", ranges = "0-9")]
+///   struct Lexer;
+///
+///   assert_eq!(Lexer::find_first_digits(b"x = 42"), Some(4));
+///   ```
+///
+/// # Enums
+///
+/// On an enum of unit variants, each variant takes its own `#[shufti(...)]`
+/// (any key but `name` and `also_negated`) and no two variants may share a byte. The enum
+/// implements `ShuftiMatch` for the union of the sets and gets
+/// `const fn classify(b: u8) -> Option<Self>`, plus `find_first_classified`
+/// and `find_iter_classified`, which pair each position with its variant:
+///
+/// ```rust,ignore
+/// #[derive(ShuftiMatcher, Debug, PartialEq)]
+/// enum Token {
+///     #[shufti(set = "\t\r\n ")]
+///     Space,
+///     #[shufti(ranges = "0-9")]
+///     Digit,
+///     #[shufti(set = "+-*/")]
+///     Operator,
+/// }
+///
+/// assert_eq!(Token::classify(b'7'), Some(Token::Digit));
+/// assert_eq!(Token::find_first_classified(b"x+1"), Some((1, Token::Operator)));
+/// ```
 #[proc_macro_derive(ShuftiMatcher, attributes(shufti))]
 pub fn derive_shufti_matcher(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
@@ -21,58 +99,505 @@ pub fn derive_shufti_matcher(input: TokenStream) -> TokenStream {
     }
 }
 
+/// Fails compilation unless the table the derive (and
+/// `ShuftiTable::try_new`) builds for `set` uses at most `buckets` shufti
+/// buckets. Sets of up to 8 bytes get one bucket per byte. Duplicate bytes
+/// are ignored.
+///
+/// # Example
+/// ```rust,ignore
+/// shufti_assert_buckets!("\t\r\n ", 4);
+/// shufti_assert_buckets!("0123456789", 1);
+/// ```
+#[proc_macro]
+pub fn shufti_assert_buckets(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as AssertBuckets);
+    match impl_assert_buckets(&input) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct AssertBuckets {
+    set: LitStr,
+    buckets: LitInt,
+}
+
+impl Parse for AssertBuckets {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let set = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let buckets = input.parse()?;
+        input.parse::<Option<Token![,]>>()?;
+        Ok(Self { set, buckets })
+    }
+}
+
+fn impl_assert_buckets(input: &AssertBuckets) -> syn::Result<TokenStream2> {
+    let limit: u32 = input.buckets.base10_parse()?;
+    // Keep the written order: the bucket-sharing builder is order-sensitive.
+    let mut needles: Vec<u8> = input.set.value().bytes().collect();
+    let mut seen = [false; 256];
+    needles.retain(|&b| !std::mem::replace(&mut seen[b as usize], true));
+
+    let needed = shufti_core::table_buckets(&needles);
+    match needed {
+        Some(n) if n <= limit => Ok(quote! { const _: () = (); }),
+        Some(n) => Err(syn::Error::new_spanned(
+            &input.set,
+            format!("shufti set needs {n} buckets, more than the asserted {limit}"),
+        )),
+        None => Err(syn::Error::new_spanned(
+            &input.set,
+            "shufti set cannot be packed into 8 buckets",
+        )),
+    }
+}
+
 fn impl_shufti_matcher(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    if let syn::Data::Enum(data) = &input.data {
+        return enum_impl(input, data);
+    }
+    let mut out = TokenStream2::new();
+    for options in parse_sets(&input.attrs)? {
+        out.extend(match &options.name {
+            None => matcher_impl(&input.ident, &input.generics, &options, &input.ident)?,
+            Some(set_name) => named_set_impl(input, set_name, &options)?,
+        });
+        if let Some(negated) = &options.also_negated {
+            out.extend(negated_impl(input, &options, negated)?);
+        }
+    }
+    Ok(out)
+}
+
+/// A `#[shufti(name = "ws", ...)]` set: a private matcher type plus
+/// `find_first_ws`-style inherent functions on the deriving type.
+fn named_set_impl(
+    input: &DeriveInput,
+    set_name: &syn::Ident,
+    options: &Options,
+) -> syn::Result<TokenStream2> {
     let name = &input.ident;
-    let generics = &input.generics;
-    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let set_ty = syn::Ident::new("Set", proc_macro2::Span::call_site());
+    let matcher = matcher_impl(&set_ty, &syn::Generics::default(), options, set_name)?;
+
+    let find_first = format_ident!("find_first_{}", set_name);
+    let find_iter = format_ident!("find_iter_{}", set_name);
+    let count = format_ident!("count_{}", set_name);
+    let contains = format_ident!("contains_{}", set_name);
+    let is_member = format_ident!("is_{}", set_name);
+    let doc = |what: &str| format!("{what} the `{set_name}` set.");
+    let (find_first_doc, find_iter_doc, count_doc, contains_doc, is_member_doc) = (
+        doc("Position of the first byte of `haystack` in"),
+        doc("Positions of every byte of `haystack` in"),
+        doc("Number of bytes of `haystack` in"),
+        doc("Whether any byte of `haystack` is in"),
+        doc("Whether `b` is in"),
+    );
+
+    Ok(quote! {
+        const _: () = {
+            // Never constructed: only its constants and associated functions
+            // are used.
+            enum #set_ty {}
+
+            #matcher
+
+            impl #impl_generics #name #ty_generics #where_clause {
+                #[doc = #find_first_doc]
+                #[inline(always)]
+                pub fn #find_first<H: ::shufti_matcher::Haystack + ?Sized>(
+                    haystack: &H,
+                ) -> ::core::option::Option<usize> {
+                    <#set_ty as ::shufti_matcher::ShuftiMatch>::find_first(haystack)
+                }
+
+                #[doc = #find_iter_doc]
+                #[inline(always)]
+                pub fn #find_iter<H: ::shufti_matcher::Haystack + ?Sized>(
+                    haystack: &H,
+                ) -> impl ::core::iter::DoubleEndedIterator<Item = usize> + '_ {
+                    <#set_ty as ::shufti_matcher::ShuftiMatch>::find_iter(haystack)
+                }
+
+                #[doc = #count_doc]
+                #[inline(always)]
+                pub fn #count<H: ::shufti_matcher::Haystack + ?Sized>(haystack: &H) -> usize {
+                    <#set_ty as ::shufti_matcher::ShuftiMatch>::count(haystack)
+                }
+
+                #[doc = #contains_doc]
+                #[inline(always)]
+                pub fn #contains<H: ::shufti_matcher::Haystack + ?Sized>(haystack: &H) -> bool {
+                    <#set_ty as ::shufti_matcher::ShuftiMatch>::contains_any(haystack)
+                }
+
+                #[doc = #is_member_doc]
+                #[inline(always)]
+                pub const fn #is_member(b: u8) -> bool {
+                    #set_ty::is_member(b)
+                }
+            }
+        };
+    })
+}
+
+/// The `also_negated` sibling of a set: a unit struct, as visible as the
+/// deriving type, matching every byte the set does not.
+fn negated_impl(
+    input: &DeriveInput,
+    options: &Options,
+    negated: &syn::Ident,
+) -> syn::Result<TokenStream2> {
+    let vis = &input.vis;
+    let doc = match &options.name {
+        Some(set_name) => format!(
+            "The bytes not in the `{set_name}` set of [`{}`].",
+            input.ident
+        ),
+        None => format!("The bytes not in [`{}`].", input.ident),
+    };
+    let complement = Options {
+        name: None,
+        also_negated: None,
+        negate: !options.negate,
+        ..options.clone()
+    };
+    let matcher = matcher_impl(negated, &syn::Generics::default(), &complement, negated)?;
+    Ok(quote! {
+        #[doc = #doc]
+        #vis struct #negated;
+
+        #matcher
+    })
+}
+
+/// An enum of byte classes: every unit variant carries its own
+/// `#[shufti(...)]` set, the enum matches their union, and `classify` maps a
+/// byte back to its variant.
+fn enum_impl(input: &DeriveInput, data: &syn::DataEnum) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    if let Some(attr) = input.attrs.iter().find(|a| a.path().is_ident("shufti")) {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "on an enum, #[shufti(...)] goes on each variant",
+        ));
+    }
+    if data.variants.is_empty() {
+        return Err(syn::Error::new_spanned(
+            name,
+            "enum has no variants to match",
+        ));
+    }
+
+    // Which variant each byte belongs to, if any.
+    let mut owner: [Option<usize>; 256] = [None; 256];
+    let mut union = Options::default();
+    let mut union_set = Vec::new();
+    for (index, variant) in data.variants.iter().enumerate() {
+        let ident = &variant.ident;
+        if !matches!(variant.fields, syn::Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                "ShuftiMatcher enum variants must be unit variants",
+            ));
+        }
+        if !variant.attrs.iter().any(|a| a.path().is_ident("shufti")) {
+            return Err(syn::Error::new_spanned(
+                ident,
+                format!("variant `{ident}` is missing #[shufti(set = \"...\")]"),
+            ));
+        }
+        let sets = parse_sets(&variant.attrs)?;
+        let options = match sets.as_slice() {
+            [options] if options.name.is_none() => options,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "named sets are not supported on enum variants",
+                ));
+            }
+        };
+        if let Some(negated) = &options.also_negated {
+            return Err(syn::Error::new_spanned(
+                negated,
+                "`also_negated` is not supported on enum variants",
+            ));
+        }
+        let needles = needles_of(options, ident)?;
+        for &b in &needles {
+            if let Some(other) = owner[b as usize].replace(index) {
+                let other = &data.variants[other].ident;
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    format!("byte 0x{b:02x} is in the sets of both `{other}` and `{ident}`"),
+                ));
+            }
+        }
+        union_set.extend(needles);
+        union.byte_string |= options.spelled();
+        union.approximate |= options.approximate;
+    }
+    union.set = Some(union_set);
+    let matcher = matcher_impl(name, &input.generics, &union, name)?;
 
-    // Parse #[shufti(set = "...")] attribute
-    let set_str = extract_set_attr(&input.attrs)?;
-    let needles: Vec<u8> = set_str.bytes().collect();
+    // One arm per variant, with runs of consecutive bytes as ranges.
+    let arms = data.variants.iter().enumerate().map(|(index, variant)| {
+        let ident = &variant.ident;
+        let mut patterns = Vec::new();
+        let mut b = 0usize;
+        while b < 256 {
+            if owner[b] != Some(index) {
+                b += 1;
+                continue;
+            }
+            let lo = b as u8;
+            while b + 1 < 256 && owner[b + 1] == Some(index) {
+                b += 1;
+            }
+            let hi = b as u8;
+            patterns.push(if lo == hi {
+                quote! { #lo }
+            } else {
+                quote! { #lo..=#hi }
+            });
+            b += 1;
+        }
+        quote! { #(#patterns)|* => ::core::option::Option::Some(Self::#ident), }
+    });
+
+    Ok(quote! {
+        #matcher
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// The variant whose set contains `b`, if any.
+            #[inline]
+            pub const fn classify(b: u8) -> ::core::option::Option<Self> {
+                match b {
+                    #(#arms)*
+                    _ => ::core::option::Option::None,
+                }
+            }
+
+            /// Position of the first byte of `haystack` in any variant's set,
+            /// and that variant.
+            #[inline]
+            pub fn find_first_classified<H: ::shufti_matcher::Haystack + ?Sized>(
+                haystack: &H,
+            ) -> ::core::option::Option<(usize, Self)> {
+                let at = <Self as ::shufti_matcher::ShuftiMatch>::find_first(haystack)?;
+                Self::classify(haystack.as_bytes()[at]).map(|class| (at, class))
+            }
+
+            /// Every byte of `haystack` in a variant's set, with its variant.
+            #[inline]
+            pub fn find_iter_classified<H: ::shufti_matcher::Haystack + ?Sized>(
+                haystack: &H,
+            ) -> impl ::core::iter::DoubleEndedIterator<Item = (usize, Self)> + '_ {
+                let bytes = haystack.as_bytes();
+                <Self as ::shufti_matcher::ShuftiMatch>::find_iter(haystack)
+                    .filter_map(move |at| Self::classify(bytes[at]).map(|class| (at, class)))
+            }
+        }
+    })
+}
+
+/// The bytes `options` describe, in order, after `dedup`,
+/// `ascii_case_insensitive` and `negate`. Errors point at `span`.
+fn needles_of(options: &Options, span: &syn::Ident) -> syn::Result<Vec<u8>> {
+    let set = options.set.as_deref().unwrap_or_default();
+    let mut needles = [set, &options.expanded].concat();
 
     if needles.is_empty() {
         return Err(syn::Error::new_spanned(
-            &input.ident,
+            span,
             "shufti set must have >=1 bytes",
         ));
     }
 
+    if options.dedup {
+        let mut seen = [false; 256];
+        needles.retain(|&b| !std::mem::replace(&mut seen[b as usize], true));
+    }
+
     // Check uniqueness
     for i in 0..needles.len() {
         for j in (i + 1)..needles.len() {
             if needles[i] == needles[j] {
-                return Err(syn::Error::new_spanned(
-                    &input.ident,
-                    format!("shufti set contains duplicate byte 0x{:02x}", needles[i]),
-                ));
+                let mut message =
+                    format!("shufti set contains duplicate byte 0x{:02x}", needles[i]);
+                // A repeated UTF-8 lead byte is easy to miss: name the chars.
+                let origin = |k| utf8_origin(options, k).map(|c| format!("'{c}'"));
+                let chars: Vec<String> = [origin(i), origin(j)].into_iter().flatten().collect();
+                if !chars.is_empty() {
+                    message += &format!(" (UTF-8 encoding of {})", chars.join(" and "));
+                }
+                return Err(syn::Error::new_spanned(span, message));
             }
         }
     }
 
-    // Compute tables at compile time (same logic as build_shufti_fast)
-    let res = if needles.len() <= 8 {
-        Some(build_shufti_tables(&needles))
-    } else {
-        build_shufti_table_slow(&needles)
-    };
+    let complement =
+        |needles: &[u8]| -> Vec<u8> { (0..=255u8).filter(|b| !needles.contains(b)).collect() };
+    let listed = needles.clone();
+    if options.ascii_case_insensitive.is_some() {
+        for i in 0..needles.len() {
+            let other = needles[i] ^ 0x20;
+            if needles[i].is_ascii_alphabetic() && !needles.contains(&other) {
+                needles.push(other);
+            }
+        }
+    }
 
-    let Some((low_tab, high_tab, bit_mask)) = res else {
-        return Err(syn::Error::new_spanned(
-            &input.ident,
-            format!("failed to build shufti table"),
-        ));
+    if options.negate {
+        needles = complement(&needles);
+        if needles.is_empty() {
+            return Err(syn::Error::new_spanned(
+                span,
+                "negated shufti set is empty: every byte is listed",
+            ));
+        }
+    }
+
+    // Folding must not silently cost the 8-bucket kernel.
+    if let Some(fold_span) = options.ascii_case_insensitive
+        && !options.allow_fallback
+        && !options.approximate
+    {
+        let unfolded = if options.negate {
+            complement(&listed)
+        } else {
+            listed
+        };
+        if fits_8_buckets(&unfolded) && !fits_8_buckets(&needles) {
+            return Err(syn::Error::new(
+                fold_span,
+                "`ascii_case_insensitive` makes this set need more than 8 shufti buckets; \
+                 add `allow_fallback` to accept the 16-bucket or truffle kernel",
+            ));
+        }
+    }
+    Ok(needles)
+}
+
+/// Whether the derive runs `needles` on the 8-bucket shufti (or cmpeq)
+/// kernel.
+fn fits_8_buckets(needles: &[u8]) -> bool {
+    !needles.is_empty()
+        && shufti_core::select_algorithm(needles, 8) != shufti_core::Algorithm::Truffle
+}
+
+/// The `ShuftiMatch` impl of `self_ty` for one set, plus its inherent
+/// `is_member`. Errors point at `span`.
+fn matcher_impl(
+    self_ty: &syn::Ident,
+    generics: &syn::Generics,
+    options: &Options,
+    span: &syn::Ident,
+) -> syn::Result<TokenStream2> {
+    let name = self_ty;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let needles = needles_of(options, span)?;
+
+    // Pick the algorithm and compute its tables at compile time (shared with
+    // the runtime builders).
+    use shufti_core::Algorithm;
+    let algorithm = shufti_core::select_algorithm(&needles, 16);
+    // Shufti over a superset of the bytes, verified per candidate.
+    let approximate =
+        options.approximate && matches!(algorithm, Algorithm::WideShufti | Algorithm::Truffle);
+    let (low_tab, high_tab, bit_mask) = if approximate {
+        shufti_core::build_approximate_table(&needles).unwrap_or_default()
+    } else {
+        shufti_core::build_table(&needles).unwrap_or_default()
     };
 
     let low_tab_tokens = low_tab.iter().map(|b| quote! { #b});
     let high_tab_tokens = high_tab.iter().map(|b| quote! { #b});
 
+    let mut bitmap = [0u64; 4];
+    for &b in &needles {
+        bitmap[(b >> 6) as usize] |= 1 << (b & 63);
+    }
+    let bitmap_tokens = bitmap.iter().map(|w| quote! { #w });
+
+    // `BUCKET_OF` is exact membership: zero iff the byte is outside `BITMAP`.
+    let is_member = |b: u8| bitmap[(b >> 6) as usize] & (1 << (b & 63)) != 0;
+    let bucket_of_tokens = (0..=255u8).map(|b| {
+        let bits = low_tab[(b & 0x0f) as usize] & high_tab[(b >> 4) as usize] & bit_mask;
+        let bits = match algorithm {
+            // The table's false positives keep no bucket.
+            _ if approximate => bits * u8::from(is_member(b)),
+            // No 8-bucket table: every member is in "bucket" 1.
+            Algorithm::WideShufti | Algorithm::Truffle => u8::from(is_member(b)),
+            _ => bits,
+        };
+        quote! { #bits }
+    });
+
+    let kernel = match algorithm {
+        // One or two bytes: a cmpeq loop beats the table lookups.
+        Algorithm::CmpEq => {
+            let (a, b) = (needles[0], needles[needles.len() - 1]);
+            quote! {
+                const CMPEQ: ::core::option::Option<[u8; 2]> =
+                    ::core::option::Option::Some([#a, #b]);
+            }
+        }
+        _ if approximate => quote! {
+            const APPROXIMATE: bool = true;
+        },
+        Algorithm::WideShufti => {
+            let (low_tab, high_tab, bit_mask) =
+                shufti_core::build_shufti_buckets(&needles, 16).unwrap_or_default();
+            quote! {
+                const WIDE: ::core::option::Option<::shufti_matcher::WideShuftiTable> =
+                    ::core::option::Option::Some(::shufti_matcher::WideShuftiTable::from_buckets(
+                        &[#(#low_tab),*],
+                        &[#(#high_tab),*],
+                        #bit_mask,
+                    ));
+            }
+        }
+        Algorithm::Truffle => {
+            let (mask_lo, mask_hi) = shufti_core::build_truffle_masks(&needles);
+            quote! {
+                const TRUFFLE: ::core::option::Option<::shufti_matcher::TruffleTable> =
+                    ::core::option::Option::Some(::shufti_matcher::TruffleTable {
+                        mask_lo: [#(#mask_lo),*],
+                        mask_hi: [#(#mask_hi),*],
+                    });
+            }
+        }
+        _ => quote! {},
+    };
+
     let needle_len = needles.len();
-    let set_repr = set_str.clone();
+    // `dedup` can split a UTF-8 sequence, so check rather than assume.
+    let utf8 = (!options.spelled())
+        .then(|| String::from_utf8(needles.clone()).ok())
+        .flatten();
+    let (set_repr, set_bytes) = match utf8 {
+        Some(set) => (set, quote! {}),
+        None => {
+            // `SET` spells each byte as the Latin-1 char of the same code point.
+            let spelled: String = needles.iter().map(|&b| char::from(b)).collect();
+            let bytes = syn::LitByteStr::new(&needles, proc_macro2::Span::call_site());
+            (spelled, quote! { const SET_BYTES: &'static [u8] = #bytes; })
+        }
+    };
 
     Ok(quote! {
         impl #impl_generics ::shufti_matcher::ShuftiMatch for #name #ty_generics #where_clause {
             const SET: &'static str = #set_repr;
+            #set_bytes
             const NEEDLE_COUNT: usize = #needle_len;
+            const BUCKET_OF: [u8; 256] = [#(#bucket_of_tokens),*];
+            const BITMAP: [u64; 4] = [#(#bitmap_tokens),*];
+            #kernel
 
             #[inline(always)]
             fn table() -> ::shufti_matcher::ShuftiTable {
@@ -83,6 +608,15 @@ fn impl_shufti_matcher(input: &DeriveInput) -> syn::Result<TokenStream2> {
                 }
             }
         }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Whether `b` is in the set; a scalar bitmap test usable in
+            /// `const` contexts.
+            #[inline(always)]
+            pub const fn is_member(b: u8) -> bool {
+                <Self as ::shufti_matcher::ShuftiMatch>::BITMAP[(b >> 6) as usize] & (1u64 << (b & 63)) != 0
+            }
+        }
     })
 }
 
@@ -90,127 +624,647 @@ fn impl_shufti_matcher(input: &DeriveInput) -> syn::Result<TokenStream2> {
 // Attribute parsing
 // ---------------------------------------------------------------------------
 
-fn extract_set_attr(attrs: &[syn::Attribute]) -> syn::Result<String> {
+/// The non-ASCII char of a string set whose UTF-8 encoding contains byte
+/// `index`.
+fn utf8_origin(options: &Options, index: usize) -> Option<char> {
+    let set = options.set.as_deref().filter(|_| !options.byte_string)?;
+    if index >= set.len() {
+        return None;
+    }
+    let set = std::str::from_utf8(set).ok()?;
+    let (_, c) = set
+        .char_indices()
+        .take_while(|&(start, _)| start <= index)
+        .last()?;
+    (!c.is_ascii()).then_some(c)
+}
+
+/// Everything `#[shufti(...)]` can say about one matcher.
+#[derive(Clone, Default)]
+struct Options {
+    /// `None` for the deriving type's own set; otherwise the suffix of the
+    /// inherent functions generated for a named set.
+    name: Option<syn::Ident>,
+    /// The bytes of the `set` literal, in order.
+    set: Option<Vec<u8>>,
+    /// Whether `set` was written as a byte string (`b"..."`).
+    byte_string: bool,
+    /// The bytes of the `ranges` and `class` keys, expanded in attribute
+    /// order; they follow `set`.
+    expanded: Vec<u8>,
+    /// Drop repeated bytes instead of rejecting them.
+    dedup: bool,
+    /// Add the other case of every ASCII letter.
+    ascii_case_insensitive: Option<proc_macro2::Span>,
+    /// Accept the 16-bucket or truffle kernel when `ascii_case_insensitive`
+    /// pushes the set past 8 buckets.
+    allow_fallback: bool,
+    /// Match the bytes *not* listed.
+    negate: bool,
+    /// Over-approximate sets that do not pack into 8 buckets instead of
+    /// using the 16-bucket or truffle kernel.
+    approximate: bool,
+    /// A sibling type to generate for the complement of the set.
+    also_negated: Option<syn::Ident>,
+}
+
+impl Options {
+    /// Whether `SET` must spell the bytes one char per byte: the resolved
+    /// bytes need not be the UTF-8 of anything the user wrote.
+    fn spelled(&self) -> bool {
+        self.byte_string || self.negate || !self.expanded.is_ascii()
+    }
+
+    /// Fold a later attribute for the same set into this one: a later `set`
+    /// replaces an earlier one, everything else accumulates.
+    fn merge(&mut self, later: Options) {
+        if later.set.is_some() {
+            self.set = later.set;
+            self.byte_string = later.byte_string;
+        }
+        self.expanded.extend(later.expanded);
+        self.dedup |= later.dedup;
+        self.ascii_case_insensitive = self.ascii_case_insensitive.or(later.ascii_case_insensitive);
+        self.allow_fallback |= later.allow_fallback;
+        self.negate |= later.negate;
+        self.approximate |= later.approximate;
+        if later.also_negated.is_some() {
+            self.also_negated = later.also_negated;
+        }
+    }
+}
+
+/// Group the `#[shufti(...)]` attributes by `name`, in order of first
+/// appearance; attributes without a `name` describe the deriving type.
+fn parse_sets(attrs: &[syn::Attribute]) -> syn::Result<Vec<Options>> {
+    let mut sets: Vec<Options> = Vec::new();
     for attr in attrs {
         if !attr.path().is_ident("shufti") {
             continue;
         }
 
-        let mut found: Option<String> = None;
+        let mut part = Options::default();
         attr.parse_nested_meta(|meta| {
-            if meta.path.is_ident("set") {
+            if meta.path.is_ident("name") {
+                let lit: LitStr = meta.value()?.parse()?;
+                let name = lit.parse::<syn::Ident>().map_err(|_| {
+                    syn::Error::new_spanned(&lit, "`name` must be an identifier, e.g. \"ws\"")
+                })?;
+                part.name = Some(name);
+                Ok(())
+            } else if meta.path.is_ident("also_negated") {
+                let lit: LitStr = meta.value()?.parse()?;
+                let negated = lit.parse::<syn::Ident>().map_err(|_| {
+                    syn::Error::new_spanned(
+                        &lit,
+                        "`also_negated` must be a type name, e.g. \"NotWhitespace\"",
+                    )
+                })?;
+                part.also_negated = Some(negated);
+                Ok(())
+            } else if meta.path.is_ident("set") {
                 let value = meta.value()?; // consumes `=`
-                let lit: Lit = value.parse()?;
-                if let Lit::Str(ls) = lit {
-                    found = Some(ls.value());
-                    Ok(())
-                } else {
-                    Err(meta.error("expected string literal for `set`"))
+                match value.parse()? {
+                    Lit::Str(ls) => {
+                        part.set = Some(ls.value().into_bytes());
+                        part.byte_string = false;
+                    }
+                    Lit::ByteStr(bs) => {
+                        part.set = Some(bs.value());
+                        part.byte_string = true;
+                    }
+                    _ => return Err(meta.error("expected string or byte string literal for `set`")),
                 }
+                Ok(())
+            } else if meta.path.is_ident("ranges") {
+                let lit: LitStr = meta.value()?.parse()?;
+                let bytes =
+                    parse_ranges(&lit.value()).map_err(|e| syn::Error::new_spanned(&lit, e))?;
+                part.expanded.extend(bytes);
+                Ok(())
+            } else if meta.path.is_ident("class") {
+                let lit: LitStr = meta.value()?.parse()?;
+                let bytes = shufti_core::class_bytes(&lit.value()).ok_or_else(|| {
+                    let names = shufti_core::CLASS_NAMES.join(", ");
+                    syn::Error::new_spanned(
+                        &lit,
+                        format!("unknown class `{}`; expected one of: {names}", lit.value()),
+                    )
+                })?;
+                part.expanded.extend(bytes);
+                Ok(())
+            } else if meta.path.is_ident("dedup") {
+                part.dedup = true;
+                Ok(())
+            } else if meta.path.is_ident("ascii_case_insensitive") {
+                part.ascii_case_insensitive = Some(meta.path.span());
+                Ok(())
+            } else if meta.path.is_ident("allow_fallback") {
+                part.allow_fallback = true;
+                Ok(())
+            } else if meta.path.is_ident("negate") {
+                part.negate = true;
+                Ok(())
+            } else if meta.path.is_ident("approximate") {
+                part.approximate = true;
+                Ok(())
             } else {
                 Err(meta.error("unknown shufti attribute key"))
             }
         })?;
+        match sets.iter_mut().find(|options| options.name == part.name) {
+            Some(options) => options.merge(part),
+            None => sets.push(part),
+        }
+    }
+    if sets.is_empty() {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "missing #[shufti(set = \"...\")] attribute",
+        ));
+    }
+    for options in &sets {
+        if options.set.is_none() && options.expanded.is_empty() {
+            let message = match &options.name {
+                Some(name) => {
+                    format!("named shufti set `{name}` has no `set`, `ranges` or `class`")
+                }
+                None => "missing #[shufti(set = \"...\")] attribute".to_string(),
+            };
+            return Err(syn::Error::new(proc_macro2::Span::call_site(), message));
+        }
+    }
+    Ok(sets)
+}
 
-        if let Some(s) = found {
-            return Ok(s);
+/// Expand a `ranges` string: a sequence of `lo-hi` ranges and single bytes,
+/// where each endpoint is an ASCII char, `0xHH`, or `\` followed by a char
+/// to take literally (e.g. `\-`). A `-` that does not sit between two
+/// endpoints is literal, as in a regex class.
+fn parse_ranges(text: &str) -> Result<Vec<u8>, String> {
+    if !text.is_ascii() {
+        return Err("`ranges` must be ASCII; write bytes above 0x7f as 0xHH".into());
+    }
+    let bytes = text.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let (lo, next) = range_endpoint(bytes, i)?;
+        if bytes.get(next) == Some(&b'-') && next + 1 < bytes.len() {
+            let (hi, after) = range_endpoint(bytes, next + 1)?;
+            if lo > hi {
+                return Err(format!("range 0x{lo:02x}-0x{hi:02x} is reversed"));
+            }
+            out.extend(lo..=hi);
+            i = after;
+        } else {
+            out.push(lo);
+            i = next;
         }
     }
-    Err(syn::Error::new(
-        proc_macro2::Span::call_site(),
-        "missing #[shufti(set = \"...\")] attribute",
-    ))
+    Ok(out)
 }
 
-// ---------------------------------------------------------------------------
-// Compile-time table construction (mirrors build_shufti_fast)
-// ---------------------------------------------------------------------------
+/// The endpoint starting at `bytes[i]` and the index just past it.
+fn range_endpoint(bytes: &[u8], i: usize) -> Result<(u8, usize), String> {
+    match bytes[i..] {
+        [b'0', b'x', hi, lo, ..] if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() => {
+            let hex = std::str::from_utf8(&bytes[i + 2..i + 4]).unwrap();
+            Ok((u8::from_str_radix(hex, 16).unwrap(), i + 4))
+        }
+        [b'\\', c, ..] => Ok((c, i + 2)),
+        [b'\\'] => Err("`ranges` ends with a lone `\\`".into()),
+        [c, ..] => Ok((c, i + 1)),
+        [] => unreachable!("called past the end of `ranges`"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
 
-fn build_shufti_tables(needles: &[u8]) -> ([u8; 16], [u8; 16], u8) {
-    let mut low_tab = [0u8; 16];
-    let mut high_tab = [0u8; 16];
+    use super::*;
 
-    for (i, &byte) in needles.iter().enumerate() {
-        let bit = 1u8 << i;
-        low_tab[(byte & 0x0f) as usize] |= bit;
-        high_tab[(byte >> 4) as usize] |= bit;
+    fn assert_buckets(set: &str, buckets: u32) -> syn::Result<TokenStream2> {
+        impl_assert_buckets(&syn::parse_str(&format!("{set:?}, {buckets}")).unwrap())
     }
 
-    let bit_mask = (1u32 << needles.len()).wrapping_sub(1) as u8;
-    (low_tab, high_tab, bit_mask)
-}
+    #[test]
+    fn test_assert_buckets() {
+        assert!(assert_buckets("0123456789", 1).is_ok());
+        // Up to 8 bytes get one bucket each, as in the derived table.
+        assert!(assert_buckets("\t\r\n ", 4).is_ok());
+        assert!(assert_buckets("\t\t\n", 2).is_ok());
+
+        let err = assert_buckets("\t\r\n ", 2).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "shufti set needs 4 buckets, more than the asserted 2"
+        );
 
-fn build_shufti_table_slow(targets: &[u8]) -> Option<([u8; 16], [u8; 16], u8)> {
-    let mut low_tab = [0u8; 16];
-    let mut high_tab = [0u8; 16];
-    let mut current_bit = 0;
-    let mut assigned_mask = 0u8;
+        // Needs more than 8 buckets ("ÿ" adds the bytes C3 BF).
+        let spread = "\x00\x11\x22\x33\x44\x55\x66\x77ÿ";
+        assert!(assert_buckets(spread, 8).is_err());
+    }
 
-    for &c in targets {
-        if current_bit >= 8 {
-            return None;
+    #[test]
+    fn test_assert_buckets_matches_derive() {
+        for set in [
+            "\t\r\n ",
+            "abc",
+            "[]{}<>()",
+            "0123456789",
+            "0123456789abcdefABCDEF",
+        ] {
+            let input = syn::parse2(quote! {
+                #[shufti(set = #set)]
+                struct M;
+            })
+            .unwrap();
+            let ts = impl_shufti_matcher(&input).unwrap().to_string();
+            let bit_mask = ts.split("bit_mask : ").nth(1).unwrap();
+            let bit_mask: u8 = bit_mask.split("u8").next().unwrap().parse().unwrap();
+            let used = bit_mask.count_ones();
+            assert!(assert_buckets(set, used).is_ok(), "{set:?}");
+            assert!(assert_buckets(set, used - 1).is_err(), "{set:?}");
         }
+    }
 
-        let hi = (c >> 4) as usize;
-        let lo = (c & 0x0F) as usize;
+    #[test]
+    fn test_cmpeq_for_small_sets() {
+        let derive = |set: &str| {
+            let input = syn::parse2(quote! {
+                #[shufti(set = #set)]
+                struct M;
+            })
+            .unwrap();
+            impl_shufti_matcher(&input).unwrap().to_string()
+        };
+        let cmpeq = "const CMPEQ : :: core :: option :: Option < [u8 ; 2] > =";
+        assert!(derive("\n").contains(cmpeq));
+        assert!(derive("\n").contains(":: Some ([10u8 , 10u8])"));
+        assert!(derive("\r\n").contains(":: Some ([13u8 , 10u8])"));
+        assert!(!derive("\t\r\n").contains("CMPEQ"));
+    }
 
-        let mut placed = false;
-        for b in 0..current_bit {
-            if is_safe(b, c, targets, &low_tab, &high_tab) {
-                low_tab[lo] |= 1 << b;
-                high_tab[hi] |= 1 << b;
-                placed = true;
-                break;
-            }
-        }
+    #[test]
+    fn test_wide_and_truffle_for_unpackable_sets() {
+        let input = syn::parse2(quote! {
+            #[shufti(set = "\x00\x11\x22\x33\x44\x55\x66\x77\x08")]
+            struct Spread;
+        })
+        .unwrap();
+        let ts = impl_shufti_matcher(&input).unwrap().to_string();
+        assert!(ts.contains("const WIDE"));
+        assert!(ts.contains("bit_mask : 0u8"));
+        assert!(!ts.contains("CMPEQ") && !ts.contains("TRUFFLE"));
 
-        if !placed && current_bit < 8 {
-            low_tab[lo] |= 1 << current_bit;
-            high_tab[hi] |= 1 << current_bit;
-            assigned_mask |= 1 << current_bit;
-            current_bit += 1;
-        }
+        // Every byte whose nibbles differ: more than 16 buckets.
+        let spread: Vec<u8> = (0..=255u8).filter(|b| b >> 4 != b & 0x0f).collect();
+        let spread = syn::LitByteStr::new(&spread, proc_macro2::Span::call_site());
+        let input = syn::parse2(quote! {
+            #[shufti(set = #spread)]
+            struct Spread;
+        })
+        .unwrap();
+        let ts = impl_shufti_matcher(&input).unwrap().to_string();
+        assert!(ts.contains("const TRUFFLE"));
+        assert!(!ts.contains("WIDE"));
     }
-    Some((low_tab, high_tab, assigned_mask))
-}
 
-/// for a bit(bucket), suppose there are chars [s1, s2, s3]
-/// we have the low parts and high parts:
-///     [s1_low, s2_low, s3_low]
-///     [s1_high, s2_high, s3_high]
-/// we have to make sure: for any c = high<<4 + s1_low, c must belongs to this bucket
-/// in other words, if it's well-formed after adding candidate to the `bit_index`,
-/// then it's ok to do so.
-fn is_safe(
-    bit_index: u8,
-    candidate: u8,
-    targets: &[u8],
-    current_low: &[u8; 16],
-    current_high: &[u8; 16],
-) -> bool {
-    let c_hi = (candidate >> 4) as usize;
-    let c_lo = (candidate & 0x0F) as usize;
-    let bit = 1 << bit_index;
+    fn set_bytes(attr: TokenStream2) -> Vec<u8> {
+        let input: DeriveInput = syn::parse2(quote! { #attr struct M; }).unwrap();
+        parse_sets(&input.attrs).unwrap().remove(0).set.unwrap()
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(set_bytes(quote! { #[shufti(set = "\x1b\x00")] }), [0x1b, 0]);
+        assert_eq!(
+            set_bytes(quote! { #[shufti(set = "\0\t\\\"")] }),
+            b"\0\t\\\""
+        );
+        assert_eq!(
+            set_bytes(quote! { #[shufti(set = "\u{1b}\u{7f}")] }),
+            [0x1b, 0x7f]
+        );
+        assert_eq!(
+            set_bytes(quote! { #[shufti(set = "\u{e9}")] }),
+            "é".as_bytes()
+        );
+        assert_eq!(set_bytes(quote! { #[shufti(set = r"\x")] }), b"\\x");
+        assert_eq!(
+            set_bytes(quote! { #[shufti(set = "a\
+            b")] }),
+            b"ab"
+        );
+        assert_eq!(
+            set_bytes(quote! { #[shufti(set = b"\x80\xff")] }),
+            [0x80, 0xff]
+        );
+
+        let input = syn::parse2(quote! {
+            #[shufti(set = "\u{e9}\u{e8}")]
+            struct Accents;
+        })
+        .unwrap();
+        let err = impl_shufti_matcher(&input).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "shufti set contains duplicate byte 0xc3 (UTF-8 encoding of 'é' and 'è')"
+        );
+        let input = syn::parse2(quote! {
+            #[shufti(set = "\x1b\u{1b}")]
+            struct Esc;
+        })
+        .unwrap();
+        let err = impl_shufti_matcher(&input).err().unwrap();
+        assert_eq!(err.to_string(), "shufti set contains duplicate byte 0x1b");
+    }
 
-    for other_hi in 0..16 {
-        for other_lo in 0..16 {
-            if (current_high[other_hi] & bit != 0) && (current_low[other_lo] & bit != 0) {
-                let ghost1 = ((c_hi << 4) | other_lo) as u8;
-                let ghost2 = ((other_hi << 4) | c_lo) as u8;
+    #[test]
+    fn test_byte_string_set() {
+        let input = syn::parse2(quote! {
+            #[shufti(set = b"\x00\x7f\xff")]
+            struct Binary;
+        })
+        .unwrap();
+        let ts = impl_shufti_matcher(&input).unwrap().to_string();
+        assert!(ts.contains(r#"const SET : & 'static str = "\0\u{7f}ÿ" ;"#));
+        assert!(ts.contains(r#"const SET_BYTES : & 'static [u8] = b"\0\x7F\xFF" ;"#));
 
-                if !targets.contains(&ghost1) || !targets.contains(&ghost2) {
-                    return false;
-                }
+        let input = syn::parse2(quote! {
+            #[shufti(set = 7)]
+            struct Int;
+        })
+        .unwrap();
+        let err = impl_shufti_matcher(&input).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "expected string or byte string literal for `set`"
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let ranges = |text: &str| parse_ranges(text);
+        assert_eq!(ranges("a-e0-2_").unwrap(), b"abcde012_");
+        assert_eq!(ranges("0x00-0x030x7f").unwrap(), [0, 1, 2, 3, 0x7f]);
+        assert_eq!(ranges("0x7e-0x80").unwrap(), [0x7e, 0x7f, 0x80]);
+        assert_eq!(ranges(r"+\-*").unwrap(), b"+-*");
+        assert_eq!(ranges("-a-b-").unwrap(), b"-ab-");
+        assert_eq!(ranges(r"\\-\]").unwrap(), b"\\]");
+        assert_eq!(ranges("0-9").unwrap(), b"0123456789");
+        assert_eq!(ranges("").unwrap(), b"");
+        assert_eq!(ranges("z-a").unwrap_err(), "range 0x7a-0x61 is reversed");
+        assert_eq!(ranges(r"a\").unwrap_err(), "`ranges` ends with a lone `\\`");
+        assert!(ranges("é").is_err());
+
+        let input = syn::parse2(quote! {
+            #[shufti(set = "_", ranges = "a-z0x80-0x81")]
+            struct Ident;
+        })
+        .unwrap();
+        let ts = impl_shufti_matcher(&input).unwrap().to_string();
+        assert!(ts.contains("const NEEDLE_COUNT : usize = 29usize"));
+        assert!(ts.contains(r#"b"_abcdefghijklmnopqrstuvwxyz\x80\x81""#));
+
+        let input = syn::parse2(quote! {
+            #[shufti(set = "a", ranges = "a-c")]
+            struct Overlap;
+        })
+        .unwrap();
+        let err = impl_shufti_matcher(&input).err().unwrap();
+        assert_eq!(err.to_string(), "shufti set contains duplicate byte 0x61");
+    }
+
+    #[test]
+    fn test_named_sets() {
+        let derive = |attrs: TokenStream2| {
+            let input = syn::parse2(quote! { #attrs struct Lexer; }).unwrap();
+            impl_shufti_matcher(&input).map(|ts| ts.to_string())
+        };
+        let ts = derive(quote! {
+            #[shufti(name = "ws", set = "\t ")]
+            #[shufti(name = "digits", ranges = "0-9")]
+            #[shufti(name = "ws", set = "\n")]
+        })
+        .unwrap();
+        assert!(ts.contains("pub fn find_first_ws"));
+        assert!(ts.contains("pub const fn is_digits"));
+        assert!(ts.contains("const SET : & 'static str = \"\\n\""));
+        assert!(!ts.contains("ShuftiMatch for Lexer"));
+
+        let err = derive(quote! { #[shufti(name = "white space", set = " ")] }).unwrap_err();
+        assert_eq!(err.to_string(), "`name` must be an identifier, e.g. \"ws\"");
+        let err = derive(quote! { #[shufti(name = "ws", dedup)] }).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "named shufti set `ws` has no `set`, `ranges` or `class`"
+        );
+    }
+
+    #[test]
+    fn test_enum() {
+        let derive = |item: TokenStream2| {
+            let input = syn::parse2(item).unwrap();
+            impl_shufti_matcher(&input).map(|ts| ts.to_string())
+        };
+        let ts = derive(quote! {
+            enum Class {
+                #[shufti(ranges = "0-9")]
+                Digit,
+                #[shufti(set = "-+")]
+                Sign,
             }
-        }
+        })
+        .unwrap();
+        assert!(ts.contains("ShuftiMatch for Class"));
+        assert!(ts.contains("const SET : & 'static str = \"0123456789-+\""));
+        assert!(
+            ts.contains("48u8 ..= 57u8 => :: core :: option :: Option :: Some (Self :: Digit)")
+        );
+        assert!(ts.contains("43u8 | 45u8 => :: core :: option :: Option :: Some (Self :: Sign)"));
+
+        let err = derive(quote! {
+            enum Class {
+                #[shufti(ranges = "a-z")]
+                Lower,
+                #[shufti(set = "xyz", ascii_case_insensitive)]
+                Xyz,
+            }
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "byte 0x78 is in the sets of both `Lower` and `Xyz`"
+        );
+        let err = derive(quote! { enum Class { #[shufti(set = "a")] A(u8) } }).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ShuftiMatcher enum variants must be unit variants"
+        );
+        let err = derive(quote! { enum Class { #[shufti(set = "a")] A, B } }).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "variant `B` is missing #[shufti(set = \"...\")]"
+        );
+        let err = derive(quote! { #[shufti(set = "a")] enum Class { A } }).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "on an enum, #[shufti(...)] goes on each variant"
+        );
     }
-    true
-}
 
-#[cfg(test)]
-mod tests {
+    #[test]
+    fn test_also_negated() {
+        let derive = |attrs: TokenStream2| {
+            let input = syn::parse2(quote! { #attrs pub(crate) struct Blank; }).unwrap();
+            impl_shufti_matcher(&input).map(|ts| ts.to_string())
+        };
+        let ts = derive(quote! { #[shufti(set = "\t ", also_negated = "NotBlank")] }).unwrap();
+        assert!(ts.contains("pub (crate) struct NotBlank ;"));
+        assert!(ts.contains("ShuftiMatch for NotBlank"));
+        assert!(ts.contains("const NEEDLE_COUNT : usize = 254usize"));
 
-    use super::*;
+        let err = derive(quote! { #[shufti(set = "\t", also_negated = "Not Tab")] }).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`also_negated` must be a type name, e.g. \"NotWhitespace\""
+        );
+        let input = syn::parse2(quote! {
+            enum Class { #[shufti(set = "a", also_negated = "NotA")] A }
+        })
+        .unwrap();
+        assert_eq!(
+            impl_shufti_matcher(&input).unwrap_err().to_string(),
+            "`also_negated` is not supported on enum variants"
+        );
+    }
+
+    #[test]
+    fn test_classes() {
+        let input: DeriveInput = syn::parse2(quote! {
+            #[shufti(class = "ascii_digit", class = "json_structural", set = "-")]
+            struct Number;
+        })
+        .unwrap();
+        let options = parse_sets(&input.attrs).unwrap().remove(0);
+        assert_eq!(options.set.unwrap(), b"-");
+        assert_eq!(options.expanded, b"0123456789,:[]{}");
+
+        let input = syn::parse2(quote! {
+            #[shufti(class = "digits")]
+            struct Typo;
+        })
+        .unwrap();
+        let err = impl_shufti_matcher(&input).err().unwrap();
+        assert!(
+            err.to_string()
+                .starts_with("unknown class `digits`; expected one of: ascii,")
+        );
+    }
+
+    #[test]
+    fn test_ascii_case_insensitive() {
+        let derive = |attr: TokenStream2| {
+            let input = syn::parse2(quote! { #attr struct M; }).unwrap();
+            impl_shufti_matcher(&input).map(|ts| ts.to_string())
+        };
+        let folded = derive(quote! { #[shufti(set = "e+-", ascii_case_insensitive)] }).unwrap();
+        assert!(folded.contains(r#"const SET : & 'static str = "e+-E" ;"#));
+        assert!(folded.contains("const NEEDLE_COUNT : usize = 4usize"));
+        let mixed = derive(quote! { #[shufti(set = "xX1", ascii_case_insensitive)] }).unwrap();
+        assert!(mixed.contains("const NEEDLE_COUNT : usize = 3usize"));
+        // Duplicates in the set itself are still errors.
+        assert!(derive(quote! { #[shufti(set = "ee", ascii_case_insensitive)] }).is_err());
+
+        // Eight buckets, but 'A' shares none of them (it would need '@').
+        let set = quote! { set = b"`a\x02\x13\x24\x35\x5b\x7c\x8d" };
+        assert!(
+            derive(quote! { #[shufti(#set)] })
+                .unwrap()
+                .contains("bit_mask : 255u8")
+        );
+        let err = derive(quote! { #[shufti(#set, ascii_case_insensitive)] }).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`ascii_case_insensitive` makes this set need more than 8 shufti buckets; \
+             add `allow_fallback` to accept the 16-bucket or truffle kernel"
+        );
+        let ts = derive(quote! { #[shufti(#set, ascii_case_insensitive, allow_fallback)] });
+        assert!(ts.unwrap().contains("const WIDE"));
+        let ts = derive(quote! { #[shufti(#set, ascii_case_insensitive, approximate)] });
+        assert!(ts.unwrap().contains("const APPROXIMATE : bool = true"));
+    }
+
+    #[test]
+    fn test_dedup() {
+        let derive = |attr: TokenStream2| {
+            let input = syn::parse2(quote! { #attr struct M; }).unwrap();
+            impl_shufti_matcher(&input).map(|ts| ts.to_string())
+        };
+        let ts = derive(quote! { #[shufti(set = "abca", ranges = "a-c", dedup)] }).unwrap();
+        assert!(ts.contains(r#"const SET : & 'static str = "abc" ;"#));
+        assert!(!ts.contains("SET_BYTES"));
+        // Dropping the second 0xc3 leaves "éè" as bytes C3 A9 A8.
+        let ts = derive(quote! { #[shufti(set = "éè", dedup)] }).unwrap();
+        assert!(ts.contains(r#"const SET_BYTES : & 'static [u8] = b"\xC3\xA9\xA8" ;"#));
+        let ts =
+            derive(quote! { #[shufti(class = "ascii_hexdigit", class = "ascii_digit", dedup)] });
+        assert!(ts.unwrap().contains("const NEEDLE_COUNT : usize = 22usize"));
+        assert!(derive(quote! { #[shufti(set = "abca")] }).is_err());
+    }
+
+    #[test]
+    fn test_negate() {
+        let derive = |attr: TokenStream2| {
+            let input = syn::parse2(quote! { #attr struct M; }).unwrap();
+            impl_shufti_matcher(&input).map(|ts| ts.to_string())
+        };
+        let ts = derive(quote! { #[shufti(class = "ascii", negate)] }).unwrap();
+        assert!(ts.contains("const NEEDLE_COUNT : usize = 128usize"));
+        assert!(ts.contains(r#"const SET_BYTES : & 'static [u8] = b"\x80\x81"#));
+        let ts = derive(quote! { #[shufti(set = "a", ascii_case_insensitive, negate)] }).unwrap();
+        assert!(ts.contains("const NEEDLE_COUNT : usize = 254usize"));
+        let err = derive(quote! { #[shufti(ranges = "0x00-0xff", negate)] }).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "negated shufti set is empty: every byte is listed"
+        );
+    }
+
+    #[test]
+    fn test_approximate_option() {
+        let input = syn::parse2(quote! {
+            #[shufti(set = "\x00\x11\x22\x33\x44\x55\x66\x77é", approximate)]
+            struct Spread;
+        })
+        .unwrap();
+        let ts = impl_shufti_matcher(&input).unwrap().to_string();
+        assert!(ts.contains("const APPROXIMATE : bool = true ;"));
+        assert!(!ts.contains("TRUFFLE"));
+
+        // Sets that pack exactly ignore the option.
+        let input = syn::parse2(quote! {
+            #[shufti(set = "abc", approximate)]
+            struct Abc;
+        })
+        .unwrap();
+        assert!(
+            !impl_shufti_matcher(&input)
+                .unwrap()
+                .to_string()
+                .contains("APPROXIMATE")
+        );
+
+        let input = syn::parse2(quote! {
+            #[shufti(set = "abc", aproximate)]
+            struct Typo;
+        })
+        .unwrap();
+        let err = impl_shufti_matcher(&input).err().unwrap();
+        assert_eq!(err.to_string(), "unknown shufti attribute key");
+    }
 
     #[test]
     fn test() {
@@ -222,9 +1276,19 @@ mod tests {
         .unwrap();
 
         let ts = impl_shufti_matcher(&input).unwrap();
+        let bucket_of = (0..=255u8)
+            .map(|b| match b {
+                b'a' => "1u8",
+                b'b' => "2u8",
+                b'c' => "4u8",
+                _ => "0u8",
+            })
+            .collect::<Vec<_>>()
+            .join(" , ");
         assert_eq!(
             ts.to_string(),
-            r#"impl :: shufti_matcher :: ShuftiMatch for MyMatcher { const SET : & 'static str = "abc" ; const NEEDLE_COUNT : usize = 3usize ; # [inline (always)] fn table () -> :: shufti_matcher :: ShuftiTable { :: shufti_matcher :: ShuftiTable { low_tab : [0u8 , 1u8 , 2u8 , 4u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8] , high_tab : [0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 7u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8] , bit_mask : 7u8 , } } }"#
+            r#"impl :: shufti_matcher :: ShuftiMatch for MyMatcher { const SET : & 'static str = "abc" ; const NEEDLE_COUNT : usize = 3usize ; const BUCKET_OF : [u8 ; 256] = [BUCKETS] ; const BITMAP : [u64 ; 4] = [0u64 , 60129542144u64 , 0u64 , 0u64] ; # [inline (always)] fn table () -> :: shufti_matcher :: ShuftiTable { :: shufti_matcher :: ShuftiTable { low_tab : [0u8 , 1u8 , 2u8 , 4u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8] , high_tab : [0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 7u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8 , 0u8] , bit_mask : 7u8 , } } } impl MyMatcher { # [doc = r" Whether `b` is in the set; a scalar bitmap test usable in"] # [doc = r" `const` contexts."] # [inline (always)] pub const fn is_member (b : u8) -> bool { < Self as :: shufti_matcher :: ShuftiMatch > :: BITMAP [(b >> 6) as usize] & (1u64 << (b & 63)) != 0 } }"#
+                .replace("BUCKETS", &bucket_of)
         );
     }
 }
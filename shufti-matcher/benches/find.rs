@@ -73,6 +73,7 @@ fn make_table(needles: &[u8]) -> ShuftiTable {
 
 /// Shufti search: 16-byte SIMD chunks + scalar epilogue.
 #[inline(never)]
+#[cfg_attr(feature = "no-unsafe", allow(unused_unsafe))]
 fn shufti_search(table: &ShuftiTable, haystack: &[u8]) -> Option<usize> {
     let mut offset = 0;
     while offset + 16 <= haystack.len() {
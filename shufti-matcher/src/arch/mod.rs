@@ -1,12 +1,17 @@
 cfg_if::cfg_if! {
-    if #[cfg(target_arch = "x86_64")] {
+    if #[cfg(feature = "no-unsafe")] {
+        mod safe;
+        pub use safe::*;
+    } else if #[cfg(target_arch = "x86_64")] {
         mod x86_64;
         pub use x86_64::*;
     } else if #[cfg(all(target_feature="neon", target_arch="aarch64"))] {
         mod neon;
         pub use neon::*;
     } else {
-       #[inline(always)]
+        pub const BACKEND: Backend = Backend::Scalar;
+
+        #[inline(always)]
         pub unsafe fn bitmask_16b(
             low_tab: &[u8; 16],
             high_tab: &[u8; 16],
@@ -23,5 +28,86 @@ cfg_if::cfg_if! {
             }
             mask
         }
+
+        #[inline(always)]
+        pub unsafe fn truffle_16b(
+            mask_lo: &[u8; 16],
+            mask_hi: &[u8; 16],
+            data: &[u8; 16],
+        ) -> u16 {
+            let mut mask = 0u16;
+            for (i, &b) in data.iter().enumerate() {
+                let rows = if b & 0x80 == 0 { mask_lo } else { mask_hi };
+                if rows[(b & 0x0f) as usize] & (1 << ((b >> 4) & 7)) != 0 {
+                    mask |= 1 << i;
+                }
+            }
+            mask
+        }
+
+        #[inline(always)]
+        pub unsafe fn cmpeq_16b(a: u8, b: u8, data: &[u8; 16]) -> u16 {
+            let mut mask = 0u16;
+            for (i, &x) in data.iter().enumerate() {
+                if x == a || x == b {
+                    mask |= 1 << i;
+                }
+            }
+            mask
+        }
     }
 }
+
+/// Index of the first matching lane of `data`, from the full lane mask.
+#[cfg(not(any(
+    feature = "no-unsafe",
+    all(target_feature = "neon", target_arch = "aarch64")
+)))]
+#[inline(always)]
+pub unsafe fn first_match_16b(
+    low_tab: &[u8; 16],
+    high_tab: &[u8; 16],
+    bit_mask: u8,
+    data: &[u8; 16],
+) -> Option<u32> {
+    let mask = unsafe { bitmask_16b(low_tab, high_tab, bit_mask, data) };
+    (mask != 0).then(|| mask.trailing_zeros())
+}
+
+/// The chunk kernel selected for this build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Backend {
+    /// Portable byte-at-a-time loop.
+    Scalar,
+    /// x86_64 `pshufb` kernel.
+    Ssse3,
+    /// AArch64 `tbl` kernel.
+    Neon,
+}
+
+impl Backend {
+    /// Short lowercase name, e.g. `"ssse3"`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Backend::Scalar => "scalar",
+            Backend::Ssse3 => "ssse3",
+            Backend::Neon => "neon",
+        }
+    }
+}
+
+impl core::fmt::Display for Backend {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The backend every search in this build runs on.
+///
+/// Kernels are chosen at compile time from the enabled target features (see
+/// `.cargo/config.toml`), so this is a constant for a given binary. Log it at
+/// startup and include it in performance reports.
+pub const fn active_backend() -> Backend {
+    BACKEND
+}
@@ -1,6 +1,12 @@
 use core::arch::aarch64::*;
 
-pub unsafe fn bitmask_16b(low_tab: &[u8; 16], high_tab: &[u8; 16], bit_mask: u8, data: &[u8; 16]) -> u16 {
+use crate::arch::Backend;
+
+pub const BACKEND: Backend = Backend::Neon;
+
+/// 0xFF in every lane of `data` that belongs to the set, 0 elsewhere.
+#[inline(always)]
+unsafe fn matches_16b(low_tab: &[u8; 16], high_tab: &[u8; 16], bit_mask: u8, data: &[u8; 16]) -> uint8x16_t {
     unsafe {
         let l_tab = vld1q_u8(low_tab.as_ptr());
         let h_tab = vld1q_u8(high_tab.as_ptr());
@@ -15,9 +21,14 @@ pub unsafe fn bitmask_16b(low_tab: &[u8; 16], high_tab: &[u8; 16], bit_mask: u8,
         let v = vandq_u8(lo_sf, hi_sf);
 
         // vtstq_u8: sets byte to 0xFF if (v & bit_mask) != 0
-        let matches = vtstq_u8(v, vmovq_n_u8(bit_mask));
+        vtstq_u8(v, vmovq_n_u8(bit_mask))
+    }
+}
 
-        // movemask: pack one bit per lane into a u16
+/// movemask: pack one bit per lane of a 0x00/0xFF vector into a u16.
+#[inline(always)]
+unsafe fn movemask(matches: uint8x16_t) -> u16 {
+    unsafe {
         let masked = vandq_u8(
             matches,
             vld1q_u8([1u8, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128].as_ptr()),
@@ -31,3 +42,51 @@ pub unsafe fn bitmask_16b(low_tab: &[u8; 16], high_tab: &[u8; 16], bit_mask: u8,
         mask64 as u16
     }
 }
+
+pub unsafe fn bitmask_16b(low_tab: &[u8; 16], high_tab: &[u8; 16], bit_mask: u8, data: &[u8; 16]) -> u16 {
+    unsafe { movemask(matches_16b(low_tab, high_tab, bit_mask, data)) }
+}
+
+/// Index of the first matching lane of `data`.
+///
+/// Skips the movemask reduction: `vshrn` narrows the comparison vector to a
+/// 64-bit value with 4 bits per lane, and `trailing_zeros` (`rbit` + `clz`)
+/// finds the first one.
+#[inline(always)]
+pub unsafe fn first_match_16b(low_tab: &[u8; 16], high_tab: &[u8; 16], bit_mask: u8, data: &[u8; 16]) -> Option<u32> {
+    unsafe {
+        let matches = matches_16b(low_tab, high_tab, bit_mask, data);
+        let nibbles = vshrn_n_u16(vreinterpretq_u16_u8(matches), 4);
+        let bits = vget_lane_u64(vreinterpret_u64_u8(nibbles), 0);
+        (bits != 0).then(|| bits.trailing_zeros() / 4)
+    }
+}
+
+/// Truffle: bit `hi & 7` of `mask_lo[lo]` (bytes below 0x80) or
+/// `mask_hi[lo]` (bytes from 0x80) marks membership of `hi << 4 | lo`.
+#[inline(always)]
+pub unsafe fn truffle_16b(mask_lo: &[u8; 16], mask_hi: &[u8; 16], data: &[u8; 16]) -> u16 {
+    unsafe {
+        let input = vld1q_u8(data.as_ptr());
+        let lo = vandq_u8(input, vmovq_n_u8(0x0f));
+        let hi = vshrq_n_u8(input, 4);
+
+        let rows_lo = vqtbl1q_u8(vld1q_u8(mask_lo.as_ptr()), lo);
+        let rows_hi = vqtbl1q_u8(vld1q_u8(mask_hi.as_ptr()), lo);
+        let rows = vbslq_u8(vcgeq_u8(input, vmovq_n_u8(0x80)), rows_hi, rows_lo);
+
+        let bits_tab = vld1q_u8([1u8, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128].as_ptr());
+        let bits = vqtbl1q_u8(bits_tab, hi);
+
+        movemask(vtstq_u8(rows, bits))
+    }
+}
+
+/// Lanes equal to `a` or `b` (vermicelli), for sets of one or two bytes.
+#[inline(always)]
+pub unsafe fn cmpeq_16b(a: u8, b: u8, data: &[u8; 16]) -> u16 {
+    unsafe {
+        let input = vld1q_u8(data.as_ptr());
+        movemask(vorrq_u8(vceqq_u8(input, vmovq_n_u8(a)), vceqq_u8(input, vmovq_n_u8(b))))
+    }
+}
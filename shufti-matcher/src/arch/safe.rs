@@ -0,0 +1,52 @@
+//! Safe scalar kernels for the `no-unsafe` feature.
+
+use crate::arch::Backend;
+
+pub const BACKEND: Backend = Backend::Scalar;
+
+#[inline(always)]
+pub fn bitmask_16b(low_tab: &[u8; 16], high_tab: &[u8; 16], bit_mask: u8, data: &[u8; 16]) -> u16 {
+    let mut mask = 0u16;
+    for (i, &b) in data.iter().enumerate() {
+        let lo = (b & 0x0f) as usize;
+        let hi = (b >> 4) as usize;
+        if (low_tab[lo] & high_tab[hi] & bit_mask) != 0 {
+            mask |= 1 << i;
+        }
+    }
+    mask
+}
+
+#[inline(always)]
+pub fn first_match_16b(
+    low_tab: &[u8; 16],
+    high_tab: &[u8; 16],
+    bit_mask: u8,
+    data: &[u8; 16],
+) -> Option<u32> {
+    let mask = bitmask_16b(low_tab, high_tab, bit_mask, data);
+    (mask != 0).then(|| mask.trailing_zeros())
+}
+
+#[inline(always)]
+pub fn truffle_16b(mask_lo: &[u8; 16], mask_hi: &[u8; 16], data: &[u8; 16]) -> u16 {
+    let mut mask = 0u16;
+    for (i, &b) in data.iter().enumerate() {
+        let rows = if b & 0x80 == 0 { mask_lo } else { mask_hi };
+        if rows[(b & 0x0f) as usize] & (1 << ((b >> 4) & 7)) != 0 {
+            mask |= 1 << i;
+        }
+    }
+    mask
+}
+
+#[inline(always)]
+pub fn cmpeq_16b(a: u8, b: u8, data: &[u8; 16]) -> u16 {
+    let mut mask = 0u16;
+    for (i, &x) in data.iter().enumerate() {
+        if x == a || x == b {
+            mask |= 1 << i;
+        }
+    }
+    mask
+}
@@ -25,4 +25,4 @@
 //     let nonzero = _mm256_cmpgt_epi8(masked, _mm256_setzero_si256());
 
 //     _mm256_movemask_epi8(nonzero) as u32
-// }
\ No newline at end of file
+// }
@@ -1,3 +1,7 @@
+use crate::arch::Backend;
+
+pub const BACKEND: Backend = Backend::Scalar;
+
 #[inline(always)]
 pub unsafe fn bitmask_16b(
     low_tab: &[u8; 16],
@@ -15,3 +19,26 @@ pub unsafe fn bitmask_16b(
     }
     mask
 }
+
+#[inline(always)]
+pub unsafe fn truffle_16b(mask_lo: &[u8; 16], mask_hi: &[u8; 16], data: &[u8; 16]) -> u16 {
+    let mut mask = 0u16;
+    for (i, &b) in data.iter().enumerate() {
+        let rows = if b & 0x80 == 0 { mask_lo } else { mask_hi };
+        if rows[(b & 0x0f) as usize] & (1 << ((b >> 4) & 7)) != 0 {
+            mask |= 1 << i;
+        }
+    }
+    mask
+}
+
+#[inline(always)]
+pub unsafe fn cmpeq_16b(a: u8, b: u8, data: &[u8; 16]) -> u16 {
+    let mut mask = 0u16;
+    for (i, &x) in data.iter().enumerate() {
+        if x == a || x == b {
+            mask |= 1 << i;
+        }
+    }
+    mask
+}
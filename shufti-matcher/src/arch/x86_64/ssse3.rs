@@ -1,3 +1,7 @@
+use crate::arch::Backend;
+
+pub const BACKEND: Backend = Backend::Ssse3;
+
 #[inline(always)]
 pub unsafe fn bitmask_16b(
     low_tab: &[u8; 16],
@@ -30,3 +34,44 @@ pub unsafe fn bitmask_16b(
         _mm_movemask_epi8(nonzero) as u16
     }
 }
+
+/// Truffle: bit `hi & 7` of `mask_lo[lo]` (bytes below 0x80) or
+/// `mask_hi[lo]` (bytes from 0x80) marks membership of `hi << 4 | lo`.
+#[inline(always)]
+pub unsafe fn truffle_16b(mask_lo: &[u8; 16], mask_hi: &[u8; 16], data: &[u8; 16]) -> u16 {
+    use core::arch::x86_64::*;
+
+    unsafe {
+        let lo_tab = _mm_loadu_si128(mask_lo.as_ptr() as *const __m128i);
+        let hi_tab = _mm_loadu_si128(mask_hi.as_ptr() as *const __m128i);
+        let input = _mm_loadu_si128(data.as_ptr() as *const __m128i);
+
+        // pshufb zeroes lanes whose index has bit 7 set, so each lookup only
+        // answers for its half of the byte range.
+        let rows_lo = _mm_shuffle_epi8(lo_tab, input);
+        let rows_hi = _mm_shuffle_epi8(hi_tab, _mm_xor_si128(input, _mm_set1_epi8(0x80_u8 as i8)));
+        let rows = _mm_or_si128(rows_lo, rows_hi);
+
+        let bits_tab = _mm_setr_epi8(1, 2, 4, 8, 16, 32, 64, -128, 1, 2, 4, 8, 16, 32, 64, -128);
+        let hi = _mm_and_si128(_mm_srli_epi16(input, 4), _mm_set1_epi8(0x07));
+        let bits = _mm_shuffle_epi8(bits_tab, hi);
+
+        let hit = _mm_and_si128(rows, bits);
+        let nonzero = _mm_andnot_si128(_mm_cmpeq_epi8(hit, _mm_setzero_si128()), _mm_set1_epi8(-1));
+
+        _mm_movemask_epi8(nonzero) as u16
+    }
+}
+
+/// Lanes equal to `a` or `b` (vermicelli), for sets of one or two bytes.
+#[inline(always)]
+pub unsafe fn cmpeq_16b(a: u8, b: u8, data: &[u8; 16]) -> u16 {
+    use core::arch::x86_64::*;
+
+    unsafe {
+        let input = _mm_loadu_si128(data.as_ptr() as *const __m128i);
+        let eq_a = _mm_cmpeq_epi8(input, _mm_set1_epi8(a as i8));
+        let eq_b = _mm_cmpeq_epi8(input, _mm_set1_epi8(b as i8));
+        _mm_movemask_epi8(_mm_or_si128(eq_a, eq_b)) as u16
+    }
+}
@@ -0,0 +1,158 @@
+//! Match streams over [`futures_io::AsyncBufRead`] (feature `futures`).
+//!
+//! Works with any executor (smol, async-std, tokio through its compat layer).
+//! Each buffer the reader hands out is searched in place and consumed up to
+//! and including the match, so nothing is copied.
+
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::io;
+
+use futures_core::Stream;
+use futures_io::AsyncBufRead;
+
+use crate::ShuftiMatch;
+
+/// Offsets of the bytes of `M`'s set in an async reader, counted from the
+/// first byte read.
+#[derive(Debug)]
+pub struct AsyncMatches<R, M: ?Sized> {
+    reader: R,
+    /// Offset of the first unconsumed byte.
+    offset: usize,
+    _matcher: PhantomData<fn() -> M>,
+}
+
+impl<R: AsyncBufRead + Unpin, M: ShuftiMatch + ?Sized> AsyncMatches<R, M> {
+    /// Search the bytes of `reader`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            offset: 0,
+            _matcher: PhantomData,
+        }
+    }
+
+    /// Offset of the next match, or `None` at end of input.
+    pub async fn next_match(&mut self) -> io::Result<Option<usize>> {
+        core::future::poll_fn(|cx| self.poll_next_match(cx)).await
+    }
+
+    /// Poll for the offset of the next match.
+    pub fn poll_next_match(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<usize>>> {
+        loop {
+            let buf = match Pin::new(&mut self.reader).poll_fill_buf(cx) {
+                Poll::Ready(Ok(buf)) => buf,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            if buf.is_empty() {
+                return Poll::Ready(Ok(None));
+            }
+            let (used, found) = match M::find_first(buf) {
+                Some(pos) => (pos + 1, Some(self.offset + pos)),
+                None => (buf.len(), None),
+            };
+            Pin::new(&mut self.reader).consume(used);
+            self.offset += used;
+            if found.is_some() {
+                return Poll::Ready(Ok(found));
+            }
+        }
+    }
+
+    /// The underlying reader, positioned just after the last match returned.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, M: ShuftiMatch + ?Sized> Stream for AsyncMatches<R, M> {
+    type Item = io::Result<usize>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_match(cx).map(Result::transpose)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::WsMatcher;
+    use core::task::Waker;
+    use futures_io::AsyncRead;
+
+    /// Hands out at most `piece` bytes per fill and is pending every other poll.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        piece: usize,
+        ready: bool,
+    }
+
+    impl AsyncRead for Trickle<'_> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            _: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            unreachable!("only the buffered interface is used")
+        }
+    }
+
+    impl AsyncBufRead for Trickle<'_> {
+        fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+            let this = self.get_mut();
+            this.ready = !this.ready;
+            if !this.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Poll::Ready(Ok(&this.data[..this.data.len().min(this.piece)]))
+        }
+
+        fn consume(self: Pin<&mut Self>, amt: usize) {
+            let this = self.get_mut();
+            this.data = &this.data[amt..];
+        }
+    }
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = core::pin::pin!(fut);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+                return out;
+            }
+        }
+    }
+
+    #[test]
+    fn test_next_match_across_buffers() {
+        let data = b"a\tb\r..............\n..\t";
+        let reader = Trickle {
+            data,
+            piece: 5,
+            ready: false,
+        };
+        let mut matches = AsyncMatches::<_, WsMatcher>::new(reader);
+        let got = block_on(async {
+            let mut got = Vec::new();
+            while let Some(pos) = matches.next_match().await.unwrap() {
+                got.push(pos);
+            }
+            got
+        });
+        assert_eq!(got, [1, 3, 18, 21]);
+    }
+
+    #[test]
+    fn test_stream() {
+        let mut matches = AsyncMatches::<_, WsMatcher>::new(&b"x\ny\n"[..]);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut next = || Pin::new(&mut matches).poll_next(&mut cx);
+        assert!(matches!(next(), Poll::Ready(Some(Ok(1)))));
+        assert!(matches!(next(), Poll::Ready(Some(Ok(3)))));
+        assert!(matches!(next(), Poll::Ready(None)));
+    }
+}
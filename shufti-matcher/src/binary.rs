@@ -0,0 +1,71 @@
+//! Binary-content detection.
+
+use crate::ShuftiMatch;
+use crate::search::{eq_mask, lane_mask, load_chunk};
+
+/// Control bytes that essentially never appear in text: everything below
+/// 0x20 except `\t`, `\n`, `\x0b`, `\x0c`, `\r` and ESC (used by ANSI colors),
+/// plus DEL.
+#[derive(crate::ShuftiMatcher)]
+#[shufti(
+    set = "\x00\x01\x02\x03\x04\x05\x06\x07\x08\x0e\x0f\x10\x11\x12\x13\x14\x15\x16\x17\x18\x19\x1a\x1c\x1d\x1e\x1f\x7f"
+)]
+struct BinaryControl;
+
+/// More than one control byte in this many marks the input as binary.
+const CONTROL_RATIO: usize = 10;
+
+/// Guess whether `prefix` (typically the first few KiB of a file) is binary.
+///
+/// Like grep and ripgrep, any NUL byte means binary. Otherwise the input is
+/// binary when more than 1 in 10 bytes is a non-text control byte.
+pub fn looks_binary(prefix: &[u8]) -> bool {
+    let mut controls = 0usize;
+    let mut offset = 0;
+    while offset < prefix.len() {
+        let (chunk, len) = load_chunk(prefix, offset);
+        let valid = lane_mask(len);
+        if eq_mask(&chunk, 0) & valid != 0 {
+            return true;
+        }
+        controls += (BinaryControl::match_16b(&chunk).bits() & valid).count_ones() as usize;
+        offset += 16;
+    }
+    controls * CONTROL_RATIO > prefix.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text() {
+        assert!(!looks_binary(b""));
+        assert!(!looks_binary(b"fn main() {\n\tprintln!(\"hi\");\r\n}\n"));
+        assert!(!looks_binary("\x1b[31mred\x1b[0m ünïcödé".as_bytes()));
+    }
+
+    #[test]
+    fn test_nul_is_binary() {
+        let mut hay = vec![b'a'; 40];
+        hay[37] = 0;
+        assert!(looks_binary(&hay));
+    }
+
+    #[test]
+    fn test_control_ratio() {
+        let mut hay = vec![b'a'; 100];
+        hay[..10].fill(0x01);
+        assert!(!looks_binary(&hay));
+        hay[10] = 0x7f;
+        assert!(looks_binary(&hay));
+    }
+
+    #[test]
+    fn test_control_set_is_exact() {
+        for b in 0..=255u8 {
+            let text = b >= 0x20 && b != 0x7f || b"\t\n\x0b\x0c\r\x1b".contains(&b);
+            assert_eq!(BinaryControl::bucket_of(b) == 0, text, "byte 0x{b:02x}");
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Word-level helpers for the packed bitmaps produced by
+//! [`ShuftiMatch::bitmap`](crate::ShuftiMatch::bitmap).
+//!
+//! Bit `i % 64` of word `i / 64` stands for byte `i` of the input. These are the
+//! usual stage-2 building blocks for combining several matchers' bitmaps
+//! (simdjson-style quote tracking, region masking, position decoding).
+
+/// Prefix-XOR of `x`: bit *i* of the result is the XOR of bits `0..=i`.
+///
+/// Uses carry-less multiplication by all-ones when `pclmulqdq` is available.
+#[inline(always)]
+pub fn prefix_xor(x: u64) -> u64 {
+    cfg_if::cfg_if! {
+        if #[cfg(all(target_arch = "x86_64", target_feature = "pclmulqdq", not(feature = "no-unsafe")))] {
+            use core::arch::x86_64::*;
+            // SAFETY: pclmulqdq/sse2 are enabled for this target.
+            unsafe {
+                let v = _mm_clmulepi64_si128(_mm_set_epi64x(0, x as i64), _mm_set1_epi8(-1), 0);
+                _mm_cvtsi128_si64(v) as u64
+            }
+        } else {
+            prefix_xor_portable(x)
+        }
+    }
+}
+
+#[inline(always)]
+#[allow(dead_code)]
+fn prefix_xor_portable(mut x: u64) -> u64 {
+    x ^= x << 1;
+    x ^= x << 2;
+    x ^= x << 4;
+    x ^= x << 8;
+    x ^= x << 16;
+    x ^= x << 32;
+    x
+}
+
+/// Replace `out` with the "inside quotes" mask of a quote bitmap: bits from
+/// each opening quote up to (not including) its closing quote, carried across
+/// words. An unclosed quote extends to the end of the bitmap.
+pub fn inside_quotes(quotes: &[u64], out: &mut Vec<u64>) {
+    out.clear();
+    let mut carry = 0u64; // all-ones while a quote is open
+    for &q in quotes {
+        let inside = prefix_xor(q) ^ carry;
+        carry = ((inside as i64) >> 63) as u64;
+        out.push(inside);
+    }
+}
+
+/// Shift the whole bitmap towards higher byte positions by `n` bits, carrying
+/// bits across word boundaries. Bits shifted past the last word are dropped.
+/// Useful for "byte after a match" tests.
+pub fn shift_up(words: &mut [u64], n: usize) {
+    let (skip, n) = (n / 64, (n % 64) as u32);
+    if skip >= words.len() {
+        words.fill(0);
+        return;
+    }
+    if skip > 0 {
+        words.copy_within(..words.len() - skip, skip);
+        words[..skip].fill(0);
+    }
+    if n == 0 {
+        return;
+    }
+    let mut carry = 0u64;
+    for w in words[skip..].iter_mut() {
+        let next = *w >> (64 - n);
+        *w = (*w << n) | carry;
+        carry = next;
+    }
+}
+
+/// Clear every bit of `words` that is set in `mask` (`words &= !mask`).
+/// Words past the end of `mask` are left untouched.
+pub fn and_not(words: &mut [u64], mask: &[u64]) {
+    for (w, &m) in words.iter_mut().zip(mask) {
+        *w &= !m;
+    }
+}
+
+/// Iterate the positions of the set bits of `words`, in increasing order.
+pub fn iter_ones(words: &[u64]) -> Ones<'_> {
+    Ones {
+        words,
+        index: 0,
+        current: words.first().copied().unwrap_or(0),
+    }
+}
+
+/// Iterator returned by [`iter_ones`].
+#[derive(Debug, Clone)]
+pub struct Ones<'a> {
+    words: &'a [u64],
+    index: usize,
+    current: u64,
+}
+
+impl Iterator for Ones<'_> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        while self.current == 0 {
+            self.index += 1;
+            self.current = *self.words.get(self.index)?;
+        }
+        let bit = self.current.trailing_zeros() as usize;
+        self.current &= self.current - 1;
+        Some(self.index * 64 + bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_xor() {
+        for x in [0u64, 1, 0b1001, 1 << 63, 0xdead_beef_cafe_babe, u64::MAX] {
+            assert_eq!(prefix_xor(x), prefix_xor_portable(x));
+        }
+        assert_eq!(prefix_xor(0b1001), 0b0111);
+    }
+
+    #[test]
+    fn test_inside_quotes_carries() {
+        // Quotes at bytes 2, 5 and 70 (the last one never closes).
+        let quotes = [1 << 2 | 1 << 5, 1 << 6];
+        let mut out = Vec::new();
+        inside_quotes(&quotes, &mut out);
+        assert_eq!(out, [0b11100, !0u64 << 6]);
+
+        // Quote opened in word 0 closes in word 1.
+        inside_quotes(&[1 << 60, 1 << 3], &mut out);
+        assert_eq!(out, [!0u64 << 60, 0b111]);
+    }
+
+    #[test]
+    fn test_shift_up_and_not() {
+        let mut words = [1 << 63 | 1, 1 << 63];
+        shift_up(&mut words, 1);
+        assert_eq!(words, [0b10, 1]);
+        and_not(&mut words, &[0b10]);
+        assert_eq!(words, [0, 1]);
+
+        let mut words = [1 << 63 | 1, 0, 0];
+        shift_up(&mut words, 65);
+        assert_eq!(words, [0, 0b10, 1]);
+        shift_up(&mut words, 64 * 3);
+        assert_eq!(words, [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_iter_ones() {
+        let words = [1 | 1 << 63, 0, 1 << 5];
+        assert_eq!(iter_ones(&words).collect::<Vec<_>>(), [0, 63, 133]);
+        assert_eq!(iter_ones(&[]).next(), None);
+    }
+}
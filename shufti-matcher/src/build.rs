@@ -0,0 +1,303 @@
+//! Runtime table construction, on top of the `shufti-core` builders the
+//! derive macro also uses.
+
+use std::fmt;
+
+use shufti_core::build_shufti_buckets;
+
+use crate::{ShuftiTable, WideShuftiTable};
+
+/// Why [`ShuftiTable::try_new`] could not build a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// The needle set is empty.
+    Empty,
+    /// The needle byte appears more than once.
+    Duplicate(u8),
+    /// The set cannot be packed into 8 buckets; a
+    /// [`Finder`](crate::Finder) handles it with the 16-bucket kernel.
+    TooManyBuckets,
+    /// An explicitly assigned bucket also accepts this byte, which was not
+    /// assigned to any bucket (see [`TableBuilder`]).
+    Unassigned(u8),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Empty => f.write_str("shufti set must have >=1 bytes"),
+            BuildError::Duplicate(b) => write!(f, "shufti set contains duplicate byte 0x{b:02x}"),
+            BuildError::TooManyBuckets => f.write_str("shufti set does not fit in 8 buckets"),
+            BuildError::Unassigned(b) => {
+                write!(f, "shufti buckets also accept unassigned byte 0x{b:02x}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Builds a [`ShuftiTable`] from buckets assigned by the caller, bypassing
+/// the automatic bucket assignment.
+///
+/// A bucket accepts every byte whose low nibble and high nibble both occur
+/// among its bytes, so a bucket should be a "rectangle" of nibbles, e.g.
+/// `0`–`9` (high nibble 3, low nibbles 0–9). [`build`](Self::build) fails
+/// when a bucket accepts a byte that was not assigned to any bucket.
+///
+/// ```rust,ignore
+/// let table = TableBuilder::new()
+///     .bucket_range(b'0'..=b'9')
+///     .bucket(b"\t\n")
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TableBuilder {
+    buckets: Vec<Vec<u8>>,
+}
+
+impl TableBuilder {
+    /// Create a builder with no buckets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the next bucket, holding `bytes`.
+    pub fn bucket(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buckets.push(bytes.to_vec());
+        self
+    }
+
+    /// Add the next bucket, holding every byte in `range`.
+    pub fn bucket_range(&mut self, range: core::ops::RangeInclusive<u8>) -> &mut Self {
+        self.buckets.push(range.collect());
+        self
+    }
+
+    /// Build the table. Fails when no bucket holds a byte, when there are
+    /// more than 8 buckets, or when a bucket accepts an unassigned byte.
+    pub fn build(&self) -> Result<ShuftiTable, BuildError> {
+        if self.buckets.len() > 8 {
+            return Err(BuildError::TooManyBuckets);
+        }
+        let mut table = ShuftiTable {
+            low_tab: [0; 16],
+            high_tab: [0; 16],
+            bit_mask: 0,
+        };
+        let mut assigned = [false; 256];
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            for &b in bucket {
+                table.low_tab[(b & 0x0f) as usize] |= 1 << i;
+                table.high_tab[(b >> 4) as usize] |= 1 << i;
+                table.bit_mask |= 1 << i;
+                assigned[b as usize] = true;
+            }
+        }
+        if table.bit_mask == 0 {
+            return Err(BuildError::Empty);
+        }
+        match (0..=255u8).find(|&b| table.bucket_of(b) != 0 && !assigned[b as usize]) {
+            Some(b) => Err(BuildError::Unassigned(b)),
+            None => Ok(table),
+        }
+    }
+}
+
+/// Bytes on which a table disagrees with its expected set, from
+/// [`ShuftiTable::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Bytes the table accepts but the set does not contain.
+    pub false_positives: Vec<u8>,
+    /// Bytes of the set the table rejects.
+    pub false_negatives: Vec<u8>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "shufti table disagrees with its set: false positives {:02x?}, false negatives {:02x?}",
+            self.false_positives, self.false_negatives
+        )
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// Compare `table` against `expected` on all 256 byte values.
+pub(crate) fn verify(table: &ShuftiTable, expected: &[u8]) -> Result<(), Mismatch> {
+    let mut in_set = [false; 256];
+    for &b in expected {
+        in_set[b as usize] = true;
+    }
+    let mut mismatch = Mismatch {
+        false_positives: Vec::new(),
+        false_negatives: Vec::new(),
+    };
+    for b in 0..=255u8 {
+        match (table.bucket_of(b) != 0, in_set[b as usize]) {
+            (true, false) => mismatch.false_positives.push(b),
+            (false, true) => mismatch.false_negatives.push(b),
+            _ => {}
+        }
+    }
+    if mismatch.false_positives.is_empty() && mismatch.false_negatives.is_empty() {
+        Ok(())
+    } else {
+        Err(mismatch)
+    }
+}
+
+/// [`build_table`] with the derive macro's checks, reported as errors.
+pub(crate) fn try_build_table(needles: &[u8]) -> Result<ShuftiTable, BuildError> {
+    let mut seen = [false; 256];
+    for &b in needles {
+        if std::mem::replace(&mut seen[b as usize], true) {
+            return Err(BuildError::Duplicate(b));
+        }
+    }
+    if needles.is_empty() {
+        return Err(BuildError::Empty);
+    }
+    build_table(needles).ok_or(BuildError::TooManyBuckets)
+}
+
+/// Build a table for `needles`, which must be unique.
+///
+/// Sets of up to 8 bytes get one bucket per byte; larger sets go through the
+/// bucket-sharing builder. Returns `None` when the set cannot be packed into
+/// 8 buckets.
+pub(crate) fn build_table(needles: &[u8]) -> Option<ShuftiTable> {
+    let (low_tab, high_tab, bit_mask) = shufti_core::build_table(needles)?;
+    Some(ShuftiTable {
+        low_tab,
+        high_tab,
+        bit_mask,
+    })
+}
+
+/// Build a 16-bucket table for `needles` (unique) with the bucket-sharing
+/// builder; `None` when empty or when the set needs more than 16 buckets
+/// (a [`TruffleTable`](crate::TruffleTable) represents any set).
+pub(crate) fn build_wide_table(needles: &[u8]) -> Option<WideShuftiTable> {
+    if needles.is_empty() {
+        return None;
+    }
+    let (low_tab, high_tab, mask) = build_shufti_buckets(needles, 16)?;
+    Some(WideShuftiTable::from_buckets(&low_tab, &high_tab, mask))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_exact(table: &WideShuftiTable, needles: &[u8]) {
+        for b in 0..=255u8 {
+            assert_eq!(
+                table.bucket_of(b) != 0,
+                needles.contains(&b),
+                "byte 0x{b:02x}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_try_build_table() {
+        let table = try_build_table(b"\t\r\n").unwrap();
+        assert_eq!(table.bit_mask, 0b111);
+        let table = try_build_table(b"0123456789abcdef").unwrap();
+        assert!(b"0123456789abcdef".iter().all(|&b| table.bucket_of(b) != 0));
+        assert_eq!(try_build_table(b"").unwrap_err(), BuildError::Empty);
+        assert_eq!(
+            try_build_table(b"abca").unwrap_err(),
+            BuildError::Duplicate(b'a')
+        );
+        assert_eq!(
+            try_build_table(b"\x00\x11\x22\x33\x44\x55\x66\x77\x88\x99").unwrap_err(),
+            BuildError::TooManyBuckets
+        );
+        assert_eq!(
+            BuildError::Duplicate(b'a').to_string(),
+            "shufti set contains duplicate byte 0x61"
+        );
+    }
+
+    #[test]
+    fn test_table_builder() {
+        // One bucket per nibble row: digits, lower and upper hex letters.
+        let table = TableBuilder::new()
+            .bucket_range(b'0'..=b'9')
+            .bucket_range(b'a'..=b'f')
+            .bucket_range(b'A'..=b'F')
+            .bucket(b"\t\n")
+            .build()
+            .unwrap();
+        assert_eq!(table.bit_mask, 0b1111);
+        assert_eq!(table.bucket_of(b'7'), 1);
+        assert_eq!(table.bucket_of(b'\n'), 1 << 3);
+        let mut hex = b"0123456789abcdefABCDEF\t\n".to_vec();
+        hex.sort_unstable();
+        assert_eq!(table.members(), hex);
+
+        // 'a' and 'r' in one bucket also accept 0x62 and 0x71.
+        assert_eq!(
+            TableBuilder::new().bucket(b"ar").build().unwrap_err(),
+            BuildError::Unassigned(0x62)
+        );
+        assert_eq!(
+            TableBuilder::new()
+                .bucket(b"ar")
+                .bucket(b"bq")
+                .build()
+                .map(|t| t.bit_mask),
+            Ok(0b11)
+        );
+        assert_eq!(
+            TableBuilder::new().bucket(b"").build().unwrap_err(),
+            BuildError::Empty
+        );
+        let mut nine = TableBuilder::new();
+        for b in 0..9 {
+            nine.bucket(&[b]);
+        }
+        assert_eq!(nine.build().unwrap_err(), BuildError::TooManyBuckets);
+    }
+
+    #[test]
+    fn test_verify() {
+        let table = build_table(b"abcdefghijklmnopqrstuvwxyz_").unwrap();
+        assert_eq!(verify(&table, b"_zyxwvutsrqponmlkjihgfedcba"), Ok(()));
+        // A bucket shared by 'a' (0x61) and 'r' (0x72) also accepts 0x62
+        // and 0x71.
+        let merged = ShuftiTable {
+            low_tab: core::array::from_fn(|i| u8::from(i == 1 || i == 2)),
+            high_tab: core::array::from_fn(|i| u8::from(i == 6 || i == 7)),
+            bit_mask: 1,
+        };
+        let err = verify(&merged, b"ar!").unwrap_err();
+        assert_eq!(err.false_positives, [0x62, 0x71]);
+        assert_eq!(err.false_negatives, [b'!']);
+        assert!(err.to_string().contains("[62, 71]"));
+    }
+
+    #[test]
+    fn test_wide_table_shares_buckets() {
+        let needles = b"\x00\x11\x22\x33\x44\x55\x66\x77\x88\x99";
+        assert!(build_table(needles).is_none());
+        let table = build_wide_table(needles).unwrap();
+        assert_eq!(table.buckets_hi.bit_mask, 0b11);
+        assert_exact(&table, needles);
+    }
+
+    #[test]
+    fn test_wide_table_bucket_limit() {
+        // Every byte except the diagonal: greedy sharing runs out of buckets.
+        let needles: Vec<u8> = (0..=255u8).filter(|b| b >> 4 != b & 0x0f).collect();
+        assert!(build_shufti_buckets(&needles, 16).is_none());
+        assert!(build_wide_table(&needles).is_none());
+        assert!(build_wide_table(&[]).is_none());
+    }
+}
@@ -0,0 +1,113 @@
+//! Matchers for sets of `char`s, including multi-byte UTF-8 characters.
+
+use crate::Finder;
+
+/// A runtime matcher for a set of `char`s.
+///
+/// The SIMD scan looks for the distinct first bytes of the members' UTF-8
+/// encodings; each candidate is then decoded and checked against the set, so
+/// `"—"` and `"…"` (which share their first byte) are told apart and a lone
+/// byte of a multi-byte member never matches.
+#[derive(Debug, Clone)]
+pub struct CharFinder {
+    leads: Finder,
+    /// Members, sorted and deduplicated.
+    chars: Vec<char>,
+}
+
+impl CharFinder {
+    /// Build a matcher for the characters of `set`. Duplicates are ignored.
+    pub fn new(set: &str) -> Self {
+        set.chars().collect()
+    }
+
+    /// Whether `c` is in the set.
+    pub fn contains(&self, c: char) -> bool {
+        self.chars.binary_search(&c).is_ok()
+    }
+
+    /// The first member of `text` with its byte index.
+    pub fn find(&self, text: &str) -> Option<(usize, char)> {
+        self.find_iter(text).next()
+    }
+
+    /// Iterate `(byte_index, char)` for every member of `text`.
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> CharMatches<'a> {
+        CharMatches {
+            finder: self,
+            text,
+            pos: 0,
+        }
+    }
+}
+
+impl FromIterator<char> for CharFinder {
+    fn from_iter<I: IntoIterator<Item = char>>(chars: I) -> Self {
+        let mut chars: Vec<char> = chars.into_iter().collect();
+        chars.sort_unstable();
+        chars.dedup();
+        let mut lead = [0u8; 4];
+        let leads = chars
+            .iter()
+            .map(|c| c.encode_utf8(&mut lead).as_bytes()[0])
+            .collect();
+        Self { leads, chars }
+    }
+}
+
+/// Iterator returned by [`CharFinder::find_iter`].
+#[derive(Debug, Clone)]
+pub struct CharMatches<'a> {
+    finder: &'a CharFinder,
+    text: &'a str,
+    /// Where the next candidate search starts; always a char boundary.
+    pos: usize,
+}
+
+impl Iterator for CharMatches<'_> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        loop {
+            let rest = self.text.get(self.pos..)?;
+            // Lead bytes are never UTF-8 continuation bytes, so every
+            // candidate starts a character.
+            let at = self.pos + self.finder.leads.find(rest)?;
+            let c = self.text[at..].chars().next()?;
+            self.pos = at + c.len_utf8();
+            if self.finder.contains(c) {
+                return Some((at, c));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multibyte_members() {
+        let quotes = CharFinder::new("…—«»");
+        let text = "a – b — «c» …";
+        assert_eq!(
+            quotes.find_iter(text).collect::<Vec<_>>(),
+            [(8, '—'), (12, '«'), (15, '»'), (18, '…')]
+        );
+        // '–' (en dash) shares its first two bytes with '—' but is not in
+        // the set.
+        assert_eq!(quotes.find("x–y"), None);
+        assert!(quotes.contains('»') && !quotes.contains('<'));
+    }
+
+    #[test]
+    fn test_mixed_ascii_and_unicode() {
+        let set: CharFinder = ['é', ',', 'é', '😀'].into_iter().collect();
+        assert_eq!(
+            set.find_iter("e,é😀x").collect::<Vec<_>>(),
+            [(1, ','), (2, 'é'), (4, '😀')]
+        );
+        assert_eq!(set.find(""), None);
+        assert_eq!(CharFinder::new("").find("abc"), None);
+    }
+}
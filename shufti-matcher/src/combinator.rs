@@ -0,0 +1,316 @@
+//! Type-level set algebra over existing matchers.
+//!
+//! [`Or`], [`And`] and [`Not`] combine their operands'
+//! [`BITMAP`](ShuftiMatch::BITMAP)s and rebuild every other constant from the
+//! result in `const fn`s, so a composed matcher costs the same per chunk as a
+//! derived one and picks its [`Algorithm`](crate::Algorithm) by the same
+//! rules:
+//!
+//! ```rust,ignore
+//! type Separator = Or<Whitespace, Punctuation>;
+//! type Visible = Not<Or<Whitespace, Controls>>;
+//! assert_eq!(Separator::find_first(b"a,b"), Some(1));
+//! ```
+
+use core::marker::PhantomData;
+
+use crate::{ShuftiMatch, ShuftiTable, TruffleTable, WideShuftiTable};
+
+/// Bytes in `A` or `B`.
+pub struct Or<A, B>(PhantomData<fn() -> (A, B)>);
+
+/// Bytes in both `A` and `B`.
+pub struct And<A, B>(PhantomData<fn() -> (A, B)>);
+
+/// Bytes not in `A`.
+pub struct Not<A>(PhantomData<fn() -> A>);
+
+/// Constructors and the usual marker impls, without the `A: Trait` bounds a
+/// derive would add.
+macro_rules! marker_impls {
+    ($name:ident<$($param:ident),+>) => {
+        impl<$($param),+> $name<$($param),+> {
+            /// The (zero-sized) matcher value, e.g. to box it as a
+            /// [`DynShuftiMatcher`](crate::DynShuftiMatcher).
+            pub const fn new() -> Self {
+                $name(PhantomData)
+            }
+        }
+
+        impl<$($param),+> Default for $name<$($param),+> {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl<$($param),+> Clone for $name<$($param),+> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+
+        impl<$($param),+> Copy for $name<$($param),+> {}
+
+        impl<$($param),+> core::fmt::Debug for $name<$($param),+> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.write_str(stringify!($name))
+            }
+        }
+    };
+}
+
+marker_impls!(Or<A, B>);
+marker_impls!(And<A, B>);
+marker_impls!(Not<A>);
+
+impl<A: ShuftiMatch, B: ShuftiMatch> Or<A, B> {
+    const PLAN: Plan = Plan::new(&bitmap_op(A::BITMAP, B::BITMAP, Op::Or));
+}
+
+impl<A: ShuftiMatch, B: ShuftiMatch> And<A, B> {
+    const PLAN: Plan = Plan::new(&bitmap_op(A::BITMAP, B::BITMAP, Op::And));
+}
+
+impl<A: ShuftiMatch> Not<A> {
+    const PLAN: Plan = Plan::new(&bitmap_op(A::BITMAP, [0; 4], Op::Not));
+}
+
+/// The `ShuftiMatch` impl of a combinator, read off its `PLAN`.
+macro_rules! plan_impl {
+    ($name:ident<$($param:ident),+>) => {
+        impl<$($param: ShuftiMatch),+> ShuftiMatch for $name<$($param),+> {
+            const SET: &'static str = Self::PLAN.set.as_str();
+            const SET_BYTES: &'static [u8] = Self::PLAN.members();
+            const NEEDLE_COUNT: usize = Self::PLAN.needle_count;
+            const BUCKET_OF: [u8; 256] = Self::PLAN.bucket_of;
+            const BITMAP: [u64; 4] = Self::PLAN.bitmap;
+            const CMPEQ: Option<[u8; 2]> = Self::PLAN.cmpeq;
+            const WIDE: Option<WideShuftiTable> = Self::PLAN.wide;
+            const TRUFFLE: Option<TruffleTable> = Self::PLAN.truffle;
+
+            fn table() -> ShuftiTable {
+                Self::PLAN.table
+            }
+        }
+    };
+}
+
+plan_impl!(Or<A, B>);
+plan_impl!(And<A, B>);
+plan_impl!(Not<A>);
+
+enum Op {
+    Or,
+    And,
+    Not,
+}
+
+/// `a | b`, `a & b` or `!a`, word by word (closures are not `const`).
+const fn bitmap_op(a: [u64; 4], b: [u64; 4], op: Op) -> [u64; 4] {
+    let mut out = [0u64; 4];
+    let mut i = 0;
+    while i < 4 {
+        out[i] = match op {
+            Op::Or => a[i] | b[i],
+            Op::And => a[i] & b[i],
+            Op::Not => !a[i],
+        };
+        i += 1;
+    }
+    out
+}
+
+/// Every `ShuftiMatch` constant of a combined set, as the derive would emit
+/// them for the same bytes.
+struct Plan {
+    bitmap: [u64; 4],
+    /// The first `needle_count` entries are the members, in increasing order.
+    members: [u8; 256],
+    set: Spelling,
+    needle_count: usize,
+    bucket_of: [u8; 256],
+    table: ShuftiTable,
+    cmpeq: Option<[u8; 2]>,
+    wide: Option<WideShuftiTable>,
+    truffle: Option<TruffleTable>,
+}
+
+impl Plan {
+    const fn new(bitmap: &[u64; 4]) -> Self {
+        let mut members = [0u8; 256];
+        let mut needle_count = 0;
+        let mut b = 0;
+        while b < 256 {
+            if bitmap[b >> 6] & (1 << (b & 63)) != 0 {
+                members[needle_count] = b as u8;
+                needle_count += 1;
+            }
+            b += 1;
+        }
+
+        let cmpeq = match needle_count {
+            1 => Some([members[0], members[0]]),
+            2 => Some([members[0], members[1]]),
+            _ => None,
+        };
+        let empty = ShuftiTable {
+            low_tab: [0; 16],
+            high_tab: [0; 16],
+            bit_mask: 0,
+        };
+        let (table, wide, truffle) = match shufti_core::build_table_from_bitmap(bitmap) {
+            Some((low_tab, high_tab, bit_mask)) => (
+                ShuftiTable {
+                    low_tab,
+                    high_tab,
+                    bit_mask,
+                },
+                None,
+                None,
+            ),
+            None if needle_count == 0 => (empty, None, None),
+            None => match shufti_core::build_buckets_from_bitmap(bitmap, 16) {
+                Some((low_tab, high_tab, bit_mask)) => (
+                    empty,
+                    Some(WideShuftiTable::from_buckets(&low_tab, &high_tab, bit_mask)),
+                    None,
+                ),
+                None => (empty, None, Some(TruffleTable::from_bitmap(bitmap))),
+            },
+        };
+        let mut bucket_of = table.bucket_map();
+        if wide.is_some() || truffle.is_some() {
+            // No 8-bucket table: every member is in "bucket" 1.
+            let mut b = 0;
+            while b < 256 {
+                bucket_of[b] = (bitmap[b >> 6] >> (b & 63)) as u8 & 1;
+                b += 1;
+            }
+        }
+
+        Plan {
+            bitmap: *bitmap,
+            members,
+            set: Spelling::new(&members, needle_count),
+            needle_count,
+            bucket_of,
+            table,
+            cmpeq,
+            wide,
+            truffle,
+        }
+    }
+
+    const fn members(&'static self) -> &'static [u8] {
+        self.members.split_at(self.needle_count).0
+    }
+}
+
+/// The members as a `str`, in increasing order; a byte `>= 0x80` is spelled
+/// as the Latin-1 `char` with the same code point.
+struct Spelling {
+    utf8: [u8; 384],
+    len: usize,
+}
+
+impl Spelling {
+    const fn new(members: &[u8; 256], count: usize) -> Self {
+        let mut utf8 = [0u8; 384];
+        let mut len = 0;
+        let mut i = 0;
+        while i < count {
+            let b = members[i];
+            if b < 0x80 {
+                utf8[len] = b;
+                len += 1;
+            } else {
+                utf8[len] = 0xc0 | b >> 6;
+                utf8[len + 1] = 0x80 | (b & 0x3f);
+                len += 2;
+            }
+            i += 1;
+        }
+        Spelling { utf8, len }
+    }
+
+    const fn as_str(&'static self) -> &'static str {
+        match core::str::from_utf8(self.utf8.split_at(self.len).0) {
+            Ok(s) => s,
+            Err(_) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::WsMatcher;
+    use crate::{Algorithm, DynShuftiMatcher, ShuftiMatcher};
+
+    #[derive(ShuftiMatcher)]
+    #[shufti(set = "0123456789")]
+    struct Digits;
+
+    #[derive(ShuftiMatcher)]
+    #[shufti(set = "\t0")]
+    struct TabZero;
+
+    #[derive(ShuftiMatcher)]
+    #[shufti(set = "\x00\x11\x22\x33\x44")]
+    struct SpreadLo;
+
+    #[derive(ShuftiMatcher)]
+    #[shufti(set = "\x55\x66\x77é")]
+    struct SpreadHi;
+
+    #[test]
+    fn test_or_and_not() {
+        type Sep = Or<WsMatcher, Digits>;
+        assert_eq!(Sep::SET, "\t\n\r0123456789");
+        assert_eq!(Sep::NEEDLE_COUNT, 13);
+        assert_eq!(Sep::ALGORITHM, Algorithm::Shufti);
+        assert_eq!(Sep::find_iter(b"a1\tb\n").collect::<Vec<_>>(), [1, 2, 4]);
+        assert_eq!(Sep::table().verify(Sep::SET.as_bytes()), Ok(()));
+
+        type Both = And<Sep, TabZero>;
+        assert_eq!((Both::SET, Both::ALGORITHM), ("\t0", Algorithm::CmpEq));
+        assert_eq!(Both::find_iter(b"x0\t1").collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(And::<WsMatcher, Digits>::find_first(b"1\t"), None);
+        assert_eq!(And::<WsMatcher, Digits>::NEEDLE_COUNT, 0);
+
+        type Visible = Not<WsMatcher>;
+        assert_eq!(Visible::NEEDLE_COUNT, 253);
+        assert!(Visible::SET.ends_with("þÿ"));
+        assert_eq!(Visible::find_first(b"\t\r\n\xff"), Some(3));
+        assert_eq!(Not::<Visible>::BITMAP, WsMatcher::BITMAP);
+        for b in 0..=255u8 {
+            assert_eq!(Visible::is_member(b), !WsMatcher::is_member(b));
+            assert_eq!(Visible::bucket_of(b) != 0, Visible::is_member(b));
+        }
+    }
+
+    #[test]
+    fn test_union_beyond_8_buckets() {
+        // 'é' is 0xc3 0xa9, spelled back as the Latin-1 "©Ã".
+        type Spread = Or<SpreadLo, SpreadHi>;
+        assert_eq!(Spread::ALGORITHM, Algorithm::WideShufti);
+        assert_eq!(Spread::SET, "\x00\x11\x22\x33\x44\x55\x66\x77©Ã");
+        let hay: Vec<u8> = (0..=255u8).collect();
+        assert_eq!(
+            Spread::find_iter(&hay).collect::<Vec<_>>(),
+            [0, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0xa9, 0xc3]
+        );
+        assert_eq!(Spread::table().bit_mask, 0);
+        assert_eq!(
+            Spread::SET_BYTES,
+            b"\x00\x11\x22\x33\x44\x55\x66\x77\xa9\xc3"
+        );
+    }
+
+    #[test]
+    fn test_combinators_as_values() {
+        let boxed: Box<dyn DynShuftiMatcher> = Box::new(Not::<Digits>::new());
+        assert_eq!(boxed.dyn_find_first(b"12a"), Some(2));
+        assert_eq!(format!("{:?}", Or::<Digits, TabZero>::default()), "Or");
+    }
+}
@@ -0,0 +1,62 @@
+//! Deadline- and budget-limited bulk scans.
+
+use std::time::Instant;
+
+use crate::search::chunk_mask;
+
+/// Chunks scanned between two deadline checks (16 KiB of input).
+pub(crate) const CHECK_EVERY: usize = 1024;
+
+/// Returned when a scan hit its deadline before reaching the end of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedOut {
+    /// Offset to pass back as `start` to continue the scan. Everything before
+    /// it has been scanned.
+    pub resume_at: usize,
+}
+
+/// Returned when a scan gave up after its byte limit without finding a match
+/// and without reaching the end of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitReached {
+    /// Offset to continue from; everything before it has been scanned.
+    pub resume_at: usize,
+}
+
+#[cold]
+#[inline(never)]
+fn timed_out(resume_at: usize) -> TimedOut {
+    TimedOut { resume_at }
+}
+
+/// Run `visit` over the chunk masks of `haystack[start..]`, checking
+/// `deadline` every [`CHECK_EVERY`] chunks. `visit` returns `true` to stop.
+#[inline(always)]
+pub(crate) fn scan_until<M, F>(
+    haystack: &[u8],
+    start: usize,
+    deadline: Instant,
+    mask_16b: M,
+    mut visit: F,
+) -> Result<(), TimedOut>
+where
+    M: Fn(&[u8; 16]) -> u16,
+    F: FnMut(usize, u16) -> bool,
+{
+    let mut offset = start;
+    let mut budget = CHECK_EVERY;
+    while offset < haystack.len() {
+        if budget == 0 {
+            if Instant::now() >= deadline {
+                return Err(timed_out(offset));
+            }
+            budget = CHECK_EVERY;
+        }
+        budget -= 1;
+        if visit(offset, chunk_mask(haystack, offset, &mask_16b)) {
+            return Ok(());
+        }
+        offset += 16;
+    }
+    Ok(())
+}
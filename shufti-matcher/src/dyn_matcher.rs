@@ -0,0 +1,135 @@
+//! Object-safe view of [`ShuftiMatch`] for heterogeneous matchers.
+
+use crate::{Mask16, ShuftiMatch, ShuftiTable, raw_16b, search};
+
+/// Object-safe companion of [`ShuftiMatch`], implemented for every
+/// matcher, so differently-typed matchers can live behind
+/// `Box<dyn DynShuftiMatcher>` (e.g. handed back by plugins).
+///
+/// Haystacks are plain `&[u8]` since generic methods cannot be called
+/// through `dyn`, and every method carries a `dyn_` prefix so a glob import
+/// of both traits leaves `M::find_first(..)` unambiguous.
+pub trait DynShuftiMatcher {
+    /// [`ShuftiMatch::SET`].
+    fn dyn_set(&self) -> &'static str;
+
+    /// [`ShuftiMatch::NEEDLE_COUNT`].
+    fn dyn_needle_count(&self) -> usize;
+
+    /// [`ShuftiMatch::table`].
+    fn dyn_table(&self) -> ShuftiTable;
+
+    /// Whether `b` belongs to the set.
+    fn dyn_is_member(&self, b: u8) -> bool;
+
+    /// [`ShuftiMatch::match_16b`].
+    fn dyn_match_16b(&self, chunk: &[u8; 16]) -> Mask16;
+
+    /// [`ShuftiMatch::find_first`].
+    fn dyn_find_first(&self, haystack: &[u8]) -> Option<usize>;
+
+    /// Position of the last byte of `haystack` in the set.
+    fn dyn_find_last(&self, haystack: &[u8]) -> Option<usize>;
+
+    /// [`ShuftiMatch::count`].
+    fn dyn_count(&self, haystack: &[u8]) -> usize;
+
+    /// [`ShuftiMatch::positions_into`].
+    fn dyn_positions_into(&self, haystack: &[u8], out: &mut Vec<usize>);
+
+    /// [`ShuftiMatch::find_iter`], boxed.
+    fn dyn_find_iter<'a>(
+        &self,
+        haystack: &'a [u8],
+    ) -> Box<dyn DoubleEndedIterator<Item = usize> + 'a>;
+}
+
+impl<M: ShuftiMatch + 'static> DynShuftiMatcher for M {
+    fn dyn_set(&self) -> &'static str {
+        M::SET
+    }
+
+    fn dyn_needle_count(&self) -> usize {
+        M::NEEDLE_COUNT
+    }
+
+    fn dyn_table(&self) -> ShuftiTable {
+        M::table()
+    }
+
+    fn dyn_is_member(&self, b: u8) -> bool {
+        M::bucket_of(b) != 0
+    }
+
+    fn dyn_match_16b(&self, chunk: &[u8; 16]) -> Mask16 {
+        M::match_16b(chunk)
+    }
+
+    fn dyn_find_first(&self, haystack: &[u8]) -> Option<usize> {
+        M::find_first(haystack)
+    }
+
+    fn dyn_find_last(&self, haystack: &[u8]) -> Option<usize> {
+        search::rfind(haystack, raw_16b::<M>)
+    }
+
+    fn dyn_count(&self, haystack: &[u8]) -> usize {
+        M::count(haystack)
+    }
+
+    fn dyn_positions_into(&self, haystack: &[u8], out: &mut Vec<usize>) {
+        M::positions_into(haystack, out)
+    }
+
+    fn dyn_find_iter<'a>(
+        &self,
+        haystack: &'a [u8],
+    ) -> Box<dyn DoubleEndedIterator<Item = usize> + 'a> {
+        Box::new(M::find_iter(haystack))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{WsMatcher, fast_table};
+
+    struct Comma;
+    impl ShuftiMatch for Comma {
+        const SET: &'static str = ",";
+        const NEEDLE_COUNT: usize = 1;
+        const BUCKET_OF: [u8; 256] = fast_table(b",").bucket_map();
+        fn table() -> ShuftiTable {
+            fast_table(b",")
+        }
+    }
+
+    #[test]
+    fn test_heterogeneous_matchers() {
+        let matchers: Vec<Box<dyn DynShuftiMatcher>> = vec![Box::new(WsMatcher), Box::new(Comma)];
+        let hay = b"a,b\tc,d\n";
+        let found: Vec<_> = matchers.iter().map(|m| m.dyn_find_first(hay)).collect();
+        assert_eq!(found, [Some(3), Some(1)]);
+
+        let comma = &matchers[1];
+        assert_eq!((comma.dyn_set(), comma.dyn_needle_count()), (",", 1));
+        assert!(comma.dyn_is_member(b',') && !comma.dyn_is_member(b'\t'));
+        assert_eq!(comma.dyn_find_iter(hay).rev().collect::<Vec<_>>(), [5, 1]);
+        assert_eq!(
+            (comma.dyn_count(hay), comma.dyn_find_last(hay)),
+            (2, Some(5))
+        );
+        let mut out = Vec::new();
+        matchers[0].dyn_positions_into(hay, &mut out);
+        assert_eq!(out, [3, 7]);
+        assert_eq!(
+            matchers[0].dyn_match_16b(b"\t...............").bits() & 1,
+            1
+        );
+        assert_eq!(
+            matchers[0].dyn_table().bit_mask,
+            WsMatcher::table().bit_mask
+        );
+        assert_eq!(comma.dyn_find_first(b""), None);
+    }
+}
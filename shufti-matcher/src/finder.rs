@@ -0,0 +1,771 @@
+//! Runtime-built matchers for sets that are only known at runtime.
+
+use core::ops::{BitAnd, BitOr, Deref, Not};
+use std::sync::OnceLock;
+
+use crate::{Algorithm, Haystack, ShuftiTable, TruffleTable, WideShuftiTable};
+
+/// Which kernel a [`Finder`] runs.
+#[derive(Debug, Clone)]
+enum Kernel {
+    /// Sets of one or two bytes, with their table for [`Finder::table`].
+    CmpEq([u8; 2], ShuftiTable),
+    Shufti(ShuftiTable),
+    Wide(WideShuftiTable),
+    /// Sets that need more than 16 buckets.
+    Truffle(TruffleTable),
+    /// The empty set, which no table can represent.
+    Empty,
+}
+
+/// Bind `$mask` to the finder's chunk kernel and evaluate `$body` with it.
+macro_rules! with_kernel {
+    ($finder:expr, |$mask:ident| $body:expr) => {{
+        let finder: &Finder = $finder;
+        match &finder.kernel {
+            &Kernel::CmpEq([a, b], _) => {
+                let $mask = |c: &[u8; 16]| kernel!(crate::arch::cmpeq_16b(a, b, c));
+                $body
+            }
+            Kernel::Shufti(t) => {
+                let $mask = |c: &[u8; 16]| kernel!(t.bitmask_16b(c));
+                $body
+            }
+            Kernel::Wide(t) => {
+                let $mask = |c: &[u8; 16]| kernel!(t.bitmask_16b(c));
+                $body
+            }
+            Kernel::Truffle(t) => {
+                let $mask = |c: &[u8; 16]| kernel!(t.bitmask_16b(c));
+                $body
+            }
+            Kernel::Empty => {
+                let $mask = |_: &[u8; 16]| 0u16;
+                $body
+            }
+        }
+    }};
+}
+
+/// A byte-set matcher built at runtime.
+///
+/// The kernel follows [`select_algorithm`](shufti_core::select_algorithm):
+/// sets of one or two bytes use `cmpeq`, sets that fit in 8 shufti buckets
+/// use the SIMD kernel, sets that fit in 16 use the [`WideShuftiTable`]
+/// kernel, and every other non-empty set uses the [`TruffleTable`] kernel.
+#[derive(Debug, Clone)]
+pub struct Finder {
+    set: [u64; 4],
+    kernel: Kernel,
+}
+
+impl Finder {
+    /// Build a finder matching any byte of `set`. Duplicates are ignored.
+    pub fn new(set: &[u8]) -> Self {
+        set.iter().copied().collect()
+    }
+
+    /// The algorithm chosen for the set; `None` for the empty set.
+    pub fn algorithm(&self) -> Option<Algorithm> {
+        match self.kernel {
+            Kernel::CmpEq(..) => Some(Algorithm::CmpEq),
+            Kernel::Shufti(_) => Some(Algorithm::Shufti),
+            Kernel::Wide(_) => Some(Algorithm::WideShufti),
+            Kernel::Truffle(_) => Some(Algorithm::Truffle),
+            Kernel::Empty => None,
+        }
+    }
+
+    /// The 8-bucket shufti table for the set, if it fits one.
+    pub fn table(&self) -> Option<&ShuftiTable> {
+        match &self.kernel {
+            Kernel::CmpEq(_, t) | Kernel::Shufti(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// The 16-bucket table in use, if the set needed more than 8 buckets.
+    pub fn wide_table(&self) -> Option<&WideShuftiTable> {
+        match &self.kernel {
+            Kernel::Wide(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// The truffle table in use, if the set needed more than 16 buckets.
+    pub fn truffle_table(&self) -> Option<&TruffleTable> {
+        match &self.kernel {
+            Kernel::Truffle(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// Rust source for a unit struct named `type_name` implementing
+    /// [`ShuftiMatch`](crate::ShuftiMatch) with this finder's table, so a set
+    /// prototyped at runtime can be frozen into generated code.
+    ///
+    /// Returns `None` when the set needs more than 8 buckets. Sets with
+    /// non-ASCII bytes also get
+    /// [`SET_BYTES`](crate::ShuftiMatch::SET_BYTES), since `SET` spells
+    /// those bytes as Latin-1 `char`s.
+    pub fn to_rust_source(&self, type_name: &str) -> Option<String> {
+        let table = self.table()?;
+        let members: Vec<u8> = (0..=255u8).filter(|&b| self.is_member(b)).collect();
+        let set: String = members.iter().map(|&b| char::from(b)).collect();
+        let set_bytes = if members.is_ascii() {
+            String::new()
+        } else {
+            format!(
+                "\n    const SET_BYTES: &'static [u8] = b\"{}\";",
+                members.escape_ascii()
+            )
+        };
+        let table = table.to_const_expr();
+        Some(format!(
+            "pub struct {type_name};
+
+impl ::shufti_matcher::ShuftiMatch for {type_name} {{
+    const SET: &'static str = {set:?};{set_bytes}
+    const NEEDLE_COUNT: usize = {count};
+    const BUCKET_OF: [u8; 256] = {table}.bucket_map();
+
+    #[inline(always)]
+    fn table() -> ::shufti_matcher::ShuftiTable {{
+        {table}
+    }}
+}}
+",
+            count = members.len(),
+        ))
+    }
+
+    /// Whether `b` belongs to the set.
+    #[inline(always)]
+    pub fn is_member(&self, b: u8) -> bool {
+        self.set[(b >> 6) as usize] & (1 << (b & 63)) != 0
+    }
+
+    /// Search `haystack` for the first byte that belongs to the set.
+    pub fn find<H: Haystack + ?Sized>(&self, haystack: &H) -> Option<usize> {
+        let haystack = haystack.as_bytes();
+        with_kernel!(self, |mask| crate::search::find_from(haystack, 0, mask))
+    }
+
+    /// Iterate the positions of every byte of `haystack` in the set.
+    pub fn find_iter<'a, H: Haystack + ?Sized>(&'a self, haystack: &'a H) -> FinderMatches<'a> {
+        FinderMatches::new(self, haystack.as_bytes(), 0)
+    }
+
+    /// Number of bytes of `haystack` that belong to the set.
+    pub fn count<H: Haystack + ?Sized>(&self, haystack: &H) -> usize {
+        let haystack = haystack.as_bytes();
+        with_kernel!(self, |mask| crate::search::count(haystack, mask))
+    }
+
+    /// Search `haystack` for the last byte that belongs to the set.
+    pub fn rfind<H: Haystack + ?Sized>(&self, haystack: &H) -> Option<usize> {
+        let haystack = haystack.as_bytes();
+        with_kernel!(self, |mask| crate::search::rfind(haystack, mask))
+    }
+
+    /// A finder for every byte *not* in the set, e.g. to find the first
+    /// byte a validator rejects. Same as `!self`; the complement gets its own
+    /// kernel, truffle when it does not fit shufti's buckets.
+    pub fn negated(&self) -> Finder {
+        !self
+    }
+
+    /// Mask of the (possibly short) chunk of `haystack` at `offset`.
+    #[inline(always)]
+    fn chunk_mask(&self, haystack: &[u8], offset: usize) -> u16 {
+        with_kernel!(self, |mask| crate::search::chunk_mask(
+            haystack, offset, &mask
+        ))
+    }
+
+    fn from_set(set: [u64; 4]) -> Self {
+        let needles: Vec<u8> = (0..=255u8)
+            .filter(|&b| set[(b >> 6) as usize] & (1 << (b & 63)) != 0)
+            .collect();
+        let kernel = if needles.is_empty() {
+            Kernel::Empty
+        } else {
+            match shufti_core::select_algorithm(&needles, 16) {
+                Algorithm::CmpEq => {
+                    let pair = [needles[0], needles[needles.len() - 1]];
+                    Kernel::CmpEq(pair, crate::build::build_table(&needles).unwrap())
+                }
+                Algorithm::Shufti => Kernel::Shufti(crate::build::build_table(&needles).unwrap()),
+                Algorithm::WideShufti => {
+                    Kernel::Wide(crate::build::build_wide_table(&needles).unwrap())
+                }
+                _ => Kernel::Truffle(TruffleTable::new(&needles)),
+            }
+        };
+        Finder { set, kernel }
+    }
+}
+
+/// Iterator over the matches of a [`Finder`], returned by
+/// [`Finder::find_iter`] and [`StreamFinder::feed`].
+#[derive(Debug, Clone)]
+pub struct FinderMatches<'a> {
+    finder: &'a Finder,
+    haystack: &'a [u8],
+    /// Added to every reported position.
+    base: usize,
+    /// Start of the chunk after the current one.
+    next: usize,
+    hits: u16,
+}
+
+impl<'a> FinderMatches<'a> {
+    fn new(finder: &'a Finder, haystack: &'a [u8], base: usize) -> Self {
+        Self {
+            finder,
+            haystack,
+            base,
+            next: 0,
+            hits: 0,
+        }
+    }
+}
+
+impl Iterator for FinderMatches<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.hits == 0 {
+            if self.next >= self.haystack.len() {
+                return None;
+            }
+            self.hits = self.finder.chunk_mask(self.haystack, self.next);
+            self.next += 16;
+        }
+        let lane = self.hits.trailing_zeros() as usize;
+        self.hits &= self.hits - 1;
+        Some(self.base + self.next - 16 + lane)
+    }
+}
+
+/// Searches a stream delivered in arbitrary-sized pieces (e.g. socket
+/// reads), reporting match positions relative to the start of the stream
+/// without concatenating the pieces.
+#[derive(Debug, Clone)]
+pub struct StreamFinder {
+    finder: Finder,
+    /// Bytes fed so far.
+    offset: usize,
+}
+
+impl StreamFinder {
+    /// Start a stream searched with `finder`.
+    pub fn new(finder: Finder) -> Self {
+        Self { finder, offset: 0 }
+    }
+
+    /// Search the next piece of the stream, yielding the absolute stream
+    /// position of every match in it.
+    pub fn feed<'a>(&'a mut self, piece: &'a [u8]) -> FinderMatches<'a> {
+        let base = self.offset;
+        self.offset += piece.len();
+        FinderMatches::new(&self.finder, piece, base)
+    }
+
+    /// Number of bytes fed so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// End the stream, returning its total length.
+    pub fn finish(self) -> usize {
+        self.offset
+    }
+}
+
+/// A [`Finder`] for a fixed set, built on first use, for crates that cannot
+/// use the derive macro:
+///
+/// ```rust,ignore
+/// static WS: LazyMatcher = LazyMatcher::new(b"\t\r\n");
+///
+/// assert_eq!(WS.find(b"hello\nworld"), Some(5));
+/// ```
+///
+/// Dereferences to the [`Finder`], so sets that fit 8 buckets run the same
+/// [`ShuftiTable`] kernel as a derived matcher.
+#[derive(Debug)]
+pub struct LazyMatcher {
+    set: &'static [u8],
+    finder: OnceLock<Finder>,
+}
+
+impl LazyMatcher {
+    /// Declare a matcher for `set`; nothing is built until first use.
+    pub const fn new(set: &'static [u8]) -> Self {
+        Self {
+            set,
+            finder: OnceLock::new(),
+        }
+    }
+
+    /// The set this matcher was declared with.
+    pub const fn set(&self) -> &'static [u8] {
+        self.set
+    }
+
+    /// The finder, building it on the first call.
+    pub fn finder(&self) -> &Finder {
+        self.finder.get_or_init(|| Finder::new(self.set))
+    }
+}
+
+impl Deref for LazyMatcher {
+    type Target = Finder;
+
+    fn deref(&self) -> &Finder {
+        self.finder()
+    }
+}
+
+/// Earliest match of any of `finders` in `haystack`, as `(finder_index,
+/// position)`. When several finders accept the byte at that position, the
+/// lowest index wins.
+///
+/// The haystack is scanned once. When the finders' shufti buckets fit side by
+/// side in one 8-bucket table, the bucket bits of the matched byte name the
+/// finder; otherwise the union of the sets is scanned and the matched byte is
+/// classified against each finder's set.
+pub fn find_first_of_any(finders: &[&Finder], haystack: &[u8]) -> Option<(usize, usize)> {
+    if let Some((table, buckets)) = pack_buckets(finders) {
+        let pos =
+            crate::search::find_from(haystack, 0, |c: &[u8; 16]| kernel!(table.bitmask_16b(c)))?;
+        let b = haystack[pos];
+        let bits = table.low_tab[(b & 0x0f) as usize] & table.high_tab[(b >> 4) as usize];
+        let idx = buckets.iter().position(|&owned| owned & bits != 0)?;
+        return Some((idx, pos));
+    }
+    let mut union = [0u64; 4];
+    for f in finders {
+        for (u, w) in union.iter_mut().zip(f.set) {
+            *u |= w;
+        }
+    }
+    let pos = Finder::from_set(union).find(haystack)?;
+    let idx = finders.iter().position(|f| f.is_member(haystack[pos]))?;
+    Some((idx, pos))
+}
+
+/// One table holding the buckets of every finder's 8-bucket table in
+/// distinct bits, and the bits each finder owns. `None` when a finder has no
+/// such table or they need more than 8 buckets together.
+fn pack_buckets(finders: &[&Finder]) -> Option<(ShuftiTable, Vec<u8>)> {
+    let mut packed = ShuftiTable {
+        low_tab: [0; 16],
+        high_tab: [0; 16],
+        bit_mask: 0,
+    };
+    let mut owned = Vec::with_capacity(finders.len());
+    let mut next = 0;
+    for finder in finders {
+        let table = match &finder.kernel {
+            Kernel::CmpEq(_, t) | Kernel::Shufti(t) => t,
+            Kernel::Empty => {
+                owned.push(0);
+                continue;
+            }
+            _ => return None,
+        };
+        let mut bits = 0u8;
+        for bucket in (0..8).filter(|&i| table.bit_mask & (1 << i) != 0) {
+            if next == 8 {
+                return None;
+            }
+            let to = 1u8 << next;
+            next += 1;
+            for nibble in 0..16 {
+                if table.low_tab[nibble] & (1 << bucket) != 0 {
+                    packed.low_tab[nibble] |= to;
+                }
+                if table.high_tab[nibble] & (1 << bucket) != 0 {
+                    packed.high_tab[nibble] |= to;
+                }
+            }
+            bits |= to;
+        }
+        packed.bit_mask |= bits;
+        owned.push(bits);
+    }
+    Some((packed, owned))
+}
+
+/// Position of the first byte of `haystack` that is in `set`, like
+/// `memchr` for an arbitrary byte set. The table is built on every call;
+/// keep a [`Finder`] to search repeatedly.
+pub fn find_any_of(set: &[u8], haystack: &[u8]) -> Option<usize> {
+    Finder::new(set).find(haystack)
+}
+
+/// Positions of every byte of `haystack` that is in `set`, in order.
+pub fn find_all_of(set: &[u8], haystack: &[u8]) -> Vec<usize> {
+    let mut out = Vec::new();
+    with_kernel!(&Finder::new(set), |mask| {
+        crate::search::positions_into(haystack, &mut out, mask)
+    });
+    out
+}
+
+/// Number of bytes of `haystack` that are in `set`.
+pub fn count_of(set: &[u8], haystack: &[u8]) -> usize {
+    with_kernel!(&Finder::new(set), |mask| crate::search::count(
+        haystack, mask
+    ))
+}
+
+impl BitOr for &Finder {
+    type Output = Finder;
+
+    /// Matches bytes in either set.
+    fn bitor(self, rhs: &Finder) -> Finder {
+        Finder::from_set(core::array::from_fn(|i| self.set[i] | rhs.set[i]))
+    }
+}
+
+impl BitAnd for &Finder {
+    type Output = Finder;
+
+    /// Matches bytes in both sets.
+    fn bitand(self, rhs: &Finder) -> Finder {
+        Finder::from_set(core::array::from_fn(|i| self.set[i] & rhs.set[i]))
+    }
+}
+
+impl Not for &Finder {
+    type Output = Finder;
+
+    /// Matches every byte outside the set.
+    fn not(self) -> Finder {
+        Finder::from_set(self.set.map(|w| !w))
+    }
+}
+
+impl BitOr for Finder {
+    type Output = Finder;
+
+    fn bitor(self, rhs: Finder) -> Finder {
+        &self | &rhs
+    }
+}
+
+impl BitAnd for Finder {
+    type Output = Finder;
+
+    fn bitand(self, rhs: Finder) -> Finder {
+        &self & &rhs
+    }
+}
+
+impl Not for Finder {
+    type Output = Finder;
+
+    fn not(self) -> Finder {
+        !&self
+    }
+}
+
+/// Accumulates the bytes of a [`Finder`] set.
+#[derive(Debug, Clone, Default)]
+pub struct FinderBuilder {
+    set: [u64; 4],
+}
+
+impl FinderBuilder {
+    /// Create a builder for the empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `b` to the set.
+    pub fn byte(&mut self, b: u8) -> &mut Self {
+        self.set[(b >> 6) as usize] |= 1 << (b & 63);
+        self
+    }
+
+    /// Add every byte of `bytes` to the set.
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.extend(bytes.iter().copied());
+        self
+    }
+
+    /// Add every byte in `range` to the set.
+    pub fn range(&mut self, range: core::ops::RangeInclusive<u8>) -> &mut Self {
+        self.extend(range);
+        self
+    }
+
+    /// Build the finder, choosing the shufti kernel when the set fits.
+    pub fn build(&self) -> Finder {
+        Finder::from_set(self.set)
+    }
+}
+
+impl Extend<u8> for FinderBuilder {
+    fn extend<I: IntoIterator<Item = u8>>(&mut self, iter: I) {
+        for b in iter {
+            self.byte(b);
+        }
+    }
+}
+
+impl FromIterator<u8> for FinderBuilder {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Self {
+        let mut builder = FinderBuilder::new();
+        builder.extend(iter);
+        builder
+    }
+}
+
+impl FromIterator<u8> for Finder {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Self {
+        iter.into_iter().collect::<FinderBuilder>().build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_range() {
+        let finder: Finder = (b'a'..=b'z').collect();
+        assert!(finder.table().is_some());
+        assert_eq!(finder.find(b"0123456789ABCDEFGHq"), Some(18));
+        assert_eq!(finder.find(b"0123456789"), None);
+    }
+
+    #[test]
+    fn test_to_rust_source() {
+        let source = Finder::new(b"\n\t").to_rust_source("Ws").unwrap();
+        let table = Finder::new(b"\t\n").table().unwrap().to_const_expr();
+        let expected = format!(
+            "pub struct Ws;
+
+impl ::shufti_matcher::ShuftiMatch for Ws {{
+    const SET: &'static str = \"\\t\\n\";
+    const NEEDLE_COUNT: usize = 2;
+    const BUCKET_OF: [u8; 256] = {table}.bucket_map();
+
+    #[inline(always)]
+    fn table() -> ::shufti_matcher::ShuftiTable {{
+        {table}
+    }}
+}}
+"
+        );
+        assert_eq!(source, expected);
+
+        let binary = Finder::new(b"\xff\x00").to_rust_source("X").unwrap();
+        assert!(binary.contains("const SET: &'static str = \"\\0ÿ\";\n"));
+        assert!(binary.contains("const SET_BYTES: &'static [u8] = b\"\\x00\\xff\";\n"));
+        assert!(Finder::new(b"").to_rust_source("X").is_none());
+    }
+
+    #[test]
+    fn test_extend_builder() {
+        let mut builder: FinderBuilder = b"\t\n".iter().copied().collect();
+        builder.extend([b' ', b'\n']);
+        builder.byte(b'\r').range(b'0'..=b'2');
+        let finder = builder.build();
+        assert_eq!(finder.find(b"abc2"), Some(3));
+        assert_eq!(finder.find(b"a3c2"), Some(3));
+        assert_eq!(finder.find(b"abc\r"), Some(3));
+        assert_eq!(finder.find(b"abc def"), Some(3));
+        assert!(!finder.is_member(b'a'));
+    }
+
+    #[test]
+    fn test_ten_bucket_set_uses_wide_table() {
+        // Ten bytes with pairwise distinct nibbles need ten buckets.
+        let finder = Finder::new(b"\x00\x11\x22\x33\x44\x55\x66\x77\x88\x99");
+        assert!(finder.table().is_none());
+        let wide = finder.wide_table().unwrap();
+        assert_eq!(wide.bucket_of(0x99), 1 << 9);
+        assert_eq!(wide.bucket_of(0x98), 0);
+        assert_eq!(finder.find(b"abc\x99"), Some(3));
+        assert_eq!(finder.find(b"zzzzzzzzzzzzzzzzz\x88"), Some(17));
+        assert_eq!(finder.find(b"abc"), None);
+    }
+
+    #[test]
+    fn test_every_set_is_vectorized() {
+        let spread: Vec<u8> = (0..=255u8).filter(|b| b >> 4 != b & 0x0f).collect();
+        let finder = Finder::new(&spread);
+        assert!(finder.truffle_table().is_some());
+        for &b in &spread {
+            assert_eq!(finder.find(&[0, 0, b]), Some(2), "byte 0x{b:02x}");
+        }
+
+        let empty = Finder::new(b"");
+        assert!(empty.table().is_none() && empty.wide_table().is_none());
+        assert!(empty.truffle_table().is_none());
+        assert_eq!(empty.find(b"abc"), None);
+    }
+
+    #[test]
+    fn test_set_operators() {
+        let ws = Finder::new(b" \t");
+        let punct = Finder::new(b",;");
+        let stop = &ws | &punct;
+        assert_eq!(stop.find(b"abc;d e"), Some(3));
+        assert!(stop.table().is_some());
+
+        let both = stop & Finder::new(b";x");
+        assert_eq!(both.find(b", ;"), Some(2));
+
+        let not_ws = !ws;
+        assert_eq!(not_ws.find(b" \t \tx"), Some(4));
+        assert_eq!(not_ws.find(b"\t \t"), None);
+    }
+
+    #[test]
+    fn test_negated() {
+        let allowed: Finder = (b'a'..=b'z').chain(b'0'..=b'9').collect();
+        let invalid = allowed.negated();
+        assert_eq!(invalid.find(b"user_42"), Some(4));
+        assert_eq!(invalid.find(b"user42"), None);
+        assert_eq!(invalid.negated().find(b"__x"), Some(2));
+        assert_eq!(Finder::new(b"").negated().count(b"\0\xff"), 2);
+        let all: Finder = (0..=255u8).collect();
+        assert_eq!(all.negated().algorithm(), None);
+    }
+
+    #[test]
+    fn test_find_first_of_any() {
+        let nl = Finder::new(b"\n");
+        let semi = Finder::new(b";");
+        let both = Finder::new(b";\n");
+        assert_eq!(find_first_of_any(&[&nl, &semi], b"abc;d\n"), Some((1, 3)));
+        assert_eq!(find_first_of_any(&[&nl, &semi], b"abcd\n;"), Some((0, 4)));
+        assert_eq!(find_first_of_any(&[&semi, &both], b"x;"), Some((0, 1)));
+        assert_eq!(find_first_of_any(&[&nl, &semi], b"abcd"), None);
+        assert_eq!(find_first_of_any(&[], b"abcd"), None);
+        assert_eq!(
+            find_first_of_any(&[&Finder::new(b""), &nl], b"a\n"),
+            Some((1, 1))
+        );
+
+        let (_, buckets) = pack_buckets(&[&nl, &semi, &both]).unwrap();
+        assert!(buckets.iter().all(|&b| b != 0));
+        let union = buckets.iter().fold(0u8, |acc, b| acc | b);
+        let total: u32 = buckets.iter().map(|b| b.count_ones()).sum();
+        assert_eq!(union.count_ones(), total);
+    }
+
+    #[test]
+    fn test_find_first_of_any_wide_union() {
+        let a = Finder::new(b"\x00\x11\x22\x33\x44\x55\x66\x77\x88");
+        let b = Finder::new(b"\x99\xaa\xbb\xcc\xdd\xee\xff\x0f\xf0");
+        assert!((&a | &b).wide_table().is_some());
+        assert!(pack_buckets(&[&a, &b]).is_none());
+        assert_eq!(find_first_of_any(&[&a, &b], b"ab\xeec\x22"), Some((1, 2)));
+        assert_eq!(find_first_of_any(&[&a, &b], b"ab\x22c\xee"), Some((0, 2)));
+    }
+
+    #[test]
+    fn test_stream_finder() {
+        let stream = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let expected = find_all_of(b"\r\n", stream);
+        for piece_len in [1, 3, 7, 16, 17, 64] {
+            let mut finder = StreamFinder::new(Finder::new(b"\r\n"));
+            let mut found = Vec::new();
+            for piece in stream.chunks(piece_len) {
+                found.extend(finder.feed(piece));
+            }
+            assert_eq!(found, expected, "pieces of {piece_len}");
+            assert_eq!(finder.offset(), stream.len());
+            assert_eq!(finder.finish(), stream.len());
+        }
+        let mut empty = StreamFinder::new(Finder::new(b""));
+        assert_eq!(empty.feed(b"abc").next(), None);
+        assert_eq!(empty.feed(b"").next(), None);
+        assert_eq!(empty.finish(), 3);
+    }
+
+    #[test]
+    fn test_free_functions() {
+        let hay = b"key=value; other=thing";
+        assert_eq!(find_any_of(b";=", hay), Some(3));
+        assert_eq!(find_all_of(b";=", hay), [3, 9, 16]);
+        assert_eq!(count_of(b";=", hay), 3);
+        assert_eq!(find_any_of(b"", hay), None);
+        assert_eq!(count_of(b"", hay), 0);
+        // A set that needs the wide kernel.
+        let wide = b"\x00\x11\x22\x33\x44\x55\x66\x77\x88\x99";
+        assert_eq!(find_all_of(wide, b"zz\x99zz\x00"), [2, 5]);
+    }
+
+    #[test]
+    fn test_find_iter_count_rfind() {
+        let hay = "a,b;c,d e,x;g,h i,j;k,l m,n;o,";
+        for finder in [
+            Finder::new(b",;"),
+            Finder::new(b",;\x00\x11\x22\x33\x44\x55\x66\x77\x88\x99"),
+        ] {
+            let expected: Vec<usize> = hay.match_indices([',', ';']).map(|(i, _)| i).collect();
+            assert_eq!(finder.find_iter(hay).collect::<Vec<_>>(), expected);
+            assert_eq!(finder.count(hay), expected.len());
+            assert_eq!(finder.rfind(hay), expected.last().copied());
+            assert_eq!(finder.rfind("abc"), None);
+        }
+        let empty = Finder::new(b"");
+        assert_eq!(empty.find_iter("a,b").next(), None);
+        assert_eq!((empty.count("a,b"), empty.rfind("a,b")), (0, None));
+    }
+
+    #[test]
+    fn test_lazy_matcher() {
+        static WS: LazyMatcher = LazyMatcher::new(b"\t\r\n");
+        static SPREAD: LazyMatcher = LazyMatcher::new(b"\x00\x11\x22\x33\x44\x55\x66\x77\x88");
+        assert_eq!(WS.set(), b"\t\r\n");
+        assert_eq!(WS.find(b"hello\nworld"), Some(5));
+        assert_eq!(WS.count(b"a\tb\r\n"), 3);
+        assert!(WS.table().is_some());
+        assert!(core::ptr::eq(WS.finder(), &*WS));
+        assert!(SPREAD.wide_table().is_some());
+        assert_eq!(SPREAD.rfind(b"\x00ab\x88c"), Some(3));
+    }
+
+    #[test]
+    fn test_algorithm_selection() {
+        let cases: [(&[u8], Algorithm); 4] = [
+            (b"\n", Algorithm::CmpEq),
+            (b"\r\n", Algorithm::CmpEq),
+            (b"\t\r\n ", Algorithm::Shufti),
+            (
+                b"\x00\x11\x22\x33\x44\x55\x66\x77\x88",
+                Algorithm::WideShufti,
+            ),
+        ];
+        for (set, algorithm) in cases {
+            let finder = Finder::new(set);
+            assert_eq!(finder.algorithm(), Some(algorithm), "{set:?}");
+            let hay = [[b'X'; 17].as_slice(), set].concat();
+            assert_eq!(
+                finder.find_iter(&hay).collect::<Vec<_>>(),
+                (17..hay.len()).collect::<Vec<_>>()
+            );
+        }
+        let pair = Finder::new(b"\r\n");
+        assert!(pair.table().is_some());
+        assert!(pair.to_rust_source("CrLf").is_some());
+        let spread: Vec<u8> = (0..=255u8).filter(|b| b >> 4 != b & 0x0f).collect();
+        assert_eq!(Finder::new(&spread).algorithm(), Some(Algorithm::Truffle));
+        assert_eq!(Finder::new(b"").algorithm(), None);
+    }
+
+    #[test]
+    fn test_empty_set() {
+        let finder: Finder = core::iter::empty().collect();
+        assert_eq!(finder.find(b"abc"), None);
+    }
+}
@@ -0,0 +1,133 @@
+//! Inputs accepted by the search APIs.
+
+use std::borrow::Cow;
+use std::io::IoSlice;
+
+/// A contiguous byte buffer that can be searched.
+///
+/// Implemented for byte slices and arrays, `str`, `Vec<u8>`, `String`,
+/// `Box<[u8]>`, `Cow<[u8]>`, [`IoSlice`], references to any of these, and
+/// (feature `bytes`) `Bytes` / `BytesMut`, so
+/// every search entry point takes `&impl Haystack` instead of needing a
+/// per-type twin.
+pub trait Haystack {
+    /// The bytes to search.
+    fn as_bytes(&self) -> &[u8];
+}
+
+impl Haystack for [u8] {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<const N: usize> Haystack for [u8; N] {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Haystack for str {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        str::as_bytes(self)
+    }
+}
+
+impl Haystack for Vec<u8> {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Haystack for String {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        str::as_bytes(self)
+    }
+}
+
+impl Haystack for Box<[u8]> {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Haystack for Cow<'_, [u8]> {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Haystack for IoSlice<'_> {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<T: Haystack + ?Sized> Haystack for &T {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        T::as_bytes(self)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Haystack for bytes::Bytes {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Haystack for bytes::BytesMut {
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ShuftiMatch;
+    use crate::tests::WsMatcher;
+
+    #[test]
+    fn test_haystack_types() {
+        let text = "key\tvalue";
+        assert_eq!(WsMatcher::find_first(text), Some(3));
+        assert_eq!(WsMatcher::find_first(&text.to_string()), Some(3));
+        assert_eq!(WsMatcher::find_first(b"key\tvalue"), Some(3));
+        assert_eq!(WsMatcher::find_first(&b"key\tvalue".to_vec()), Some(3));
+        assert_eq!(WsMatcher::find_first(&b"a\n"[..]), Some(1));
+        let boxed: Box<[u8]> = b"ab\r".to_vec().into_boxed_slice();
+        assert_eq!(WsMatcher::find_first(&boxed), Some(2));
+        assert_eq!(
+            WsMatcher::find_first(&std::borrow::Cow::Borrowed(&b"\n"[..])),
+            Some(0)
+        );
+        assert_eq!(
+            WsMatcher::find_first(&std::io::IoSlice::new(b"x\t")),
+            Some(1)
+        );
+        assert_eq!(WsMatcher::find_first(&&b"\n"[..]), Some(0));
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_bytes() {
+        let bytes = bytes::Bytes::from_static(b"abc\n");
+        assert_eq!(WsMatcher::find_first(&bytes), Some(3));
+        let mut buf = bytes::BytesMut::from(&b"a\tb"[..]);
+        assert_eq!(WsMatcher::find_first(&buf), Some(1));
+        buf.clear();
+        assert_eq!(WsMatcher::find_first(&buf), None);
+    }
+}
@@ -0,0 +1,86 @@
+//! ANSI highlighting of matches, for debugging sets and CLI output.
+
+use crate::ShuftiMatch;
+
+/// An ANSI SGR style, e.g. [`Style::RED`] or `Style("1;4")` for bold
+/// underline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style(pub &'static str);
+
+impl Style {
+    pub const RED: Style = Style("31");
+    pub const GREEN: Style = Style("32");
+    pub const YELLOW: Style = Style("33");
+    pub const BLUE: Style = Style("34");
+    pub const MAGENTA: Style = Style("35");
+    pub const CYAN: Style = Style("36");
+    pub const BOLD: Style = Style("1");
+    pub const INVERSE: Style = Style("7");
+}
+
+const RESET: &str = "\x1b[0m";
+
+/// Render `haystack` with each run of matches wrapped in `style`. Bytes
+/// outside printable ASCII (and `\`) are escaped so control bytes can't
+/// disturb the terminal.
+pub(crate) fn highlight<M: ShuftiMatch + ?Sized>(haystack: &[u8], style: Style) -> String {
+    let mut out = String::with_capacity(haystack.len());
+    let mut copied = 0;
+    let mut open_until = None;
+    for pos in M::find_iter(haystack) {
+        if open_until != Some(pos) {
+            if open_until.is_some() {
+                out.push_str(RESET);
+            }
+            escape_into(&haystack[copied..pos], &mut out);
+            out.push_str("\x1b[");
+            out.push_str(style.0);
+            out.push('m');
+        }
+        escape_into(&haystack[pos..pos + 1], &mut out);
+        copied = pos + 1;
+        open_until = Some(copied);
+    }
+    if open_until.is_some() {
+        out.push_str(RESET);
+    }
+    escape_into(&haystack[copied..], &mut out);
+    out
+}
+
+fn escape_into(bytes: &[u8], out: &mut String) {
+    for &b in bytes {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.extend(b.escape_ascii().map(char::from)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::WsMatcher;
+
+    #[test]
+    fn test_highlight_runs() {
+        assert_eq!(
+            WsMatcher::highlight(b"a\tb\r\nc", Style::RED),
+            "a\x1b[31m\\t\x1b[0mb\x1b[31m\\r\\n\x1b[0mc"
+        );
+        assert_eq!(
+            WsMatcher::highlight(b"\n", Style("1;4")),
+            "\x1b[1;4m\\n\x1b[0m"
+        );
+        assert_eq!(WsMatcher::highlight(b"", Style::RED), "");
+    }
+
+    #[test]
+    fn test_highlight_escapes() {
+        assert_eq!(
+            WsMatcher::highlight(b"\x1b[2J\\\xff", Style::RED),
+            "\\x1b[2J\\\\\\xff"
+        );
+    }
+}
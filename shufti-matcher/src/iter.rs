@@ -0,0 +1,617 @@
+//! Iterators over the matches of a [`ShuftiMatch`] type.
+
+use core::marker::PhantomData;
+use core::ops::Range;
+
+use crate::search::{chunk_mask, eq_mask, lane_mask, load_chunk, run_from, stride_mask};
+use crate::{ShuftiMatch, TruffleTable, raw_16b};
+
+/// Iterator returned by [`ShuftiMatch::find_iter`]: the offset of every match,
+/// in order.
+///
+/// Double-ended: the back end scans chunks backwards from the end of the
+/// haystack, so `.rev()` and `.last()` never walk the front. When both ends
+/// reach the same chunk they share its remaining lanes.
+#[derive(Debug, Clone)]
+pub struct Matches<'a, M: ?Sized> {
+    haystack: &'a [u8],
+    /// Start of the chunk after the front's current one.
+    next: usize,
+    hits: u16,
+    /// Start of the back's current chunk.
+    back: usize,
+    back_hits: u16,
+    _matcher: PhantomData<fn() -> M>,
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> Matches<'a, M> {
+    pub(crate) fn new(haystack: &'a [u8]) -> Self {
+        Self {
+            haystack,
+            next: 0,
+            hits: 0,
+            back: haystack.len().div_ceil(16) * 16,
+            back_hits: 0,
+            _matcher: PhantomData,
+        }
+    }
+
+    /// Whether both ends are on the same chunk.
+    #[inline(always)]
+    fn shared(&self) -> bool {
+        self.next == self.back + 16
+    }
+}
+
+impl<M: ShuftiMatch + ?Sized> Iterator for Matches<'_, M> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            let hits = if self.hits != 0 {
+                &mut self.hits
+            } else if self.back_hits != 0 && self.shared() {
+                &mut self.back_hits
+            } else if self.next < self.back {
+                self.hits = chunk_mask(self.haystack, self.next, &raw_16b::<M>);
+                self.next += 16;
+                continue;
+            } else if self.next == self.back {
+                // Step onto the back's chunk.
+                self.next += 16;
+                continue;
+            } else {
+                return None;
+            };
+            let lane = hits.trailing_zeros() as usize;
+            *hits &= *hits - 1;
+            return Some(self.next - 16 + lane);
+        }
+    }
+
+    fn last(mut self) -> Option<usize> {
+        self.next_back()
+    }
+}
+
+impl<M: ShuftiMatch + ?Sized> DoubleEndedIterator for Matches<'_, M> {
+    fn next_back(&mut self) -> Option<usize> {
+        loop {
+            let hits = if self.back_hits != 0 {
+                &mut self.back_hits
+            } else if self.hits != 0 && self.shared() {
+                &mut self.hits
+            } else if self.back >= self.next + 16 {
+                self.back -= 16;
+                self.back_hits = chunk_mask(self.haystack, self.back, &raw_16b::<M>);
+                continue;
+            } else if self.back == self.next && self.back != 0 {
+                // Step onto the front's chunk.
+                self.back -= 16;
+                continue;
+            } else {
+                return None;
+            };
+            let lane = 15 - hits.leading_zeros() as usize;
+            *hits &= !(1 << lane);
+            return Some(self.back + lane);
+        }
+    }
+}
+
+/// Iterator returned by [`ShuftiMatch::find_iter_str`]: `(byte_index, char)`
+/// for every match that starts a character.
+#[derive(Debug, Clone)]
+pub struct StrMatches<'a, M: ?Sized> {
+    text: &'a str,
+    matches: Matches<'a, M>,
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> StrMatches<'a, M> {
+    pub(crate) fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            matches: Matches::new(text.as_bytes()),
+        }
+    }
+}
+
+impl<M: ShuftiMatch + ?Sized> Iterator for StrMatches<'_, M> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        let text = self.text;
+        self.matches
+            .by_ref()
+            .find(|&pos| text.is_char_boundary(pos))
+            .map(|pos| (pos, text[pos..].chars().next().unwrap()))
+    }
+}
+
+/// Iterator returned by [`ShuftiMatch::split`]: the pieces of the haystack
+/// between matches, like [`str::split`] with the whole set as delimiter.
+#[derive(Debug, Clone)]
+pub struct Split<'a, M: ?Sized> {
+    matches: Matches<'a, M>,
+    /// Start of the next piece.
+    start: usize,
+    finished: bool,
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> Split<'a, M> {
+    pub(crate) fn new(haystack: &'a [u8]) -> Self {
+        Self {
+            matches: Matches::new(haystack),
+            start: 0,
+            finished: false,
+        }
+    }
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> Iterator for Split<'a, M> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.finished {
+            return None;
+        }
+        let haystack = self.matches.haystack;
+        match self.matches.next() {
+            Some(pos) => {
+                let piece = haystack.get(self.start..pos).unwrap_or_default();
+                self.start = pos + 1;
+                Some(piece)
+            }
+            None => {
+                self.finished = true;
+                Some(haystack.get(self.start..).unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// Iterator returned by [`ShuftiMatch::splitn`]: at most `n` pieces, the
+/// last holding the unscanned remainder.
+#[derive(Debug, Clone)]
+pub struct SplitN<'a, M: ?Sized> {
+    split: Split<'a, M>,
+    remaining: usize,
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> SplitN<'a, M> {
+    pub(crate) fn new(haystack: &'a [u8], n: usize) -> Self {
+        Self {
+            split: Split::new(haystack),
+            remaining: n,
+        }
+    }
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> Iterator for SplitN<'a, M> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        match self.remaining {
+            0 => None,
+            1 => {
+                self.remaining = 0;
+                if self.split.finished {
+                    return None;
+                }
+                self.split.finished = true;
+                self.split.matches.haystack.get(self.split.start..)
+            }
+            _ => {
+                self.remaining -= 1;
+                self.split.next()
+            }
+        }
+    }
+}
+
+/// Iterator returned by [`ShuftiMatch::split_inclusive`]: like [`Split`],
+/// but each piece keeps its terminating delimiter.
+#[derive(Debug, Clone)]
+pub struct SplitInclusive<'a, M: ?Sized> {
+    matches: Matches<'a, M>,
+    /// Start of the next piece.
+    start: usize,
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> SplitInclusive<'a, M> {
+    pub(crate) fn new(haystack: &'a [u8]) -> Self {
+        Self {
+            matches: Matches::new(haystack),
+            start: 0,
+        }
+    }
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> Iterator for SplitInclusive<'a, M> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let haystack = self.matches.haystack;
+        let start = self.start;
+        let end = match self.matches.next() {
+            Some(pos) => pos + 1,
+            None if start < haystack.len() => haystack.len(),
+            None => return None,
+        };
+        self.start = end;
+        haystack.get(start..end)
+    }
+}
+
+/// Iterator returned by [`ShuftiMatch::tokens`]: the maximal runs of
+/// non-member bytes, like [`str::split_whitespace`].
+#[derive(Debug, Clone)]
+pub struct Tokens<'a, M: ?Sized> {
+    haystack: &'a [u8],
+    /// Where the search for the next token starts.
+    pos: usize,
+    _matcher: PhantomData<fn() -> M>,
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> Tokens<'a, M> {
+    pub(crate) fn new(haystack: &'a [u8]) -> Self {
+        Self {
+            haystack,
+            pos: 0,
+            _matcher: PhantomData,
+        }
+    }
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> Iterator for Tokens<'a, M> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let token = run_from(self.haystack, self.pos, &|c: &[u8; 16]| !raw_16b::<M>(c))?;
+        self.pos = token.end;
+        self.haystack.get(token)
+    }
+}
+
+/// Maximal runs of set lanes, decoded from chunk masks. A run that reaches
+/// the top lane of a chunk is carried into the following chunks.
+#[derive(Debug, Clone)]
+struct RunScanner<'a> {
+    haystack: &'a [u8],
+    /// Start of the chunk after the current one.
+    next: usize,
+    /// Set lanes of the current chunk not yet reported.
+    bits: u16,
+}
+
+impl<'a> RunScanner<'a> {
+    fn new(haystack: &'a [u8]) -> Self {
+        Self {
+            haystack,
+            next: 0,
+            bits: 0,
+        }
+    }
+
+    fn next_run<K>(&mut self, mask_16b: &K) -> Option<Range<usize>>
+    where
+        K: Fn(&[u8; 16]) -> u16,
+    {
+        while self.bits == 0 {
+            if self.next >= self.haystack.len() {
+                return None;
+            }
+            self.bits = chunk_mask(self.haystack, self.next, mask_16b);
+            self.next += 16;
+        }
+        let base = self.next - 16;
+        let lane = self.bits.trailing_zeros() as usize;
+        let end = lane + (!(self.bits >> lane)).trailing_zeros() as usize;
+        if end < 16 {
+            self.bits &= !lane_mask(end);
+            return Some(base + lane..base + end);
+        }
+        // The run fills the rest of a full chunk: extend it chunk by chunk.
+        self.bits = 0;
+        while self.next < self.haystack.len() {
+            let mask = chunk_mask(self.haystack, self.next, mask_16b);
+            self.next += 16;
+            let len = (!mask).trailing_zeros() as usize;
+            if len < 16 {
+                self.bits = mask & !lane_mask(len);
+                return Some(base + lane..self.next - 16 + len);
+            }
+        }
+        Some(base + lane..self.haystack.len())
+    }
+}
+
+/// Iterator returned by [`ShuftiMatch::match_runs`]: the half-open range of
+/// every maximal run of matching bytes, in order.
+#[derive(Debug, Clone)]
+pub struct MatchRuns<'a, M: ?Sized> {
+    runs: RunScanner<'a>,
+    _matcher: PhantomData<fn() -> M>,
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> MatchRuns<'a, M> {
+    pub(crate) fn new(haystack: &'a [u8]) -> Self {
+        Self {
+            runs: RunScanner::new(haystack),
+            _matcher: PhantomData,
+        }
+    }
+}
+
+impl<M: ShuftiMatch + ?Sized> Iterator for MatchRuns<'_, M> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        self.runs.next_run(&raw_16b::<M>)
+    }
+}
+
+/// Iterator returned by [`ShuftiMatch::gap_runs`]: the half-open range of
+/// every maximal run of non-matching bytes, in order.
+#[derive(Debug, Clone)]
+pub struct GapRuns<'a, M: ?Sized> {
+    runs: RunScanner<'a>,
+    _matcher: PhantomData<fn() -> M>,
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> GapRuns<'a, M> {
+    pub(crate) fn new(haystack: &'a [u8]) -> Self {
+        Self {
+            runs: RunScanner::new(haystack),
+            _matcher: PhantomData,
+        }
+    }
+}
+
+impl<M: ShuftiMatch + ?Sized> Iterator for GapRuns<'_, M> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        self.runs.next_run(&|c: &[u8; 16]| !raw_16b::<M>(c))
+    }
+}
+
+/// Iterator returned by [`ShuftiMatch::find_iter_strided`].
+///
+/// Chunks start at `phase` and advance by 16, so the grid pattern of each
+/// chunk only depends on its index; it is AND-ed into the chunk mask before
+/// any bit is decoded.
+#[derive(Debug, Clone)]
+pub struct StridedMatches<'a, M: ?Sized> {
+    haystack: &'a [u8],
+    phase: usize,
+    stride: usize,
+    /// Start of the chunk after the current one.
+    next: usize,
+    hits: u16,
+    _matcher: PhantomData<fn() -> M>,
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> StridedMatches<'a, M> {
+    pub(crate) fn new(haystack: &'a [u8], stride: usize, phase: usize) -> Self {
+        Self {
+            haystack,
+            phase,
+            stride,
+            // A zero stride selects nothing.
+            next: if stride == 0 { usize::MAX } else { phase },
+            hits: 0,
+            _matcher: PhantomData,
+        }
+    }
+}
+
+impl<M: ShuftiMatch + ?Sized> Iterator for StridedMatches<'_, M> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.hits == 0 {
+            if self.next >= self.haystack.len() {
+                return None;
+            }
+            let grid = stride_mask(self.next, self.phase, self.stride);
+            self.hits = chunk_mask(self.haystack, self.next, &raw_16b::<M>) & grid;
+            self.next += 16;
+        }
+        let lane = self.hits.trailing_zeros() as usize;
+        self.hits &= self.hits - 1;
+        Some(self.next - 16 + lane)
+    }
+}
+
+/// Iterator returned by [`ShuftiMatch::find_iter_source`].
+///
+/// Pulls the source 16 bytes at a time into an internal block and yields the
+/// offsets of matching bytes, counted from the first byte of the source.
+#[derive(Debug, Clone)]
+pub struct SourceMatches<I, M: ?Sized> {
+    source: I,
+    block: [u8; 16],
+    /// Offset of the first byte of `block`.
+    base: usize,
+    /// Offset of the first byte after `block`.
+    next: usize,
+    hits: u16,
+    exhausted: bool,
+    _matcher: PhantomData<fn() -> M>,
+}
+
+impl<I: Iterator<Item = u8>, M: ShuftiMatch + ?Sized> SourceMatches<I, M> {
+    pub(crate) fn new(source: I) -> Self {
+        Self {
+            source,
+            block: [0; 16],
+            base: 0,
+            next: 0,
+            hits: 0,
+            exhausted: false,
+            _matcher: PhantomData,
+        }
+    }
+
+    /// Refill `block` from the source; returns the number of bytes read.
+    fn refill(&mut self) -> usize {
+        let mut len = 0;
+        for (slot, b) in self.block.iter_mut().zip(self.source.by_ref()) {
+            *slot = b;
+            len += 1;
+        }
+        self.block[len..].fill(0);
+        len
+    }
+}
+
+impl<I: Iterator<Item = u8>, M: ShuftiMatch + ?Sized> Iterator for SourceMatches<I, M> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.hits == 0 {
+            if self.exhausted {
+                return None;
+            }
+            let len = self.refill();
+            self.exhausted = len < 16;
+            self.base = self.next;
+            self.next += len;
+            self.hits = raw_16b::<M>(&self.block) & lane_mask(len);
+        }
+        let lane = self.hits.trailing_zeros() as usize;
+        self.hits &= self.hits - 1;
+        Some(self.base + lane)
+    }
+}
+
+/// A match together with its source position, as yielded by
+/// [`ShuftiMatch::find_iter_located`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Located {
+    /// Byte offset of the match in the haystack.
+    pub offset: usize,
+    /// 1-based line number; lines are separated by `\n`.
+    pub line: usize,
+    /// 1-based byte column within the line.
+    pub column: usize,
+}
+
+/// Iterator returned by [`ShuftiMatch::find_iter_located`].
+///
+/// Each chunk is tested against the matcher and against `\n`; newlines are
+/// counted with popcounts, so chunks without matches cost two kernel calls
+/// and never bit-iterate.
+#[derive(Debug, Clone)]
+pub struct LocatedMatches<'a, M: ?Sized> {
+    haystack: &'a [u8],
+    /// Start of the chunk after the current one.
+    next: usize,
+    hits: u16,
+    newlines: u16,
+    /// Line of the first byte of the current chunk.
+    line: usize,
+    /// Offset where the line containing the current chunk's first byte starts.
+    line_start: usize,
+    _matcher: PhantomData<fn() -> M>,
+}
+
+impl<'a, M: ShuftiMatch + ?Sized> LocatedMatches<'a, M> {
+    pub(crate) fn new(haystack: &'a [u8]) -> Self {
+        Self {
+            haystack,
+            next: 0,
+            hits: 0,
+            newlines: 0,
+            line: 1,
+            line_start: 0,
+            _matcher: PhantomData,
+        }
+    }
+}
+
+impl<M: ShuftiMatch + ?Sized> Iterator for LocatedMatches<'_, M> {
+    type Item = Located;
+
+    fn next(&mut self) -> Option<Located> {
+        while self.hits == 0 {
+            if self.next >= self.haystack.len() {
+                return None;
+            }
+            // Fold the current chunk's newlines into the line counters.
+            if self.newlines != 0 {
+                self.line += self.newlines.count_ones() as usize;
+                self.line_start = self.next - 16 + highest_bit(self.newlines) + 1;
+            }
+            let (chunk, len) = load_chunk(self.haystack, self.next);
+            self.hits = raw_16b::<M>(&chunk) & lane_mask(len);
+            self.newlines = eq_mask(&chunk, b'\n') & lane_mask(len);
+            self.next += 16;
+        }
+
+        let base = self.next - 16;
+        let lane = self.hits.trailing_zeros() as usize;
+        self.hits &= self.hits - 1;
+        let before = self.newlines & lane_mask(lane);
+        let offset = base + lane;
+        let (line, line_start) = if before == 0 {
+            (self.line, self.line_start)
+        } else {
+            (
+                self.line + before.count_ones() as usize,
+                base + highest_bit(before) + 1,
+            )
+        };
+        Some(Located {
+            offset,
+            line,
+            column: offset - line_start + 1,
+        })
+    }
+}
+
+#[inline(always)]
+fn highest_bit(mask: u16) -> usize {
+    15 - mask.leading_zeros() as usize
+}
+/// Iterator returned by [`TruffleTable::find_iter`].
+#[derive(Debug, Clone)]
+pub struct TruffleMatches<'a> {
+    table: &'a TruffleTable,
+    haystack: &'a [u8],
+    /// Start of the chunk after the current one.
+    next: usize,
+    hits: u16,
+}
+
+impl<'a> TruffleMatches<'a> {
+    pub(crate) fn new(table: &'a TruffleTable, haystack: &'a [u8]) -> Self {
+        Self {
+            table,
+            haystack,
+            next: 0,
+            hits: 0,
+        }
+    }
+}
+
+impl Iterator for TruffleMatches<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.hits == 0 {
+            if self.next >= self.haystack.len() {
+                return None;
+            }
+            let table = self.table;
+            self.hits = chunk_mask(self.haystack, self.next, &|c: &[u8; 16]| {
+                kernel!(table.bitmask_16b(c))
+            });
+            self.next += 16;
+        }
+        let lane = self.hits.trailing_zeros() as usize;
+        self.hits &= self.hits - 1;
+        Some(self.next - 16 + lane)
+    }
+}
@@ -0,0 +1,479 @@
+//! Table-driven lexing on top of the shufti byte classes.
+//!
+//! Every byte of the input falls into one [`Class`]: the bucket its shufti
+//! table assigns it to, or [`Class::Other`] for bytes outside the needle set.
+//! A [`Lexer`] is a small state machine: its state is the kind of the open
+//! token (or *idle*), and for each state it holds one [`Action`] per class.
+//!
+//! Runs of *other* bytes are never walked byte-by-byte: whenever the current
+//! state drops them (idle) or continues the open token with them, the lexer
+//! jumps straight to the next member byte using the 16-byte chunk masks.
+//!
+//! ```rust
+//! use shufti_matcher::lexer::{Action, Class, Lexer};
+//! use shufti_matcher::{ShuftiMatch, ShuftiMatcher};
+//!
+//! #[derive(ShuftiMatcher)]
+//! #[shufti(set = " ,\"")]
+//! struct Delims;
+//!
+//! #[derive(Debug, Clone, Copy, PartialEq)]
+//! enum Kind { Word, Comma, Str }
+//!
+//! const SPACE: Class = Class::Bucket(0);
+//! const COMMA: Class = Class::Bucket(1);
+//! const QUOTE: Class = Class::Bucket(2);
+//!
+//! let lexer = Lexer::new(Delims::table())
+//!     .rule(Class::Other, Action::Start(Kind::Word))
+//!     .rule(COMMA, Action::Single(Kind::Comma))
+//!     .rule(QUOTE, Action::Start(Kind::Str))
+//!     .rule_in(Kind::Word, Class::Other, Action::Continue)
+//!     .rule_in(Kind::Str, Class::Other, Action::Continue)
+//!     .rule_in(Kind::Str, SPACE, Action::Continue)
+//!     .rule_in(Kind::Str, COMMA, Action::Continue)
+//!     .rule_in(Kind::Str, QUOTE, Action::Terminate);
+//!
+//! let hay = b"say \"hi, there\",ok";
+//! let tokens: Vec<_> = lexer.tokens(hay).map(|t| (t.kind, &hay[t.span])).collect();
+//! assert_eq!(
+//!     tokens,
+//!     [
+//!         (Kind::Word, &b"say"[..]),
+//!         (Kind::Str, &b"\"hi, there\""[..]),
+//!         (Kind::Comma, &b","[..]),
+//!         (Kind::Word, &b"ok"[..]),
+//!     ]
+//! );
+//! ```
+
+use core::ops::Range;
+
+use crate::ShuftiTable;
+
+/// A byte class as seen by the lexer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// Bytes outside the needle set.
+    Other,
+    /// Member bytes whose lowest bucket bit is `n` (0..8).
+    ///
+    /// For sets of up to 8 bytes the derive assigns bucket *i* to the *i*-th
+    /// byte of `set`. Larger sets are packed by the slow builder, where several
+    /// bytes can share one bucket; a rule for that bucket applies to all of them.
+    /// Use [`ShuftiMatch::bucket_of`](crate::ShuftiMatch::bucket_of) to see the
+    /// grouping, or [`Class::Byte`] to address a single byte.
+    Bucket(u8),
+    /// A single member byte, regardless of the bucket it shares.
+    Byte(u8),
+}
+
+/// What the lexer does with a byte of a given class in a given state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action<K> {
+    /// Terminate the open token before this byte and drop the byte.
+    Skip,
+    /// Terminate the open token before this byte and start a token of kind `K`
+    /// at it.
+    Start(K),
+    /// Extend the open token with this byte. Dropped when idle.
+    Continue,
+    /// Extend the open token with this byte, then terminate it. Dropped when idle.
+    Terminate,
+    /// Terminate the open token before this byte and emit the byte as a
+    /// one-byte token of kind `K`.
+    Single(K),
+}
+
+/// A token produced by [`Lexer::tokens`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<K> {
+    /// Kind the token was started with.
+    pub kind: K,
+    /// Byte range of the token in the haystack.
+    pub span: Range<usize>,
+}
+
+/// Actions for one lexer state, resolved per byte.
+#[derive(Debug, Clone)]
+struct Rules<K> {
+    other: Option<Action<K>>,
+    members: [Option<Action<K>>; 256],
+}
+
+impl<K: Copy> Rules<K> {
+    fn new() -> Self {
+        Self {
+            other: None,
+            members: [None; 256],
+        }
+    }
+}
+
+/// A per-state, per-class state machine driven by the chunk masks of a
+/// [`ShuftiTable`].
+///
+/// Idle rules default to [`Action::Skip`]. A class without a rule in a token
+/// state terminates the open token before the byte, which is then handled by
+/// the idle rules.
+#[derive(Debug, Clone)]
+pub struct Lexer<K> {
+    table: ShuftiTable,
+    buckets: [u8; 256],
+    idle: Rules<K>,
+    states: Vec<(K, Rules<K>)>,
+}
+
+impl<K: Copy + PartialEq> Lexer<K> {
+    /// Create a lexer over `table` with no rules.
+    pub fn new(table: ShuftiTable) -> Self {
+        Self {
+            table,
+            buckets: table.bucket_map(),
+            idle: Rules::new(),
+            states: Vec::new(),
+        }
+    }
+
+    /// Set the action for `class` while no token is open. Later rules override
+    /// earlier ones for the bytes they share.
+    ///
+    /// # Panics
+    /// Panics if `class` is a bucket `>= 8`, or a byte outside the needle set.
+    pub fn rule(mut self, class: Class, action: Action<K>) -> Self {
+        let buckets = self.buckets;
+        Self::set(&mut self.idle, &buckets, class, action);
+        self
+    }
+
+    /// Set the action for `class` while a token of `kind` is open.
+    ///
+    /// # Panics
+    /// Panics if `class` is a bucket `>= 8`, or a byte outside the needle set.
+    pub fn rule_in(mut self, kind: K, class: Class, action: Action<K>) -> Self {
+        let idx = match self.states.iter().position(|(k, _)| *k == kind) {
+            Some(idx) => idx,
+            None => {
+                self.states.push((kind, Rules::new()));
+                self.states.len() - 1
+            }
+        };
+        let buckets = self.buckets;
+        Self::set(&mut self.states[idx].1, &buckets, class, action);
+        self
+    }
+
+    /// Lex `haystack`, yielding tokens in input order. A token still open at
+    /// the end of the input is emitted up to the end.
+    pub fn tokens<'a>(&'a self, haystack: &'a [u8]) -> Tokens<'a, K> {
+        Tokens {
+            lexer: self,
+            haystack,
+            pos: 0,
+            open: None,
+            pending: None,
+        }
+    }
+
+    fn set(rules: &mut Rules<K>, buckets: &[u8; 256], class: Class, action: Action<K>) {
+        match class {
+            Class::Other => rules.other = Some(action),
+            Class::Bucket(n) => {
+                assert!(n < 8, "shufti tables have 8 buckets, got bucket {n}");
+                for (b, &bits) in buckets.iter().enumerate() {
+                    if bits != 0 && bits.trailing_zeros() == n as u32 {
+                        rules.members[b] = Some(action);
+                    }
+                }
+            }
+            Class::Byte(b) => {
+                assert!(
+                    buckets[b as usize] != 0,
+                    "byte 0x{b:02x} is not in the needle set"
+                );
+                rules.members[b as usize] = Some(action);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn rules_in(&self, kind: K) -> Option<&Rules<K>> {
+        self.states.iter().find(|(k, _)| *k == kind).map(|(_, r)| r)
+    }
+
+    #[inline(always)]
+    fn action(&self, rules: Option<&Rules<K>>, b: u8) -> Option<Action<K>> {
+        let rules = rules?;
+        if self.buckets[b as usize] == 0 {
+            rules.other
+        } else {
+            rules.members[b as usize]
+        }
+    }
+
+    #[inline(always)]
+    fn next_member(&self, haystack: &[u8], start: usize) -> usize {
+        let table = &self.table;
+        // SAFETY: `bitmask_16b` dispatches to the kernel selected for this target.
+        crate::search::find_from(haystack, start, |c| kernel!(table.bitmask_16b(c)))
+            .unwrap_or(haystack.len())
+    }
+}
+
+/// Iterator returned by [`Lexer::tokens`].
+#[derive(Debug, Clone)]
+pub struct Tokens<'a, K> {
+    lexer: &'a Lexer<K>,
+    haystack: &'a [u8],
+    pos: usize,
+    open: Option<(K, usize)>,
+    pending: Option<Token<K>>,
+}
+
+impl<K: Copy + PartialEq> Iterator for Tokens<'_, K> {
+    type Item = Token<K>;
+
+    fn next(&mut self) -> Option<Token<K>> {
+        if let Some(token) = self.pending.take() {
+            return Some(token);
+        }
+        let lexer = self.lexer;
+        loop {
+            if self.pos >= self.haystack.len() {
+                let (kind, start) = self.open.take()?;
+                return Some(Token {
+                    kind,
+                    span: start..self.haystack.len(),
+                });
+            }
+
+            let rules = match self.open {
+                Some((kind, _)) => lexer.rules_in(kind),
+                None => Some(&lexer.idle),
+            };
+
+            // Fast path: other bytes are dropped (idle) or continue the open
+            // token, so skip to the next member byte in one SIMD scan.
+            let other = rules.and_then(|r| r.other);
+            let uniform = match self.open {
+                None => !matches!(other, Some(Action::Start(_) | Action::Single(_))),
+                Some(_) => other == Some(Action::Continue),
+            };
+            if uniform {
+                self.pos = lexer.next_member(self.haystack, self.pos);
+                if self.pos >= self.haystack.len() {
+                    continue;
+                }
+            }
+
+            let pos = self.pos;
+            let action = lexer.action(rules, self.haystack[pos]);
+            let Some((kind, start)) = self.open else {
+                // Idle: only `Start` and `Single` do anything.
+                self.pos += 1;
+                match action {
+                    Some(Action::Start(k)) => self.open = Some((k, pos)),
+                    Some(Action::Single(k)) => {
+                        return Some(Token {
+                            kind: k,
+                            span: pos..pos + 1,
+                        });
+                    }
+                    _ => {}
+                }
+                continue;
+            };
+
+            let end = match action {
+                Some(Action::Continue) => {
+                    self.pos += 1;
+                    continue;
+                }
+                Some(Action::Terminate) => {
+                    self.pos += 1;
+                    self.open = None;
+                    pos + 1
+                }
+                Some(Action::Skip) => {
+                    self.pos += 1;
+                    self.open = None;
+                    pos
+                }
+                Some(Action::Start(k)) => {
+                    self.pos += 1;
+                    self.open = Some((k, pos));
+                    pos
+                }
+                Some(Action::Single(k)) => {
+                    self.pos += 1;
+                    self.open = None;
+                    self.pending = Some(Token {
+                        kind: k,
+                        span: pos..pos + 1,
+                    });
+                    pos
+                }
+                // No rule: close the token and revisit the byte as idle.
+                None => {
+                    self.open = None;
+                    pos
+                }
+            };
+            return Some(Token {
+                kind,
+                span: start..end,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::fast_table;
+    use crate::{ShuftiMatch, ShuftiMatcher};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Kind {
+        Word,
+        Num,
+        Comma,
+        Str,
+        A,
+        B,
+    }
+
+    #[derive(ShuftiMatcher)]
+    #[shufti(set = " ,")]
+    struct Delims;
+
+    #[derive(ShuftiMatcher)]
+    #[shufti(set = "0123456789")]
+    struct Digits;
+
+    fn spans(lexer: &Lexer<Kind>, hay: &[u8]) -> Vec<(Kind, Range<usize>)> {
+        lexer.tokens(hay).map(|t| (t.kind, t.span)).collect()
+    }
+
+    fn words() -> Lexer<Kind> {
+        Lexer::new(Delims::table())
+            .rule(Class::Other, Action::Start(Kind::Word))
+            .rule(Class::Bucket(1), Action::Single(Kind::Comma))
+            .rule_in(Kind::Word, Class::Other, Action::Continue)
+    }
+
+    #[test]
+    fn test_words_and_punct() {
+        assert_eq!(
+            spans(&words(), b"foo  bar,baz"),
+            vec![
+                (Kind::Word, 0..3),
+                (Kind::Word, 5..8),
+                (Kind::Comma, 8..9),
+                (Kind::Word, 9..12),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_single_after_token_at_chunk_boundary() {
+        for at in [15, 16, 17] {
+            let mut hay = vec![b'x'; 24];
+            hay[at] = b',';
+            assert_eq!(
+                spans(&words(), &hay),
+                vec![
+                    (Kind::Word, 0..at),
+                    (Kind::Comma, at..at + 1),
+                    (Kind::Word, at + 1..24),
+                ],
+                "comma at {at}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_switch_between_token_kinds() {
+        let lexer = Lexer::new(Digits::table())
+            .rule(Class::Other, Action::Start(Kind::Word))
+            .rule(Class::Bucket(0), Action::Start(Kind::Num))
+            .rule_in(Kind::Word, Class::Other, Action::Continue)
+            .rule_in(Kind::Word, Class::Bucket(0), Action::Start(Kind::Num))
+            .rule_in(Kind::Num, Class::Bucket(0), Action::Continue)
+            .rule_in(Kind::Num, Class::Other, Action::Start(Kind::Word));
+        assert_eq!(
+            spans(&lexer, b"ab12cd3"),
+            vec![
+                (Kind::Word, 0..2),
+                (Kind::Num, 2..4),
+                (Kind::Word, 4..6),
+                (Kind::Num, 6..7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_slow_builder_shared_bucket() {
+        // Ten digits do not fit the one-bucket-per-byte layout; the slow
+        // builder packs all of them into bucket 0.
+        let lexer = Lexer::new(Digits::table())
+            .rule(Class::Bucket(0), Action::Start(Kind::Num))
+            .rule_in(Kind::Num, Class::Bucket(0), Action::Continue);
+        let hay = b"id 12345678901234567890 x9";
+        assert_eq!(
+            spans(&lexer, hay),
+            vec![(Kind::Num, 3..23), (Kind::Num, 25..26)]
+        );
+    }
+
+    #[test]
+    fn test_byte_rule_splits_shared_bucket() {
+        assert_eq!(Digits::bucket_of(b'0'), Digits::bucket_of(b'9'));
+        let lexer = Lexer::new(Digits::table())
+            .rule(Class::Bucket(0), Action::Single(Kind::Num))
+            .rule(Class::Byte(b'0'), Action::Single(Kind::A));
+        assert_eq!(
+            spans(&lexer, b"x0y1"),
+            vec![(Kind::A, 1..2), (Kind::Num, 3..4)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not in the needle set")]
+    fn test_byte_rule_rejects_non_member() {
+        let _ = Lexer::<Kind>::new(Digits::table()).rule(Class::Byte(b'x'), Action::Skip);
+    }
+
+    #[test]
+    fn test_terminate_includes_byte() {
+        let lexer = Lexer::new(fast_table(b"\""))
+            .rule(Class::Bucket(0), Action::Start(Kind::Str))
+            .rule_in(Kind::Str, Class::Other, Action::Continue)
+            .rule_in(Kind::Str, Class::Bucket(0), Action::Terminate);
+        let hay = b"a \"quoted string that spans chunks\" b \"open";
+        assert_eq!(
+            spans(&lexer, hay),
+            vec![(Kind::Str, 2..35), (Kind::Str, 38..43)]
+        );
+    }
+
+    #[test]
+    fn test_lowest_bucket_wins() {
+        // 'a' is in buckets 0 and 1.
+        let mut table = fast_table(b"");
+        table.low_tab[(b'a' & 0x0f) as usize] = 0b11;
+        table.high_tab[(b'a' >> 4) as usize] = 0b11;
+        table.bit_mask = 0b11;
+        let lexer = Lexer::new(table)
+            .rule(Class::Bucket(0), Action::Single(Kind::A))
+            .rule(Class::Bucket(1), Action::Single(Kind::B));
+        assert_eq!(spans(&lexer, b"xax"), vec![(Kind::A, 1..2)]);
+    }
+
+    #[test]
+    fn test_empty() {
+        let lexer = Lexer::<Kind>::new(fast_table(b","));
+        assert_eq!(spans(&lexer, b""), vec![]);
+        assert_eq!(spans(&lexer, b"abc"), vec![]);
+    }
+}
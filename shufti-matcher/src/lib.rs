@@ -1,11 +1,35 @@
+#![cfg_attr(feature = "no-unsafe", forbid(unsafe_code))]
 //! # shufti-runtime
 //!
 //! Runtime support for the `ShuftiMatcher` derive macro.
 //!
 //! Provides:
 //! - [`ShuftiTable`] – the precomputed SIMD lookup tables (AArch64).
+//! - [`TableBuilder`] – a table from caller-assigned buckets.
+//! - [`TruffleTable`] – an exact table for any byte set, used when shufti's
+//!   buckets run out.
+//! - [`WideShuftiTable`] – a 16-bucket table pair for sets that need more than 8.
 //! - [`ShuftiMatcher`] – trait implemented by the derive macro; exposes
 //!   [`match_16b`](ShuftiMatcher::match_16b) and [`find_first`](ShuftiMatcher::find_first).
+//! - [`Or`] / [`And`] / [`Not`] – matchers composed from other matchers at
+//!   compile time.
+//! - [`DynShuftiMatcher`] – object-safe view of any matcher, for `Box<dyn …>`.
+//! - [`Algorithm`] – the cmpeq / shufti / wide-shufti / truffle choice made for each set.
+//! - [`active_backend`] – which SIMD kernel this build dispatches to.
+//! - [`Mask16`] / [`Mask32`] / [`Mask64`] – typed chunk masks.
+//! - [`Finder`] / [`FinderBuilder`] – matchers for sets only known at runtime,
+//!   and the one-shot [`find_any_of`] / [`find_all_of`] / [`count_of`].
+//! - [`LazyMatcher`] – a `static` runtime matcher built on first use.
+//! - [`CharFinder`] – sets of `char`s, including multi-byte UTF-8 characters.
+//! - [`ShuftiMatch::highlight`] – ANSI rendering of matches for debugging.
+//! - [`bitmask`] – stage-2 helpers over the packed bitmaps from
+//!   [`bitmap`](ShuftiMatch::bitmap).
+//! - `async_read` (feature `futures`) – match streams over `futures_io::AsyncBufRead`.
+//! - `Serialize`/`Deserialize` for [`ShuftiTable`] (feature `serde`), versioned
+//!   and validated on load.
+//! - [`MatcherRegistry`] – matchers of either kind looked up by name.
+//! - `registry` (feature `registry`) – named finders loaded from JSON/TOML.
+//! - `lexer` (feature `lexer`) – a table-driven lexer over the shufti byte classes.
 //!
 //! ## Usage
 //!
@@ -21,16 +45,111 @@
 //! assert_eq!(pos, Some(5));
 //! ```
 
+//! ## Panics
+//!
+//! The search entry points neither assert on their arguments nor index out of
+//! bounds: out-of-range starts and offsets simply find nothing, and the short
+//! epilogue chunk is always copied into a padded buffer. For the
+//! non-allocating ones (the `find_*`, `count`, prefix, suffix and trimming
+//! methods of [`ShuftiMatch`] and its match and split iterators, plus
+//! [`Finder`]'s `find`, `rfind`, `count` and `find_iter` and
+//! [`TruffleTable::find_first`]) this is checked at link time by
+//! `tests/no_panic.rs`, run with
+//! `cargo test -p shufti-matcher --profile no-panic --test no_panic`. The
+//! check does not cover the `memchr-fallback` route, which calls into
+//! `memchr`.
+//!
+//! Outside that surface, allocating methods can panic on capacity overflow
+//! like any `Vec`, deadline-bounded scans call [`Instant::now`], and the
+//! `lexer` rule builders (`Lexer::rule`, `Lexer::rule_in`) panic by design on
+//! invalid rule configuration (documented on each method).
+//!
+//! [`Instant::now`]: std::time::Instant::now
+//!
+//! ## `no-unsafe`
+//!
+//! With the `no-unsafe` feature the crate is built with
+//! `#![forbid(unsafe_code)]`: every kernel is the safe scalar loop, the table
+//! kernels become safe functions, and the raw-pointer entry points
+//! ([`ShuftiMatch::find_first_nul_terminated`], [`ShuftiMatch::find_first_raw`])
+//! are unavailable.
+
 // Re-export the derive macro for convenience (one `use` covers both).
-pub use shufti_macro::ShuftiMatcher;
+pub use shufti_macro::{ShuftiMatcher, shufti_assert_buckets};
+
+// Lets the derive's `::shufti_matcher::…` paths resolve inside this crate.
+extern crate self as shufti_matcher;
+
+/// Call a chunk kernel: inside `unsafe` normally, directly under `no-unsafe`
+/// where the kernels are safe functions.
+macro_rules! kernel {
+    ($call:expr) => {{
+        // SAFETY: kernels dispatch to the implementation selected for this
+        // target; the intrinsics they use are enabled at compile time.
+        #[cfg(not(feature = "no-unsafe"))]
+        let out = unsafe { $call };
+        #[cfg(feature = "no-unsafe")]
+        let out = $call;
+        out
+    }};
+}
+
 mod arch;
+#[cfg(feature = "futures")]
+pub mod async_read;
+mod binary;
+pub mod bitmask;
+mod build;
+mod charset;
+mod combinator;
+mod deadline;
+mod dyn_matcher;
+mod finder;
+mod haystack;
+mod highlight;
+mod iter;
+mod line_index;
+mod mask;
+mod matcher_registry;
+#[cfg(feature = "registry")]
+pub mod registry;
+mod search;
+#[cfg(feature = "serde")]
+mod table_serde;
+
+pub use arch::{Backend, active_backend};
+pub use binary::looks_binary;
+pub use build::{BuildError, Mismatch, TableBuilder};
+pub use charset::{CharFinder, CharMatches};
+pub use combinator::{And, Not, Or};
+pub use deadline::{LimitReached, TimedOut};
+pub use dyn_matcher::DynShuftiMatcher;
+pub use finder::{
+    Finder, FinderBuilder, FinderMatches, LazyMatcher, StreamFinder, count_of, find_all_of,
+    find_any_of, find_first_of_any,
+};
+pub use haystack::Haystack;
+pub use highlight::Style;
+pub use iter::{
+    GapRuns, Located, LocatedMatches, MatchRuns, Matches, SourceMatches, Split, SplitInclusive,
+    SplitN, StrMatches, StridedMatches, Tokens, TruffleMatches,
+};
+pub use line_index::LineIndex;
+pub use mask::{Mask16, Mask32, Mask64, MaskOnes};
+pub use matcher_registry::{MatcherRegistry, Registered};
+pub use shufti_core::Algorithm;
+
+use std::ops::Range;
+
+#[cfg(feature = "lexer")]
+pub mod lexer;
 
 // ---------------------------------------------------------------------------
 // ShuftiTable
 // ---------------------------------------------------------------------------
 
 /// Pre-computed shufti lookup tables. Produced by the derive macro at compile
-/// time; never constructed at runtime.
+/// time, or by [`FinderBuilder`] for sets only known at runtime.
 #[derive(Debug, Clone, Copy)]
 pub struct ShuftiTable {
     pub low_tab: [u8; 16],
@@ -44,12 +163,394 @@ impl ShuftiTable {
     ///
     /// # Safety
     /// Must be called on an AArch64 target with NEON available.
+    #[cfg(not(feature = "no-unsafe"))]
     #[inline(always)]
     pub unsafe fn bitmask_16b(&self, data: &[u8; 16]) -> u16 {
         unsafe { crate::arch::bitmask_16b(&self.low_tab, &self.high_tab, self.bit_mask, data) }
     }
+
+    /// Safe [`bitmask_16b`](Self::bitmask_16b) (feature `no-unsafe`).
+    #[cfg(feature = "no-unsafe")]
+    #[inline(always)]
+    pub fn bitmask_16b(&self, data: &[u8; 16]) -> u16 {
+        crate::arch::bitmask_16b(&self.low_tab, &self.high_tab, self.bit_mask, data)
+    }
+
+    /// [`bitmask_16b`](Self::bitmask_16b) over 32 bytes: bit *i* of the result
+    /// is set when `data[i]` is a member.
+    ///
+    /// # Safety
+    /// Same requirements as [`bitmask_16b`](Self::bitmask_16b).
+    #[cfg(not(feature = "no-unsafe"))]
+    #[inline(always)]
+    pub unsafe fn bitmask_32b(&self, data: &[u8; 32]) -> u32 {
+        let mut mask = 0;
+        for (i, chunk) in data.as_chunks::<16>().0.iter().enumerate() {
+            mask |= (unsafe { self.bitmask_16b(chunk) } as u32) << (16 * i);
+        }
+        mask
+    }
+
+    /// Safe [`bitmask_32b`](Self::bitmask_32b) (feature `no-unsafe`).
+    #[cfg(feature = "no-unsafe")]
+    #[inline(always)]
+    pub fn bitmask_32b(&self, data: &[u8; 32]) -> u32 {
+        let mut mask = 0;
+        for (i, chunk) in data.as_chunks::<16>().0.iter().enumerate() {
+            mask |= (self.bitmask_16b(chunk) as u32) << (16 * i);
+        }
+        mask
+    }
+
+    /// [`bitmask_16b`](Self::bitmask_16b) over 64 bytes: bit *i* of the result
+    /// is set when `data[i]` is a member.
+    ///
+    /// # Safety
+    /// Same requirements as [`bitmask_16b`](Self::bitmask_16b).
+    #[cfg(not(feature = "no-unsafe"))]
+    #[inline(always)]
+    pub unsafe fn bitmask_64b(&self, data: &[u8; 64]) -> u64 {
+        let mut mask = 0;
+        for (i, chunk) in data.as_chunks::<16>().0.iter().enumerate() {
+            mask |= (unsafe { self.bitmask_16b(chunk) } as u64) << (16 * i);
+        }
+        mask
+    }
+
+    /// Safe [`bitmask_64b`](Self::bitmask_64b) (feature `no-unsafe`).
+    #[cfg(feature = "no-unsafe")]
+    #[inline(always)]
+    pub fn bitmask_64b(&self, data: &[u8; 64]) -> u64 {
+        let mut mask = 0;
+        for (i, chunk) in data.as_chunks::<16>().0.iter().enumerate() {
+            mask |= (self.bitmask_16b(chunk) as u64) << (16 * i);
+        }
+        mask
+    }
+
+    /// Index of the first member lane of `data`, or `None`. On NEON this
+    /// skips the full movemask reduction.
+    ///
+    /// # Safety
+    /// Same requirements as [`bitmask_16b`](Self::bitmask_16b).
+    #[cfg(not(feature = "no-unsafe"))]
+    #[inline(always)]
+    pub unsafe fn first_match_16b(&self, data: &[u8; 16]) -> Option<u32> {
+        unsafe { crate::arch::first_match_16b(&self.low_tab, &self.high_tab, self.bit_mask, data) }
+    }
+
+    /// Safe [`first_match_16b`](Self::first_match_16b) (feature `no-unsafe`).
+    #[cfg(feature = "no-unsafe")]
+    #[inline(always)]
+    pub fn first_match_16b(&self, data: &[u8; 16]) -> Option<u32> {
+        crate::arch::first_match_16b(&self.low_tab, &self.high_tab, self.bit_mask, data)
+    }
+
+    /// Build a table for `needles` at runtime, with the derive macro's rules:
+    /// up to 8 needles get a bucket each, larger sets share buckets. Fails on
+    /// an empty set, a duplicate byte, or a set that does not fit 8 buckets.
+    pub fn try_new(needles: &[u8]) -> Result<Self, BuildError> {
+        build::try_build_table(needles)
+    }
+
+    /// Table for the bytes accepted by `self` or `other`, with the buckets
+    /// assigned afresh over the merged set. Fails when the union does not
+    /// fit 8 buckets.
+    pub fn union(&self, other: &ShuftiTable) -> Result<Self, BuildError> {
+        let needles: Vec<u8> = (0..=255u8)
+            .filter(|&b| self.bucket_of(b) | other.bucket_of(b) != 0)
+            .collect();
+        build::try_build_table(&needles)
+    }
+
+    /// Table for the bytes accepted by both `self` and `other`. Fails with
+    /// [`BuildError::Empty`] when the sets are disjoint.
+    pub fn intersection(&self, other: &ShuftiTable) -> Result<Self, BuildError> {
+        let needles: Vec<u8> = (0..=255u8)
+            .filter(|&b| self.bucket_of(b) != 0 && other.bucket_of(b) != 0)
+            .collect();
+        build::try_build_table(&needles)
+    }
+
+    /// Table for every byte `self` rejects. Fails with
+    /// [`BuildError::TooManyBuckets`] when the complement does not pack into
+    /// 8 buckets; [`Finder::negated`] falls back to the wide or truffle
+    /// kernel instead.
+    pub fn complement(&self) -> Result<Self, BuildError> {
+        let needles: Vec<u8> = (0..=255u8).filter(|&b| self.bucket_of(b) == 0).collect();
+        build::try_build_table(&needles)
+    }
+
+    /// Bucket bits the table assigns to `b`; 0 when `b` is not in the set.
+    #[inline(always)]
+    pub const fn bucket_of(&self, b: u8) -> u8 {
+        self.low_tab[(b & 0x0f) as usize] & self.high_tab[(b >> 4) as usize] & self.bit_mask
+    }
+
+    /// Every byte the table accepts, in increasing order.
+    pub fn members(&self) -> Vec<u8> {
+        (0..=255u8).filter(|&b| self.bucket_of(b) != 0).collect()
+    }
+
+    /// Check all 256 byte values against `expected` (order and duplicates
+    /// do not matter), reporting every false positive and false negative.
+    pub fn verify(&self, expected: &[u8]) -> Result<(), Mismatch> {
+        build::verify(self, expected)
+    }
+
+    /// The bytes each bucket accepts, in increasing order: entry *i* lists
+    /// every `b` whose [`bucket_of`](Self::bucket_of) has bit *i* set. A byte
+    /// appears under several buckets when their nibble combinations overlap.
+    pub fn buckets(&self) -> [Vec<u8>; 8] {
+        core::array::from_fn(|i| {
+            (0..=255u8)
+                .filter(|&b| self.bucket_of(b) & (1 << i) != 0)
+                .collect()
+        })
+    }
+
+    /// Rust source for a constant expression that rebuilds this table, e.g.
+    /// to freeze a runtime-built table into generated code.
+    pub fn to_const_expr(&self) -> String {
+        format!(
+            "::shufti_matcher::ShuftiTable {{ low_tab: {:?}, high_tab: {:?}, bit_mask: {:#04x} }}",
+            self.low_tab, self.high_tab, self.bit_mask
+        )
+    }
+
+    /// [`bucket_of`](Self::bucket_of) for every byte value.
+    pub const fn bucket_map(&self) -> [u8; 256] {
+        let mut map = [0u8; 256];
+        let mut b = 0;
+        while b < 256 {
+            map[b] = self.bucket_of(b as u8);
+            b += 1;
+        }
+        map
+    }
+}
+
+/// Scalar [`ShuftiMatch::find_first`] over a bucket map, usable in `const`
+/// contexts, e.g. to reject a static config that contains control bytes at
+/// compile time:
+///
+/// ```rust,ignore
+/// const _: () = assert!(find_first_const(&Controls::BUCKET_OF, CONFIG).is_none());
+/// ```
+pub const fn find_first_const(bucket_of: &[u8; 256], haystack: &[u8]) -> Option<usize> {
+    let mut i = 0;
+    while i < haystack.len() {
+        if bucket_of[haystack[i] as usize] != 0 {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Membership bitmap of the bytes with a nonzero entry in `bucket_of`.
+const fn bitmap_of(bucket_of: &[u8; 256]) -> [u64; 4] {
+    let mut bitmap = [0u64; 4];
+    let mut b = 0;
+    while b < 256 {
+        if bucket_of[b] != 0 {
+            bitmap[b >> 6] |= 1 << (b & 63);
+        }
+        b += 1;
+    }
+    bitmap
+}
+
+/// The (up to) three smallest members of a membership bitmap, and how many
+/// there are; feeds `memchr` from the set itself rather than
+/// [`ShuftiMatch::SET`], whose spelling may repeat or widen bytes.
+#[cfg(feature = "memchr-fallback")]
+const fn first_members(bitmap: &[u64; 4]) -> ([u8; 3], usize) {
+    let mut out = [0u8; 3];
+    let mut len = 0;
+    let mut b = 0;
+    while b < 256 && len < 3 {
+        if bitmap[b >> 6] & (1 << (b & 63)) != 0 {
+            out[len] = b as u8;
+            len += 1;
+        }
+        b += 1;
+    }
+    (out, len)
+}
+
+/// The raw bits of [`ShuftiMatch::match_16b`], the shape the search loops
+/// take their chunk kernel in.
+#[inline(always)]
+pub(crate) fn raw_16b<M: ShuftiMatch + ?Sized>(chunk: &[u8; 16]) -> u16 {
+    M::match_16b(chunk).0
+}
+
+/// Clear the lanes of `mask` whose byte of `chunk` is not in `bitmap`.
+#[inline(always)]
+fn verify_candidates(mask: u16, chunk: &[u8; 16], bitmap: &[u64; 4]) -> u16 {
+    let mut verified = mask;
+    let mut candidates = mask;
+    while candidates != 0 {
+        let lane = candidates.trailing_zeros() as usize;
+        candidates &= candidates - 1;
+        let b = chunk[lane];
+        if bitmap[(b >> 6) as usize] & (1 << (b & 63)) == 0 {
+            verified &= !(1 << lane);
+        }
+    }
+    verified
+}
+
+/// A 16-bucket shufti table: two 8-bucket table pairs whose chunk masks are
+/// OR-ed together. Fits sets that need between 9 and 16 buckets, at the cost
+/// of a second pair of lookups per chunk ([`Algorithm::WideShufti`]).
+#[derive(Debug, Clone, Copy)]
+pub struct WideShuftiTable {
+    /// Buckets 0..8.
+    pub buckets_lo: ShuftiTable,
+    /// Buckets 8..16.
+    pub buckets_hi: ShuftiTable,
+}
+
+impl WideShuftiTable {
+    /// Test a 16-byte chunk against both table pairs.
+    ///
+    /// # Safety
+    /// Same requirements as [`ShuftiTable::bitmask_16b`].
+    #[cfg(not(feature = "no-unsafe"))]
+    #[inline(always)]
+    pub unsafe fn bitmask_16b(&self, data: &[u8; 16]) -> u16 {
+        unsafe { self.buckets_lo.bitmask_16b(data) | self.buckets_hi.bitmask_16b(data) }
+    }
+
+    /// Safe [`bitmask_16b`](Self::bitmask_16b) (feature `no-unsafe`).
+    #[cfg(feature = "no-unsafe")]
+    #[inline(always)]
+    pub fn bitmask_16b(&self, data: &[u8; 16]) -> u16 {
+        self.buckets_lo.bitmask_16b(data) | self.buckets_hi.bitmask_16b(data)
+    }
+
+    /// Split 16-bit nibble tables (bucket `i` in bit `i`) into the two pairs.
+    pub const fn from_buckets(low_tab: &[u16; 16], high_tab: &[u16; 16], bit_mask: u16) -> Self {
+        let mut table = WideShuftiTable {
+            buckets_lo: ShuftiTable {
+                low_tab: [0; 16],
+                high_tab: [0; 16],
+                bit_mask: bit_mask as u8,
+            },
+            buckets_hi: ShuftiTable {
+                low_tab: [0; 16],
+                high_tab: [0; 16],
+                bit_mask: (bit_mask >> 8) as u8,
+            },
+        };
+        let mut i = 0;
+        while i < 16 {
+            table.buckets_lo.low_tab[i] = low_tab[i] as u8;
+            table.buckets_lo.high_tab[i] = high_tab[i] as u8;
+            table.buckets_hi.low_tab[i] = (low_tab[i] >> 8) as u8;
+            table.buckets_hi.high_tab[i] = (high_tab[i] >> 8) as u8;
+            i += 1;
+        }
+        table
+    }
+
+    /// 16-bit bucket mask of `b`; 0 when `b` is not in the set.
+    #[inline(always)]
+    pub const fn bucket_of(&self, b: u8) -> u16 {
+        self.buckets_lo.bucket_of(b) as u16 | (self.buckets_hi.bucket_of(b) as u16) << 8
+    }
+}
+
+/// A truffle table: represents any byte set exactly with one lookup per
+/// nibble, for sets that do not fit shufti's buckets.
+///
+/// Bit `h & 7` of `mask_lo[l]` (when `h < 8`) or `mask_hi[l]` (when `h >= 8`)
+/// is set when byte `h << 4 | l` is a member. [`Finder`] and the derive
+/// select it for sets that need more than 16 buckets.
+#[derive(Debug, Clone, Copy)]
+pub struct TruffleTable {
+    /// Rows for bytes `0x00..0x80`, indexed by low nibble.
+    pub mask_lo: [u8; 16],
+    /// Rows for bytes `0x80..=0xff`, indexed by low nibble.
+    pub mask_hi: [u8; 16],
+}
+
+impl TruffleTable {
+    /// Build the table for `set`; duplicates are ignored.
+    pub fn new(set: &[u8]) -> Self {
+        let (mask_lo, mask_hi) = shufti_core::build_truffle_masks(set);
+        TruffleTable { mask_lo, mask_hi }
+    }
+
+    /// The table for the bytes set in a [`ShuftiMatch::BITMAP`]-style
+    /// membership bitmap, in `const` contexts.
+    pub const fn from_bitmap(bitmap: &[u64; 4]) -> Self {
+        let mut table = TruffleTable {
+            mask_lo: [0; 16],
+            mask_hi: [0; 16],
+        };
+        let mut b = 0;
+        while b < 256 {
+            if bitmap[b >> 6] & (1 << (b & 63)) != 0 {
+                let rows = if b & 0x80 == 0 {
+                    &mut table.mask_lo
+                } else {
+                    &mut table.mask_hi
+                };
+                rows[b & 0x0f] |= 1 << ((b >> 4) & 7);
+            }
+            b += 1;
+        }
+        table
+    }
+
+    /// Whether `b` is in the set.
+    #[inline(always)]
+    pub const fn is_member(&self, b: u8) -> bool {
+        let rows = if b & 0x80 == 0 {
+            &self.mask_lo
+        } else {
+            &self.mask_hi
+        };
+        rows[(b & 0x0f) as usize] & (1 << ((b >> 4) & 7)) != 0
+    }
+
+    /// Test a 16-byte chunk: bit *i* of the result is set when `data[i]` is a
+    /// member.
+    ///
+    /// # Safety
+    /// Same requirements as [`ShuftiTable::bitmask_16b`].
+    #[cfg(not(feature = "no-unsafe"))]
+    #[inline(always)]
+    pub unsafe fn bitmask_16b(&self, data: &[u8; 16]) -> u16 {
+        unsafe { crate::arch::truffle_16b(&self.mask_lo, &self.mask_hi, data) }
+    }
+
+    /// Safe [`bitmask_16b`](Self::bitmask_16b) (feature `no-unsafe`).
+    #[cfg(feature = "no-unsafe")]
+    #[inline(always)]
+    pub fn bitmask_16b(&self, data: &[u8; 16]) -> u16 {
+        crate::arch::truffle_16b(&self.mask_lo, &self.mask_hi, data)
+    }
+
+    /// Search `haystack` for the first byte that belongs to the set.
+    pub fn find_first<H: Haystack + ?Sized>(&self, haystack: &H) -> Option<usize> {
+        search::find_from(haystack.as_bytes(), 0, |c: &[u8; 16]| {
+            kernel!(self.bitmask_16b(c))
+        })
+    }
+
+    /// Iterate the positions of every byte of `haystack` in the set.
+    pub fn find_iter<'a, H: Haystack + ?Sized>(&'a self, haystack: &'a H) -> TruffleMatches<'a> {
+        TruffleMatches::new(self, haystack.as_bytes())
+    }
 }
 
+/// Iterator returned by [`ShuftiMatch::chunk_masks`].
+pub type ChunkMasks<'a> = core::iter::Map<core::slice::Iter<'a, [u8; 16]>, fn(&[u8; 16]) -> Mask16>;
+
 // ---------------------------------------------------------------------------
 // ShuftiMatcher trait
 // ---------------------------------------------------------------------------
@@ -60,156 +561,2270 @@ impl ShuftiTable {
 /// - [`match_16b`](Self::match_16b) – single 16-byte chunk, returns position bitmask.
 /// - [`find_first`](Self::find_first) – searches an arbitrary byte slice.
 pub trait ShuftiMatch {
-    /// The literal set string provided to `#[shufti(set = "...")]`.
+    /// The literal set string provided to `#[shufti(set = "...")]`. A byte
+    /// string set (`set = b"..."`) is spelled with each byte `>= 0x80` as the
+    /// Latin-1 `char` of the same code point; see [`SET_BYTES`](Self::SET_BYTES).
     const SET: &'static str;
+    /// The set's bytes as written: the bytes of `set = b"..."`, or of
+    /// [`SET`](Self::SET) for a string set.
+    const SET_BYTES: &'static [u8] = Self::SET.as_bytes();
     /// Number of distinct bytes in the set.
     const NEEDLE_COUNT: usize;
+    /// Bucket bits of every byte value, exact membership included: an entry
+    /// is zero iff the byte is not in the set. For an exact 8-bucket
+    /// [`table`](Self::table) this is its [`ShuftiTable::bucket_map`]; an
+    /// [`APPROXIMATE`](Self::APPROXIMATE) table's false positives are cleared
+    /// by [`BITMAP`](Self::BITMAP), and the [`WIDE`](Self::WIDE) and
+    /// [`TRUFFLE`](Self::TRUFFLE) kernels put every member in bucket 1.
+    const BUCKET_OF: [u8; 256];
+
+    /// 256-bit membership bitmap: bit `b & 63` of word `b >> 6` is set when
+    /// `b` is in the set. The derive emits it as a literal.
+    const BITMAP: [u64; 4] = bitmap_of(&Self::BUCKET_OF);
+
+    /// The one or two member bytes (repeated for a single byte) when the
+    /// set is small enough for a plain `cmpeq` loop, which beats the double
+    /// table lookup. [`match_16b`](Self::match_16b) and
+    /// [`first_match_16b`](Self::first_match_16b) then compare against them
+    /// instead of using [`table`](Self::table). The derive sets it for sets
+    /// of at most two bytes.
+    const CMPEQ: Option<[u8; 2]> = None;
+
+    /// 16-bucket table for sets the derive could not pack into 8 buckets
+    /// but could into 16. When set, [`match_16b`](Self::match_16b) uses it,
+    /// and [`table`](Self::table) returns an empty table.
+    const WIDE: Option<WideShuftiTable> = None;
+
+    /// Truffle table for sets the derive could not pack into 16 buckets. When
+    /// set, [`match_16b`](Self::match_16b) uses it, and [`table`](Self::table)
+    /// returns an empty table (it cannot represent the set).
+    const TRUFFLE: Option<TruffleTable> = None;
+
+    /// Whether [`table`](Self::table) over-approximates the set: it may
+    /// accept bytes outside it, and [`match_16b`](Self::match_16b) drops
+    /// each candidate whose [`BITMAP`](Self::BITMAP) bit is clear. Set by
+    /// `#[shufti(approximate)]` for sets that do not pack into 8 buckets, as
+    /// an alternative to the wide or truffle kernel that stays fast while
+    /// candidates are rare.
+    const APPROXIMATE: bool = false;
+
+    /// The algorithm the chunk kernels run, from [`CMPEQ`](Self::CMPEQ),
+    /// [`WIDE`](Self::WIDE) and [`TRUFFLE`](Self::TRUFFLE). The derive picks
+    /// it with the same rules as [`Finder`] (see [`Algorithm`]), so every set
+    /// compiles.
+    const ALGORITHM: Algorithm = if Self::CMPEQ.is_some() {
+        Algorithm::CmpEq
+    } else if Self::WIDE.is_some() {
+        Algorithm::WideShufti
+    } else if Self::TRUFFLE.is_some() {
+        Algorithm::Truffle
+    } else {
+        Algorithm::Shufti
+    };
 
     /// Return the precomputed lookup tables for this matcher.
     fn table() -> ShuftiTable;
 
-    /// Test exactly 16 bytes. Returns a `u16` bitmask: bit *i* is 1 when
-    /// `chunk[i]` belongs to the set. Wraps [`ShuftiTable::test_chunk`].
+    /// Whether `b` is in the set, by a scalar [`BITMAP`](Self::BITMAP) test
+    /// rather than the SIMD table. Derived matchers also get an inherent
+    /// `const fn is_member`.
+    #[inline(always)]
+    fn is_member(b: u8) -> bool {
+        Self::BITMAP[(b >> 6) as usize] & (1 << (b & 63)) != 0
+    }
+
+    /// Bucket bits assigned to `b`; 0 when `b` is not in the set.
+    #[inline(always)]
+    fn bucket_of(b: u8) -> u8 {
+        Self::BUCKET_OF[b as usize]
+    }
+
+    /// Test exactly 16 bytes; lane *i* of the mask is set when `chunk[i]`
+    /// belongs to the set. Wraps [`ShuftiTable::test_chunk`].
     ///
     /// # Safety
     /// On AArch64 the underlying NEON intrinsics are used directly. Callers
     /// on other architectures get the scalar fallback automatically.
     #[inline(always)]
-    fn match_16b(chunk: &[u8; 16]) -> u16 {
-        // SAFETY: test_chunk is always safe on the current platform;
-        // the unsafe block is required only because of the aarch64 intrinsics.
-        unsafe { Self::table().bitmask_16b(chunk) }
+    fn match_16b(chunk: &[u8; 16]) -> Mask16 {
+        if let Some([a, b]) = Self::CMPEQ {
+            return Mask16(kernel!(arch::cmpeq_16b(a, b, chunk)));
+        }
+        if let Some(wide) = Self::WIDE {
+            return Mask16(kernel!(wide.bitmask_16b(chunk)));
+        }
+        if let Some(truffle) = Self::TRUFFLE {
+            return Mask16(kernel!(truffle.bitmask_16b(chunk)));
+        }
+        let mask = kernel!(Self::table().bitmask_16b(chunk));
+        if Self::APPROXIMATE {
+            return Mask16(verify_candidates(mask, chunk, &Self::BITMAP));
+        }
+        Mask16(mask)
+    }
+
+    /// Test exactly 32 bytes; lane *i* of the mask is set when `chunk[i]`
+    /// belongs to the set. Wraps [`ShuftiTable::bitmask_32b`].
+    #[inline(always)]
+    fn match_32b(chunk: &[u8; 32]) -> Mask32 {
+        if Self::ALGORITHM != Algorithm::Shufti || Self::APPROXIMATE {
+            let mut mask = 0;
+            for (i, c) in chunk.as_chunks::<16>().0.iter().enumerate() {
+                mask |= (raw_16b::<Self>(c) as u32) << (16 * i);
+            }
+            return Mask32(mask);
+        }
+        Mask32(kernel!(Self::table().bitmask_32b(chunk)))
+    }
+
+    /// Test exactly 64 bytes; lane *i* of the mask is set when `chunk[i]`
+    /// belongs to the set. Wraps [`ShuftiTable::bitmask_64b`].
+    #[inline(always)]
+    fn match_64b(chunk: &[u8; 64]) -> Mask64 {
+        if Self::ALGORITHM != Algorithm::Shufti || Self::APPROXIMATE {
+            let mut mask = 0;
+            for (i, c) in chunk.as_chunks::<16>().0.iter().enumerate() {
+                mask |= (raw_16b::<Self>(c) as u64) << (16 * i);
+            }
+            return Mask64(mask);
+        }
+        Mask64(kernel!(Self::table().bitmask_64b(chunk)))
+    }
+
+    /// Index of the first member lane of `chunk`, or `None`. Wraps
+    /// [`ShuftiTable::first_match_16b`].
+    #[inline(always)]
+    fn first_match_16b(chunk: &[u8; 16]) -> Option<u32> {
+        if Self::ALGORITHM != Algorithm::Shufti || Self::APPROXIMATE {
+            let mask = raw_16b::<Self>(chunk);
+            return (mask != 0).then(|| mask.trailing_zeros());
+        }
+        kernel!(Self::table().first_match_16b(chunk))
     }
 
     /// Search `haystack` for the first byte that belongs to the set.
     ///
-    /// Processes the input in 16-byte chunks using [`match_16b`](Self::match_16b),
-    /// then handles the remaining epilogue byte-by-byte with a scalar fallback.
-    fn find_first(haystack: &[u8]) -> Option<usize> {
-        let table = Self::table();
-        let mut offset = 0usize;
-
-        // --- 16-byte fast path ---
-        while offset + 16 <= haystack.len() {
-            // SAFETY: we've checked there are ≥16 bytes available.
-            let chunk: &[u8; 16] = unsafe { &*(haystack.as_ptr().add(offset) as *const [u8; 16]) };
-            let mask = unsafe { table.bitmask_16b(chunk) } as u32;
-            if mask != 0 {
-                return Some(offset + mask.trailing_zeros() as usize);
-            }
-            offset += 16;
-        }
-
-        // --- Scalar epilogue ---
-        // Load the remaining bytes into a zeroed 16-byte buffer so we can
-        // still use the SIMD path (zeros never match real needles unless '\0'
-        // is in the set — handled below via position-clamped output).
-        let remainder = haystack.len() - offset;
-        if remainder > 0 {
-            let mut buf = [0u8; 16];
-            buf[..remainder].copy_from_slice(&haystack[offset..]);
-            let mask = unsafe { table.bitmask_16b(&buf) } as u32;
-            if mask != 0 {
-                let pos = mask.trailing_zeros() as usize;
-                if pos < remainder {
-                    return Some(offset + pos);
-                }
-                // pos >= remainder means only the zero-padding matched '\0'
-                // If '\0' is actually a needle we need the scalar check:
-                for i in 0..remainder {
-                    if mask & (1 << i) != 0 {
-                        return Some(offset + i);
-                    }
-                }
-            }
+    /// Full chunks go through [`first_match_16b`](Self::first_match_16b), the
+    /// rest through [`match_16b`](Self::match_16b).
+    /// The epilogue is loaded into a zero-padded buffer so it still takes the
+    /// SIMD path; padding lanes are masked off, so a `\0` needle is handled
+    /// correctly.
+    ///
+    /// With the `memchr-fallback` feature, sets of 1–3 bytes go through
+    /// `memchr` instead when no SIMD backend is available.
+    fn find_first<H: Haystack + ?Sized>(haystack: &H) -> Option<usize> {
+        let haystack = haystack.as_bytes();
+        #[cfg(feature = "memchr-fallback")]
+        if matches!(arch::BACKEND, Backend::Scalar) && (1..=3).contains(&Self::NEEDLE_COUNT) {
+            let (needles, len) = const { first_members(&Self::BITMAP) };
+            return search::memchr_find(needles, len, haystack);
         }
+        search::find_first_lane(haystack, Self::first_match_16b, raw_16b::<Self>)
+    }
 
-        None
+    /// [`find_first`](Self::find_first) together with the bucket bits of the
+    /// matched byte (see [`bucket_of`](Self::bucket_of)), so callers that give
+    /// buckets a meaning can classify the hit without a second table.
+    fn find_first_with_bucket<H: Haystack + ?Sized>(haystack: &H) -> Option<(usize, u8)> {
+        let haystack = haystack.as_bytes();
+        let pos = Self::find_first(haystack)?;
+        Some((pos, Self::bucket_of(*haystack.get(pos)?)))
     }
-}
 
-// ---------------------------------------------------------------------------
-// Tests
-// ---------------------------------------------------------------------------
+    /// Position of the first byte of this set that is immediately followed by
+    /// a byte of `B`'s set (double shufti), e.g. `\r` then `\n`, or `:` then
+    /// a space. Pairs spanning a chunk boundary are found too.
+    fn find_followed_by<B, H>(haystack: &H) -> Option<usize>
+    where
+        B: ShuftiMatch + ?Sized,
+        H: Haystack + ?Sized,
+    {
+        search::find_pair(haystack.as_bytes(), raw_16b::<Self>, raw_16b::<B>)
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Absolute position of the first match at or after `start`, for resuming
+    /// a search without re-slicing. The scan begins at `start` directly, so
+    /// the head need not be 16-byte aligned. Returns `None` when `start` is
+    /// past the end.
+    fn find_from<H: Haystack + ?Sized>(haystack: &H, start: usize) -> Option<usize> {
+        search::find_from(haystack.as_bytes(), start, raw_16b::<Self>)
+    }
 
-    /// Minimal hand-rolled implementation for testing without the macro.
-    struct WsMatcher;
-    impl ShuftiMatch for WsMatcher {
-        const SET: &'static str = "\t\r\n";
-        const NEEDLE_COUNT: usize = 3;
-        fn table() -> ShuftiTable {
-            // build_shufti_fast equivalent for [0x09, 0x0d, 0x0a]
-            let needles: &[u8] = b"\t\r\n";
-            let mut low_tab = [0u8; 16];
-            let mut high_tab = [0u8; 16];
-            for (i, &b) in needles.iter().enumerate() {
-                let bit = 1u8 << i;
-                low_tab[(b & 0x0f) as usize] |= bit;
-                high_tab[(b >> 4) as usize] |= bit;
+    /// Absolute position of the first match inside `range`, e.g. one record
+    /// already located in a larger buffer. Nothing outside `range` is read,
+    /// including by the padded epilogue. Returns `None` for an out-of-bounds
+    /// or reversed range.
+    fn find_in<H: Haystack + ?Sized>(haystack: &H, range: Range<usize>) -> Option<usize> {
+        let window = haystack.as_bytes().get(range.clone())?;
+        search::find_from(window, 0, raw_16b::<Self>).map(|pos| range.start + pos)
+    }
+
+    /// Whether `haystack` contains any byte of the set; stops at the first.
+    fn contains_any<H: Haystack + ?Sized>(haystack: &H) -> bool {
+        Self::find_first(haystack).is_some()
+    }
+
+    /// Whether `haystack` contains no byte of the set; stops at the first.
+    fn none_in_set<H: Haystack + ?Sized>(haystack: &H) -> bool {
+        !Self::contains_any(haystack)
+    }
+
+    /// Whether every byte of `haystack` is in the set (e.g. "is this header
+    /// value all token characters?"). Always scans the whole input. `true`
+    /// for an empty haystack.
+    fn all_in_set<H: Haystack + ?Sized>(haystack: &H) -> bool {
+        search::all(haystack.as_bytes(), raw_16b::<Self>)
+    }
+
+    /// First match in a sequence of buffers searched as one logical haystack
+    /// (e.g. the `IoSlice`s of a vectored read), as `(buffer_index, offset)`
+    /// within that buffer. Empty buffers are skipped.
+    fn find_first_vectored<B: Haystack>(bufs: &[B]) -> Option<(usize, usize)> {
+        bufs.iter()
+            .enumerate()
+            .find_map(|(i, buf)| Some((i, Self::find_first(buf)?)))
+    }
+
+    /// [`find_first`](Self::find_first) over a `str`, returning only positions
+    /// on a character boundary, so `&text[pos..]` never panics. Matches on
+    /// UTF-8 continuation bytes (possible only with non-ASCII sets) are
+    /// skipped.
+    fn find_first_str(text: &str) -> Option<usize> {
+        Self::find_iter_str(text).next().map(|(pos, _)| pos)
+    }
+
+    /// Iterate `(byte_index, char)` for every match of `text` that lies on a
+    /// character boundary; the `char` is the one starting there.
+    fn find_iter_str(text: &str) -> StrMatches<'_, Self> {
+        StrMatches::new(text)
+    }
+
+    /// Position of the first byte of `haystack` that is *not* in the set, e.g.
+    /// the end of leading whitespace.
+    fn find_first_not<H: Haystack + ?Sized>(haystack: &H) -> Option<usize> {
+        search::find_from(haystack.as_bytes(), 0, |c: &[u8; 16]| !raw_16b::<Self>(c))
+    }
+
+    /// Position of the last byte of `haystack` that is *not* in the set, e.g.
+    /// where trailing whitespace starts (exclusive).
+    fn find_last_not<H: Haystack + ?Sized>(haystack: &H) -> Option<usize> {
+        search::rfind(haystack.as_bytes(), |c: &[u8; 16]| !raw_16b::<Self>(c))
+    }
+
+    /// Length of the leading run of set bytes, like C's `strspn`.
+    fn prefix_len<H: Haystack + ?Sized>(haystack: &H) -> usize {
+        let haystack = haystack.as_bytes();
+        Self::find_first_not(haystack).unwrap_or(haystack.len())
+    }
+
+    /// The rest of `haystack` after its leading run of set bytes, e.g. to
+    /// consume whitespace in a lexer.
+    fn skip_prefix<H: Haystack + ?Sized>(haystack: &H) -> &[u8] {
+        let haystack = haystack.as_bytes();
+        haystack
+            .get(Self::prefix_len(haystack)..)
+            .unwrap_or_default()
+    }
+
+    /// Length of the trailing run of set bytes, scanning backwards from the
+    /// end.
+    fn suffix_len<H: Haystack + ?Sized>(haystack: &H) -> usize {
+        let haystack = haystack.as_bytes();
+        Self::find_last_not(haystack).map_or(haystack.len(), |pos| haystack.len() - pos - 1)
+    }
+
+    /// `haystack` without its leading run of set bytes.
+    fn trim_start<H: Haystack + ?Sized>(haystack: &H) -> &[u8] {
+        Self::skip_prefix(haystack)
+    }
+
+    /// `haystack` without its trailing run of set bytes.
+    fn trim_end<H: Haystack + ?Sized>(haystack: &H) -> &[u8] {
+        let haystack = haystack.as_bytes();
+        let end = haystack.len().saturating_sub(Self::suffix_len(haystack));
+        haystack.get(..end).unwrap_or_default()
+    }
+
+    /// `haystack` without its leading and trailing runs of set bytes.
+    fn trim<H: Haystack + ?Sized>(haystack: &H) -> &[u8] {
+        Self::trim_end(Self::trim_start(haystack))
+    }
+
+    /// Split `haystack` around its first match into the bytes before it, the
+    /// matched byte, and the bytes after it.
+    fn split_at_first_match<H: Haystack + ?Sized>(haystack: &H) -> Option<(&[u8], u8, &[u8])> {
+        let haystack = haystack.as_bytes();
+        let pos = Self::find_first(haystack)?;
+        let (before, rest) = haystack.split_at_checked(pos)?;
+        let (&byte, after) = rest.split_first()?;
+        Some((before, byte, after))
+    }
+
+    /// Replace every maximal run of set bytes with a single `with` byte (e.g.
+    /// whitespace normalization). Borrows `haystack` when nothing changes.
+    fn squeeze<H: Haystack + ?Sized>(haystack: &H, with: u8) -> std::borrow::Cow<'_, [u8]> {
+        search::squeeze(haystack.as_bytes(), with, raw_16b::<Self>)
+    }
+
+    /// Whether `a` and `b` are equal once every set byte is removed from both,
+    /// e.g. whitespace-insensitive comparison of config lines. Ignored runs
+    /// are skipped on each side independently; nothing is allocated.
+    fn eq_ignoring_set<A, B>(a: &A, b: &B) -> bool
+    where
+        A: Haystack + ?Sized,
+        B: Haystack + ?Sized,
+    {
+        let (a, b) = (a.as_bytes(), b.as_bytes());
+        let (mut gaps_a, mut gaps_b) = (Self::gap_runs(a), Self::gap_runs(b));
+        let (mut left, mut right): (&[u8], &[u8]) = (&[], &[]);
+        loop {
+            if left.is_empty() {
+                left = gaps_a.next().map_or(&[], |r| &a[r]);
+            }
+            if right.is_empty() {
+                right = gaps_b.next().map_or(&[], |r| &b[r]);
             }
-            ShuftiTable {
-                low_tab,
-                high_tab,
-                bit_mask: (1u32 << needles.len()).wrapping_sub(1) as u8,
+            // Gaps are never empty, so an empty side is exhausted.
+            let n = left.len().min(right.len());
+            if n == 0 {
+                return left.len() == right.len();
             }
+            if left[..n] != right[..n] {
+                return false;
+            }
+            (left, right) = (&left[n..], &right[n..]);
         }
     }
 
-    #[test]
-    fn test_match_16b_none() {
-        let chunk = *b"abcdefghijklmnop";
-        assert_eq!(WsMatcher::match_16b(&chunk), 0);
+    /// Copy `haystack` with every set byte replaced by `replacement` (a byte
+    /// string, possibly empty or longer than one byte), e.g. control
+    /// characters with `?`. Spans without matches are copied wholesale.
+    fn replace_all<H: Haystack + ?Sized>(haystack: &H, replacement: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        search::rewrite_into(haystack.as_bytes(), &mut out, raw_16b::<Self>, |_, out| {
+            out.extend_from_slice(replacement)
+        });
+        out
     }
 
-    #[test]
-    fn test_match_16b_first() {
-        let chunk = *b"\tbcdefghijklmnop";
-        let mask = WsMatcher::match_16b(&chunk);
-        assert_eq!(mask.trailing_zeros(), 0);
+    /// Append `haystack` to `out`, bulk-copying the clean spans and calling
+    /// `escape(byte, out)` for each set byte to write its escaped form. A
+    /// general engine for JSON, CSV or shell quoting.
+    fn escape_into<H, F>(haystack: &H, out: &mut Vec<u8>, escape: F)
+    where
+        H: Haystack + ?Sized,
+        F: FnMut(u8, &mut Vec<u8>),
+    {
+        search::rewrite_into(haystack.as_bytes(), out, raw_16b::<Self>, escape)
     }
 
-    #[test]
-    fn test_match_16b_last() {
-        let chunk = *b"abcdefghijklmno\n";
-        let mask = WsMatcher::match_16b(&chunk);
-        assert_eq!(mask.trailing_zeros(), 15);
+    /// Copy `haystack` without its set bytes, e.g. to strip whitespace from
+    /// a base64 blob. Each chunk is compacted with a byte shuffle rather than
+    /// copied byte by byte.
+    fn remove_all<H: Haystack + ?Sized>(haystack: &H) -> Vec<u8> {
+        let mut out = Vec::new();
+        search::remove_into(haystack.as_bytes(), &mut out, raw_16b::<Self>);
+        out
     }
 
-    #[test]
-    fn test_find_first_empty() {
-        assert_eq!(WsMatcher::find_first(b""), None);
+    /// In-place [`remove_all`](Self::remove_all).
+    fn remove_all_in_place(buf: &mut Vec<u8>) {
+        search::remove_in_place(buf, raw_16b::<Self>)
     }
 
-    #[test]
-    fn test_find_first_no_match() {
-        assert_eq!(WsMatcher::find_first(b"hello world"), None);
+    /// Append `haystack` to `out` with every maximal run of set bytes
+    /// collapsed to the single byte `with` (`"foo \t bar"` → `"foo bar"`).
+    /// The streaming counterpart of [`squeeze`](Self::squeeze) for callers
+    /// that reuse an output buffer.
+    fn collapse_runs<H: Haystack + ?Sized>(haystack: &H, with: u8, out: &mut Vec<u8>) {
+        search::collapse_into(haystack.as_bytes(), out, with, raw_16b::<Self>)
     }
 
-    #[test]
-    fn test_find_first_exact_16() {
-        let hay = b"abcdefghijklmno\n";
-        assert_eq!(WsMatcher::find_first(hay), Some(15));
+    /// In-place [`squeeze`](Self::squeeze).
+    fn squeeze_in_place(buf: &mut Vec<u8>, with: u8) {
+        search::squeeze_in_place(buf, with, raw_16b::<Self>)
     }
 
-    #[test]
-    fn test_find_first_crosses_chunk_boundary() {
-        let hay = b"abcdefghijklmnopqrs\tuvwxyz";
-        assert_eq!(WsMatcher::find_first(hay), Some(19));
+    /// Number of matches in `haystack`, in one streaming pass summing the
+    /// popcounts of the chunk masks.
+    fn count<H: Haystack + ?Sized>(haystack: &H) -> usize {
+        search::count(haystack.as_bytes(), raw_16b::<Self>)
     }
 
-    #[test]
-    fn test_find_first_epilogue_only() {
-        let hay = b"abcde\r";
-        assert_eq!(WsMatcher::find_first(hay), Some(5));
+    /// Position of the `n`th match (counting from 0), e.g. the fifth comma of
+    /// a record is `find_nth(record, 4)`. Single pass, no allocation.
+    fn find_nth<H: Haystack + ?Sized>(haystack: &H, n: usize) -> Option<usize> {
+        search::find_nth(haystack.as_bytes(), n, raw_16b::<Self>)
     }
 
-    #[test]
-    fn test_find_first_first_byte() {
-        let hay = b"\nhello";
-        assert_eq!(WsMatcher::find_first(hay), Some(0));
+    /// Byte range of field `k` (counting from 0) when `haystack` is split at
+    /// set bytes, e.g. one CSV column; `None` when there are fewer than
+    /// `k + 1` fields. Earlier fields are skipped by popcount, not split.
+    fn field_range<H: Haystack + ?Sized>(haystack: &H, k: usize) -> Option<Range<usize>> {
+        let haystack = haystack.as_bytes();
+        let start = match k.checked_sub(1) {
+            None => 0,
+            Some(n) => Self::find_nth(haystack, n)? + 1,
+        };
+        let end = Self::find_from(haystack, start).unwrap_or(haystack.len());
+        Some(start..end)
+    }
+
+    /// Write the positions of the first `out.len()` matches into `out` and
+    /// return how many were found, without allocating. Scanning stops as soon
+    /// as `out` is full.
+    fn find_n_into<H: Haystack + ?Sized>(haystack: &H, out: &mut [usize]) -> usize {
+        search::find_n_into(haystack.as_bytes(), out, raw_16b::<Self>)
+    }
+
+    /// Call `visit(offset, mask)` for every 64-byte block of `haystack`, where
+    /// bit *i* of `mask` is set when `haystack[offset + i]` matches, until it
+    /// returns [`ControlFlow::Break`]. The last block may be short; its lanes
+    /// past the end are clear. Building block on the SIMD loop for custom
+    /// scanners such as quote trackers.
+    ///
+    /// [`ControlFlow::Break`]: std::ops::ControlFlow::Break
+    fn for_each_chunk<H, B, F>(haystack: &H, visit: F) -> std::ops::ControlFlow<B>
+    where
+        H: Haystack + ?Sized,
+        F: FnMut(usize, u64) -> std::ops::ControlFlow<B>,
+    {
+        search::for_each_block(haystack.as_bytes(), raw_16b::<Self>, visit)
+    }
+
+    /// Append the position of every match in `haystack` to `out`.
+    fn positions_into<H: Haystack + ?Sized>(haystack: &H, out: &mut Vec<usize>) {
+        search::positions_into(haystack.as_bytes(), out, raw_16b::<Self>)
+    }
+
+    /// Append the value of every matching byte of `haystack` to `out`, in
+    /// order (e.g. to see which delimiters a file actually uses).
+    fn collect_matched_bytes<H: Haystack + ?Sized>(haystack: &H, out: &mut Vec<u8>) {
+        search::matched_bytes_into(haystack.as_bytes(), out, raw_16b::<Self>)
+    }
+
+    /// Number of matches in each `block_size`-byte block of `haystack`; the
+    /// last block may be short. Returns an empty vector when `block_size` is 0.
+    fn block_counts<H: Haystack + ?Sized>(haystack: &H, block_size: usize) -> Vec<u32> {
+        search::block_counts(haystack.as_bytes(), block_size, raw_16b::<Self>)
+    }
+
+    /// Cumulative [`block_counts`](Self::block_counts): entry `i` is the number
+    /// of matches before block `i`, and the last entry is the total. The count
+    /// before any offset `x` is then entry `x / block_size` plus the matches in
+    /// `haystack[x - x % block_size..x]`, and the `n`th match lives in the block
+    /// found by binary search for `n`.
+    fn block_prefix_counts<H: Haystack + ?Sized>(haystack: &H, block_size: usize) -> Vec<u32> {
+        let haystack = haystack.as_bytes();
+        let mut total = 0;
+        let mut prefix = vec![0];
+        for count in Self::block_counts(haystack, block_size) {
+            total += count;
+            prefix.push(total);
+        }
+        prefix
+    }
+
+    /// [`find_first`](Self::find_first) over `haystack[start..]` that gives up
+    /// once `deadline` has passed. The deadline is checked every 16 KiB.
+    ///
+    /// Returns the absolute position of the first match, or [`TimedOut`] with
+    /// the offset to resume from.
+    fn find_first_until<H: Haystack + ?Sized>(
+        haystack: &H,
+        start: usize,
+        deadline: std::time::Instant,
+    ) -> Result<Option<usize>, TimedOut> {
+        let haystack = haystack.as_bytes();
+        let mut found = None;
+        deadline::scan_until(
+            haystack,
+            start,
+            deadline,
+            raw_16b::<Self>,
+            |offset, mask| {
+                if mask != 0 {
+                    found = Some(offset + mask.trailing_zeros() as usize);
+                }
+                found.is_some()
+            },
+        )?;
+        Ok(found)
+    }
+
+    /// [`find_first`](Self::find_first) that scans at most the first `limit`
+    /// bytes, to cap the work spent on untrusted input. Unlike searching
+    /// `&haystack[..limit]`, a miss inside the limit is told apart from a miss
+    /// in the whole haystack: the former is [`LimitReached`].
+    fn find_first_within<H: Haystack + ?Sized>(
+        haystack: &H,
+        limit: usize,
+    ) -> Result<Option<usize>, LimitReached> {
+        let haystack = haystack.as_bytes();
+        match haystack.get(..limit) {
+            Some(window) if window.len() < haystack.len() => Self::find_first(window)
+                .map(Some)
+                .ok_or(LimitReached { resume_at: limit }),
+            _ => Ok(Self::find_first(haystack)),
+        }
+    }
+
+    /// [`positions_into`](Self::positions_into) over `haystack[start..]` that
+    /// gives up once `deadline` has passed. Positions found before the deadline
+    /// stay in `out`; resume with [`TimedOut::resume_at`] to continue.
+    fn positions_into_until<H: Haystack + ?Sized>(
+        haystack: &H,
+        start: usize,
+        out: &mut Vec<usize>,
+        deadline: std::time::Instant,
+    ) -> Result<(), TimedOut> {
+        let haystack = haystack.as_bytes();
+        deadline::scan_until(
+            haystack,
+            start,
+            deadline,
+            raw_16b::<Self>,
+            |offset, mask| {
+                search::push_positions(out, offset, mask);
+                false
+            },
+        )
+    }
+
+    /// Iterate the offset of every match in `haystack`.
+    fn find_iter<H: Haystack + ?Sized>(haystack: &H) -> Matches<'_, Self> {
+        Matches::new(haystack.as_bytes())
+    }
+
+    /// Iterate the pieces of `haystack` between matches, like [`str::split`]
+    /// with every byte of the set as a delimiter. Consecutive delimiters yield
+    /// empty pieces, and an empty haystack yields one empty piece.
+    fn split<H: Haystack + ?Sized>(haystack: &H) -> Split<'_, Self> {
+        Split::new(haystack.as_bytes())
+    }
+
+    /// [`split`](Self::split) into at most `n` pieces, like [`str::splitn`]:
+    /// after `n - 1` delimiters the rest of `haystack` is yielded untouched and
+    /// never scanned. An `n` of 0 yields nothing.
+    fn splitn<H: Haystack + ?Sized>(haystack: &H, n: usize) -> SplitN<'_, Self> {
+        SplitN::new(haystack.as_bytes(), n)
+    }
+
+    /// [`split`](Self::split) where every piece keeps its delimiter, like
+    /// [`str::split_inclusive`]; concatenating the pieces gives back
+    /// `haystack`. A trailing delimiter does not produce an empty last piece.
+    fn split_inclusive<H: Haystack + ?Sized>(haystack: &H) -> SplitInclusive<'_, Self> {
+        SplitInclusive::new(haystack.as_bytes())
+    }
+
+    /// Iterate the maximal runs of bytes *outside* the set, treating every run
+    /// of set bytes as one separator, like [`str::split_whitespace`]. Never
+    /// yields an empty token.
+    fn tokens<H: Haystack + ?Sized>(haystack: &H) -> Tokens<'_, Self> {
+        Tokens::new(haystack.as_bytes())
+    }
+
+    /// Iterate the half-open ranges of the maximal runs of matching bytes,
+    /// e.g. the spans a sanitizer has to rewrite.
+    fn match_runs<H: Haystack + ?Sized>(haystack: &H) -> MatchRuns<'_, Self> {
+        MatchRuns::new(haystack.as_bytes())
+    }
+
+    /// The first maximal run of at least `k` consecutive matching bytes, e.g.
+    /// a padding block or a long whitespace gap. Shorter runs are skipped a
+    /// whole run at a time. A `k` of 0 is satisfied by the empty run at 0.
+    fn find_run<H: Haystack + ?Sized>(haystack: &H, k: usize) -> Option<Range<usize>> {
+        if k == 0 {
+            return Some(0..0);
+        }
+        Self::match_runs(haystack).find(|run| run.len() >= k)
+    }
+
+    /// Iterate the half-open ranges of the maximal runs of bytes *outside* the
+    /// set: the gaps between delimiters, with their positions.
+    fn gap_runs<H: Haystack + ?Sized>(haystack: &H) -> GapRuns<'_, Self> {
+        GapRuns::new(haystack.as_bytes())
+    }
+
+    /// Render `haystack` for a terminal with every run of matches wrapped in
+    /// the ANSI `style`; non-printable bytes are escaped.
+    fn highlight<H: Haystack + ?Sized>(haystack: &H, style: Style) -> String {
+        highlight::highlight::<Self>(haystack.as_bytes(), style)
+    }
+
+    /// Iterate every match in `haystack` with its 1-based line and column,
+    /// counting `\n` bytes on the fly.
+    fn find_iter_located<H: Haystack + ?Sized>(haystack: &H) -> LocatedMatches<'_, Self> {
+        LocatedMatches::new(haystack.as_bytes())
+    }
+
+    /// Iterate the matches at positions `phase + k * stride` only, e.g. one
+    /// column of fixed-width records. A `stride` of 0 yields nothing.
+    fn find_iter_strided<H: Haystack + ?Sized>(
+        haystack: &H,
+        stride: usize,
+        phase: usize,
+    ) -> StridedMatches<'_, Self> {
+        StridedMatches::new(haystack.as_bytes(), stride, phase)
+    }
+
+    /// First match at a position `phase + k * stride`.
+    fn find_first_strided<H: Haystack + ?Sized>(
+        haystack: &H,
+        stride: usize,
+        phase: usize,
+    ) -> Option<usize> {
+        Self::find_iter_strided(haystack, stride, phase).next()
+    }
+
+    /// Number of matches at positions `phase + k * stride`.
+    fn count_strided<H: Haystack + ?Sized>(haystack: &H, stride: usize, phase: usize) -> usize {
+        Self::find_iter_strided(haystack, stride, phase).count()
+    }
+
+    /// Iterate the offsets of matching bytes in a byte iterator (e.g. a
+    /// decompressor's output) without collecting it first. The source is
+    /// buffered 16 bytes at a time.
+    fn find_iter_source<I: IntoIterator<Item = u8>>(source: I) -> SourceMatches<I::IntoIter, Self> {
+        SourceMatches::new(source.into_iter())
+    }
+
+    /// Write a packed membership bitmap of `haystack` into `out` (replacing its
+    /// contents): bit `i % 64` of word `i / 64` is set when `haystack[i]` is in
+    /// the set.
+    fn bitmap<H: Haystack + ?Sized>(haystack: &H, out: &mut Vec<u64>) {
+        search::bitmap_into(haystack.as_bytes(), out, raw_16b::<Self>)
+    }
+
+    /// Replace the contents of `set` with one bit per byte of `haystack`: bit
+    /// `i` is set when `haystack[i]` matches. The set ends up exactly
+    /// `haystack.len()` bits long.
+    #[cfg(feature = "fixedbitset")]
+    fn fill_bitset<H: Haystack + ?Sized>(haystack: &H, set: &mut fixedbitset::FixedBitSet) {
+        let haystack = haystack.as_bytes();
+        if set.len() > haystack.len() {
+            *set = fixedbitset::FixedBitSet::with_capacity(haystack.len());
+        } else {
+            set.clear();
+            set.grow(haystack.len());
+        }
+        search::fill_usize_words(haystack, set.as_mut_slice(), raw_16b::<Self>);
+    }
+
+    /// Replace the contents of `bits` with one bit per byte of `haystack`: bit
+    /// `i` is set when `haystack[i]` matches.
+    #[cfg(feature = "bitvec")]
+    fn fill_bitvec<H: Haystack + ?Sized>(haystack: &H, bits: &mut bitvec::vec::BitVec) {
+        let haystack = haystack.as_bytes();
+        bits.clear();
+        bits.resize(haystack.len(), false);
+        search::fill_usize_words(haystack, bits.as_raw_mut_slice(), raw_16b::<Self>);
+    }
+
+    /// [`find_first`](Self::find_first) over the bytes of a C string, excluding
+    /// the terminator.
+    fn find_first_cstr(s: &core::ffi::CStr) -> Option<usize> {
+        Self::find_first(s.to_bytes())
+    }
+
+    /// [`find_first`](Self::find_first) over a NUL-terminated buffer, stopping
+    /// at the terminator without computing its length first.
+    ///
+    /// # Safety
+    /// `ptr` must point to a readable, NUL-terminated byte sequence.
+    ///
+    /// Not available with the `no-unsafe` feature.
+    #[cfg(not(feature = "no-unsafe"))]
+    unsafe fn find_first_nul_terminated(ptr: *const u8) -> Option<usize> {
+        unsafe { search::find_nul_terminated(ptr, raw_16b::<Self>) }
+    }
+
+    /// [`find_first`](Self::find_first) over `start..end`, memchr's raw-pointer
+    /// style: returns a pointer to the first match, or null when there is none
+    /// (including when `start == end`).
+    ///
+    /// # Safety
+    /// `start` and `end` must be derived from the same allocation with
+    /// `start <= end`, and every byte in between must be readable.
+    ///
+    /// Not available with the `no-unsafe` feature.
+    #[cfg(not(feature = "no-unsafe"))]
+    unsafe fn find_first_raw(start: *const u8, end: *const u8) -> *const u8 {
+        // SAFETY: the caller guarantees `start..end` is one readable range.
+        let haystack =
+            unsafe { core::slice::from_raw_parts(start, end.offset_from_unsigned(start)) };
+        match Self::find_first(haystack) {
+            Some(pos) => haystack[pos..].as_ptr(),
+            None => core::ptr::null(),
+        }
+    }
+
+    /// [`find_first`](Self::find_first) over data already laid out as 16-byte
+    /// blocks. Returns the absolute offset into the flattened blocks; there is
+    /// no prologue or epilogue to handle.
+    fn search_chunks(chunks: &[[u8; 16]]) -> Option<usize> {
+        chunks.iter().enumerate().find_map(|(i, chunk)| {
+            let mask = raw_16b::<Self>(chunk);
+            (mask != 0).then(|| i * 16 + mask.trailing_zeros() as usize)
+        })
+    }
+
+    /// Per-block [`match_16b`](Self::match_16b) masks of `chunks`, in order.
+    fn chunk_masks(chunks: &[[u8; 16]]) -> ChunkMasks<'_> {
+        chunks
+            .iter()
+            .map(Self::match_16b as fn(&[u8; 16]) -> Mask16)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// build_shufti_fast equivalent: one bucket per (unique, ≤8) needle.
+    pub(crate) const fn fast_table(needles: &[u8]) -> ShuftiTable {
+        let mut low_tab = [0u8; 16];
+        let mut high_tab = [0u8; 16];
+        let mut i = 0;
+        while i < needles.len() {
+            let (b, bit) = (needles[i], 1u8 << i);
+            low_tab[(b & 0x0f) as usize] |= bit;
+            high_tab[(b >> 4) as usize] |= bit;
+            i += 1;
+        }
+        ShuftiTable {
+            low_tab,
+            high_tab,
+            bit_mask: (1u32 << needles.len()).wrapping_sub(1) as u8,
+        }
+    }
+
+    /// Minimal hand-rolled implementation for testing without the macro.
+    pub(crate) struct WsMatcher;
+    impl ShuftiMatch for WsMatcher {
+        const SET: &'static str = "\t\r\n";
+        const NEEDLE_COUNT: usize = 3;
+        const BUCKET_OF: [u8; 256] = fast_table(b"\t\r\n").bucket_map();
+        fn table() -> ShuftiTable {
+            fast_table(b"\t\r\n")
+        }
+    }
+
+    #[test]
+    fn test_match_wide_chunks() {
+        let mut chunk = [b'x'; 64];
+        let lanes = [0, 15, 16, 31, 32, 47, 48, 63];
+        for i in lanes {
+            chunk[i] = b'\n';
+        }
+        let mask = WsMatcher::match_64b(&chunk);
+        assert_eq!(mask.iter_ones().collect::<Vec<_>>(), lanes);
+        let half = WsMatcher::match_32b(chunk[..32].try_into().unwrap());
+        assert_eq!(half.bits(), mask.bits() as u32);
+        assert_eq!(
+            half.positions(100).collect::<Vec<_>>(),
+            [100, 115, 116, 131]
+        );
+        assert!(WsMatcher::match_32b(&[b'x'; 32]).is_empty());
+        assert_eq!(NulMatcher::match_64b(&[0; 64]).bits(), u64::MAX);
+        assert_eq!(WsMatcher::match_16b(&[b'\n'; 16]).count(), 16);
+    }
+
+    #[test]
+    fn test_match_16b_none() {
+        let chunk = *b"abcdefghijklmnop";
+        assert!(WsMatcher::match_16b(&chunk).is_empty());
+    }
+
+    #[test]
+    fn test_match_16b_first() {
+        let chunk = *b"\tbcdefghijklmnop";
+        let mask = WsMatcher::match_16b(&chunk);
+        assert_eq!(mask.first(), Some(0));
+    }
+
+    #[test]
+    fn test_match_16b_last() {
+        let chunk = *b"abcdefghijklmno\n";
+        let mask = WsMatcher::match_16b(&chunk);
+        assert_eq!(mask.first(), Some(15));
+    }
+
+    #[test]
+    fn test_first_match_16b() {
+        let mut chunk = *b"abcdefghijklmnop";
+        assert_eq!(WsMatcher::first_match_16b(&chunk), None);
+        for lane in (0..16).rev() {
+            chunk[lane] = b'\n';
+            assert_eq!(WsMatcher::first_match_16b(&chunk), Some(lane as u32));
+            assert_eq!(WsMatcher::match_16b(&chunk).first(), Some(lane));
+        }
+    }
+
+    #[test]
+    fn test_find_first_empty() {
+        assert_eq!(WsMatcher::find_first(b""), None);
+    }
+
+    #[test]
+    fn test_find_first_no_match() {
+        assert_eq!(WsMatcher::find_first(b"hello world"), None);
+    }
+
+    #[test]
+    fn test_find_first_exact_16() {
+        let hay = b"abcdefghijklmno\n";
+        assert_eq!(WsMatcher::find_first(hay), Some(15));
+    }
+
+    #[test]
+    fn test_find_first_crosses_chunk_boundary() {
+        let hay = b"abcdefghijklmnopqrs\tuvwxyz";
+        assert_eq!(WsMatcher::find_first(hay), Some(19));
+    }
+
+    #[test]
+    fn test_find_first_epilogue_only() {
+        let hay = b"abcde\r";
+        assert_eq!(WsMatcher::find_first(hay), Some(5));
+    }
+
+    #[test]
+    fn test_find_first_first_byte() {
+        let hay = b"\nhello";
+        assert_eq!(WsMatcher::find_first(hay), Some(0));
+    }
+
+    #[test]
+    fn test_find_first_cstr() {
+        assert_eq!(WsMatcher::find_first_cstr(c"hello\nworld"), Some(5));
+        assert_eq!(WsMatcher::find_first_cstr(c"hello world"), None);
+        assert_eq!(WsMatcher::find_first_cstr(c""), None);
+    }
+
+    #[cfg(not(feature = "no-unsafe"))]
+    #[test]
+    fn test_find_first_nul_terminated() {
+        // Shift the string through every alignment and hit position.
+        #[repr(align(16))]
+        struct Aligned([u8; 96]);
+        let mut aligned = Aligned([b'a'; 96]);
+        let buf = &mut aligned.0;
+        for start in 0..16 {
+            for len in 0..48 {
+                let end = start + len;
+                buf[end] = 0;
+                buf[end + 1] = b'\t'; // past the terminator: never reported
+                let ptr = buf[start..].as_ptr();
+                assert_eq!(unsafe { WsMatcher::find_first_nul_terminated(ptr) }, None);
+                if len > 0 {
+                    buf[end - 1] = b'\r';
+                    let got = unsafe { WsMatcher::find_first_nul_terminated(ptr) };
+                    assert_eq!(got, Some(len - 1), "start {start}, len {len}");
+                    buf[end - 1] = b'a';
+                }
+                buf[end] = b'a';
+                buf[end + 1] = b'a';
+            }
+        }
+        let nul_set = NulMatcher::find_first_nul_terminated;
+        assert_eq!(unsafe { nul_set(c"abc".as_ptr().cast()) }, None);
+
+        // An allocation that ends at the terminator: a read past it would be
+        // out of bounds.
+        let exact: Box<[u8]> = Box::from(&b"aaaaaaaaaaaaaaaaa\r\0"[..]);
+        let got = unsafe { WsMatcher::find_first_nul_terminated(exact.as_ptr()) };
+        assert_eq!(got, Some(17));
+        let exact: Box<[u8]> = Box::from(&b"aaaaaaaaaaaaaaaaaa\0"[..]);
+        assert_eq!(
+            unsafe { WsMatcher::find_first_nul_terminated(exact.as_ptr()) },
+            None
+        );
+    }
+
+    struct NulMatcher;
+    impl ShuftiMatch for NulMatcher {
+        const SET: &'static str = "\0";
+        const NEEDLE_COUNT: usize = 1;
+        const BUCKET_OF: [u8; 256] = fast_table(b"\0").bucket_map();
+        fn table() -> ShuftiTable {
+            fast_table(b"\0")
+        }
+    }
+
+    #[test]
+    fn test_squeeze() {
+        use std::borrow::Cow;
+        let cases: [(&[u8], &[u8]); 5] = [
+            (b"", b""),
+            (b"a b", b"a b"),
+            (b"a\t\r\nb\n", b"a b "),
+            (
+                b"\n\n\nabcdefghijklmnopqrstu\r\n\t\r\n\t\r\n\t\r\n\t\r\n\t\r\nz",
+                b" abcdefghijklmnopqrstu z",
+            ),
+            (b"\t\t", b" "),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(&*WsMatcher::squeeze(input, b' '), expected);
+            let mut buf = input.to_vec();
+            WsMatcher::squeeze_in_place(&mut buf, b' ');
+            assert_eq!(buf, expected);
+            let mut out = b">".to_vec();
+            WsMatcher::collapse_runs(input, b' ', &mut out);
+            assert_eq!(out[1..], *expected);
+        }
+        assert!(matches!(
+            WsMatcher::squeeze(b"a\nb", b'\n'),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(WsMatcher::squeeze(b"a\tb", b'\n'), Cow::Owned(_)));
+    }
+
+    #[test]
+    fn test_deadline_scans() {
+        use std::time::{Duration, Instant};
+        let mut hay = vec![b'a'; deadline::CHECK_EVERY * 16 * 3];
+        hay[5] = b'\n';
+        let last = hay.len() - 1;
+        hay[last] = b'\t';
+
+        let later = Instant::now() + Duration::from_secs(3600);
+        assert_eq!(WsMatcher::find_first_until(&hay, 0, later), Ok(Some(5)));
+        assert_eq!(WsMatcher::find_first_until(&hay, 6, later), Ok(Some(last)));
+
+        // An expired deadline still scans the first batch of chunks.
+        let past = Instant::now();
+        let stop = deadline::CHECK_EVERY * 16 + 6;
+        assert_eq!(
+            WsMatcher::find_first_until(&hay, 6, past),
+            Err(TimedOut { resume_at: stop })
+        );
+
+        let mut out = Vec::new();
+        let mut start = 0;
+        while let Err(t) = WsMatcher::positions_into_until(&hay, start, &mut out, past) {
+            start = t.resume_at;
+        }
+        assert_eq!(out, [5, last]);
+    }
+
+    #[test]
+    fn test_count() {
+        assert_eq!(WsMatcher::count(b"a\tb\r..............\n..\t"), 4);
+        assert_eq!(WsMatcher::count(&[b'\n'; 33]), 33);
+        assert_eq!(WsMatcher::count(b""), 0);
+        assert_eq!(NulMatcher::count(b"abc"), 0);
+        assert_eq!(NulMatcher::count(&[0u8; 17]), 17);
+    }
+
+    #[test]
+    fn test_find_nth() {
+        let hay = b"a\tb\r..............\n..\t";
+        let all: Vec<usize> = WsMatcher::find_iter(hay).collect();
+        for (n, &pos) in all.iter().enumerate() {
+            assert_eq!(WsMatcher::find_nth(hay, n), Some(pos));
+        }
+        assert_eq!(WsMatcher::find_nth(hay, all.len()), None);
+        assert_eq!(WsMatcher::find_nth(hay, usize::MAX), None);
+        let dense = [b'\n'; 40];
+        assert_eq!(WsMatcher::find_nth(&dense, 33), Some(33));
+        assert_eq!(NulMatcher::find_nth(b"a\0", 1), None);
+    }
+
+    #[test]
+    fn test_field_range() {
+        let record = b"id\tname\t\t................tail";
+        let fields: Vec<_> = WsMatcher::split(record).collect();
+        for (k, field) in fields.iter().enumerate() {
+            let range = WsMatcher::field_range(record, k).unwrap();
+            assert_eq!(&record[range], *field, "field {k}");
+        }
+        assert_eq!(WsMatcher::field_range(record, 2), Some(8..8));
+        assert_eq!(WsMatcher::field_range(record, fields.len()), None);
+        assert_eq!(WsMatcher::field_range(b"", 0), Some(0..0));
+        assert_eq!(WsMatcher::field_range(b"a\n", 1), Some(2..2));
+        assert_eq!(WsMatcher::field_range(b"a\n", usize::MAX), None);
+    }
+
+    #[test]
+    fn test_find_n_into() {
+        let hay = b"a\tb\r..............\n..\t";
+        let mut out = [0; 3];
+        assert_eq!(WsMatcher::find_n_into(hay, &mut out), 3);
+        assert_eq!(out, [1, 3, 18]);
+        let mut out = [usize::MAX; 6];
+        assert_eq!(WsMatcher::find_n_into(hay, &mut out), 4);
+        assert_eq!(out, [1, 3, 18, 21, usize::MAX, usize::MAX]);
+        assert_eq!(WsMatcher::find_n_into(hay, &mut []), 0);
+        assert_eq!(NulMatcher::find_n_into(b"ab", &mut [0; 4]), 0);
+    }
+
+    #[test]
+    fn test_for_each_chunk() {
+        use std::ops::ControlFlow;
+
+        let mut hay = vec![b'x'; 150];
+        for i in [3, 63, 64, 100, 149] {
+            hay[i] = b'\n';
+        }
+        let mut blocks = Vec::new();
+        let flow = WsMatcher::for_each_chunk(&hay, |offset, mask| {
+            blocks.push((offset, mask));
+            ControlFlow::<()>::Continue(())
+        });
+        assert_eq!(flow, ControlFlow::Continue(()));
+        assert_eq!(
+            blocks,
+            [(0, 1 << 3 | 1 << 63), (64, 1 | 1 << 36), (128, 1 << 21)]
+        );
+        // Breaking stops the scan and returns the value.
+        let mut visited = 0;
+        let flow = WsMatcher::for_each_chunk(&hay, |offset, mask| {
+            visited += 1;
+            if mask >> 63 != 0 {
+                ControlFlow::Break(offset + 63)
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!((flow, visited), (ControlFlow::Break(63), 1));
+        // Padding of the short last block is never set.
+        let mut last = 0;
+        let _ = NulMatcher::for_each_chunk(&[0u8; 70], |_, mask| {
+            last = mask;
+            ControlFlow::<()>::Continue(())
+        });
+        assert_eq!(last, 0b11_1111);
+    }
+
+    #[test]
+    fn test_positions_into() {
+        let mut out = vec![99];
+        WsMatcher::positions_into(b"a\tb\r..............\n..\t", &mut out);
+        assert_eq!(out, [99, 1, 3, 18, 21]);
+    }
+
+    #[test]
+    fn test_collect_matched_bytes() {
+        let mut out = vec![b'x'];
+        WsMatcher::collect_matched_bytes(b"a\r\nb..............\t\n", &mut out);
+        assert_eq!(out, b"x\r\n\t\n");
+        let mut out = Vec::new();
+        NulMatcher::collect_matched_bytes(b"ab\0", &mut out);
+        assert_eq!(out, [0]);
+    }
+
+    #[test]
+    fn test_block_counts() {
+        let mut hay = vec![b'a'; 50];
+        for i in [0, 6, 7, 15, 16, 17, 40, 49] {
+            hay[i] = b'\n';
+        }
+        for block_size in [1, 3, 7, 16, 20, 64] {
+            let expected: Vec<u32> = hay
+                .chunks(block_size)
+                .map(|b| b.iter().filter(|&&c| c == b'\n').count() as u32)
+                .collect();
+            assert_eq!(
+                WsMatcher::block_counts(&hay, block_size),
+                expected,
+                "size {block_size}"
+            );
+        }
+        assert_eq!(WsMatcher::block_counts(&hay, 7), [2, 1, 3, 0, 0, 1, 0, 1]);
+        assert_eq!(WsMatcher::block_prefix_counts(&hay, 16), [0, 4, 6, 7, 8]);
+        assert_eq!(WsMatcher::block_prefix_counts(b"", 16), [0]);
+        assert!(WsMatcher::block_counts(&hay, 0).is_empty());
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let hay = b"a\tb\r..............\n..\t";
+        assert_eq!(
+            WsMatcher::find_iter(hay).collect::<Vec<_>>(),
+            [1, 3, 18, 21]
+        );
+        assert_eq!(WsMatcher::find_iter(b"").next(), None);
+        assert_eq!(NulMatcher::find_iter(b"abc").next(), None);
+    }
+
+    #[test]
+    fn test_find_iter_double_ended() {
+        let hay = b"a\tb\r..............\n..\t";
+        assert_eq!(
+            WsMatcher::find_iter(hay).rev().collect::<Vec<_>>(),
+            [21, 18, 3, 1]
+        );
+        assert_eq!(WsMatcher::find_iter(hay).last(), Some(21));
+        assert_eq!(WsMatcher::find_iter(b"").next_back(), None);
+        // Alternate ends and check the two halves meet exactly, at every
+        // length and for dense and sparse sets.
+        for len in 0..70 {
+            for period in [1, 2, 7, 16, 17] {
+                let hay: Vec<u8> = (0..len)
+                    .map(|i| if i % period == 0 { b'\n' } else { b'x' })
+                    .collect();
+                let forward: Vec<usize> = WsMatcher::find_iter(&hay).collect();
+                let mut iter = WsMatcher::find_iter(&hay);
+                let (mut front, mut back) = (Vec::new(), Vec::new());
+                for step in 0.. {
+                    let next = if step % 3 == 0 {
+                        iter.next_back()
+                    } else {
+                        iter.next()
+                    };
+                    match next {
+                        Some(pos) if step % 3 == 0 => back.push(pos),
+                        Some(pos) => front.push(pos),
+                        None => break,
+                    }
+                }
+                assert_eq!(iter.next(), None);
+                assert_eq!(iter.next_back(), None);
+                front.extend(back.iter().rev());
+                assert_eq!(front, forward, "len {len} period {period}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        let pieces: Vec<&[u8]> = WsMatcher::split(b"a\tbc\r\n..............xyz\t").collect();
+        assert_eq!(pieces, [&b"a"[..], b"bc", b"", b"..............xyz", b""]);
+        assert_eq!(WsMatcher::split(b"").collect::<Vec<_>>(), [b""]);
+        assert_eq!(WsMatcher::split(b"abc").collect::<Vec<_>>(), [b"abc"]);
+        // Rejoining the pieces with the original delimiters restores the input.
+        let hay = b"\n..\t...............\r.\n";
+        let mut rebuilt = Vec::new();
+        for (piece, delim) in
+            WsMatcher::split(hay).zip(WsMatcher::find_iter(hay).map(Some).chain([None]))
+        {
+            rebuilt.extend_from_slice(piece);
+            rebuilt.extend(delim.map(|pos| hay[pos]));
+        }
+        assert_eq!(rebuilt, hay);
+    }
+
+    #[test]
+    fn test_splitn() {
+        let hay = b"Host: a\r\n\r\nbody\twith\nbreaks";
+        let pieces: Vec<&[u8]> = WsMatcher::splitn(hay, 3).collect();
+        assert_eq!(pieces, [&b"Host: a"[..], b"", b"\r\nbody\twith\nbreaks"]);
+        assert_eq!(WsMatcher::splitn(hay, 0).next(), None);
+        assert_eq!(WsMatcher::splitn(hay, 1).collect::<Vec<_>>(), [hay]);
+        // A limit above the number of pieces behaves like split.
+        assert!(WsMatcher::splitn(hay, 100).eq(WsMatcher::split(hay)));
+        assert_eq!(
+            WsMatcher::splitn(b"a\nb", 2).collect::<Vec<_>>(),
+            [b"a", b"b"]
+        );
+        assert_eq!(WsMatcher::splitn(b"", 2).collect::<Vec<_>>(), [b""]);
+    }
+
+    #[test]
+    fn test_split_inclusive() {
+        let hay = b"GET /\r\nHost: a\r\n................\n";
+        let pieces: Vec<&[u8]> = WsMatcher::split_inclusive(hay).collect();
+        assert_eq!(
+            pieces,
+            [
+                &b"GET /\r"[..],
+                b"\n",
+                b"Host: a\r",
+                b"\n",
+                b"................\n"
+            ]
+        );
+        assert_eq!(pieces.concat(), hay);
+        assert_eq!(
+            WsMatcher::split_inclusive(b"a\nb").collect::<Vec<_>>(),
+            [&b"a\n"[..], b"b"]
+        );
+        assert_eq!(WsMatcher::split_inclusive(b"").next(), None);
+        for len in 0..40 {
+            let hay: Vec<u8> = (0..len)
+                .map(|i| if i % 7 == 3 { b'\n' } else { b'x' })
+                .collect();
+            assert_eq!(
+                WsMatcher::split_inclusive(&hay)
+                    .collect::<Vec<_>>()
+                    .concat(),
+                hay
+            );
+        }
+    }
+
+    #[test]
+    fn test_tokens() {
+        let hay = b"  let\t\tx =\r\n\n................ 42\n";
+        let tokens: Vec<&[u8]> = WsMatcher::tokens(hay).collect();
+        assert_eq!(tokens, [&b"  let"[..], b"x =", b"................ 42"]);
+        assert_eq!(WsMatcher::tokens(b"").next(), None);
+        assert_eq!(WsMatcher::tokens(b"\n\t\r\n").next(), None);
+        assert_eq!(WsMatcher::tokens(b"abc").collect::<Vec<_>>(), [b"abc"]);
+        // Same as split with the empty pieces dropped.
+        for len in 0..40 {
+            let hay: Vec<u8> = (0..len)
+                .map(|i| if i % 5 < 2 { b'\t' } else { b'x' })
+                .collect();
+            assert!(WsMatcher::tokens(&hay).eq(WsMatcher::split(&hay).filter(|p| !p.is_empty())));
+        }
+    }
+
+    /// Maximal runs of members by a byte-at-a-time scan.
+    fn scalar_runs(hay: &[u8], member: impl Fn(u8) -> bool) -> Vec<Range<usize>> {
+        let mut runs = Vec::new();
+        let mut start = None;
+        for (i, &b) in hay.iter().chain([&0]).enumerate() {
+            match (start, member(b) && i < hay.len()) {
+                (None, true) => start = Some(i),
+                (Some(s), false) => {
+                    runs.push(s..i);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        runs
+    }
+
+    #[test]
+    fn test_match_runs() {
+        let hay = b"a \t\tb\n";
+        assert_eq!(WsMatcher::match_runs(hay).collect::<Vec<_>>(), [2..4, 5..6]);
+        assert_eq!(WsMatcher::match_runs(b"").next(), None);
+        // Runs crossing and filling chunks, at every alignment.
+        for len in 0..70 {
+            for (lo, hi) in [(0, len), (3, 40), (15, 17), (16, 32), (10, 64)] {
+                let hay: Vec<u8> = (0..len)
+                    .map(|i| {
+                        if (lo..hi).contains(&i) || i % 23 == 0 {
+                            b'\n'
+                        } else {
+                            b'x'
+                        }
+                    })
+                    .collect();
+                let expected = scalar_runs(&hay, |b| b == b'\n');
+                assert_eq!(WsMatcher::match_runs(&hay).collect::<Vec<_>>(), expected);
+            }
+        }
+        let mut runs = NulMatcher::match_runs(&[0u8; 33]);
+        assert_eq!((runs.next(), runs.next()), (Some(0..33), None));
+    }
+
+    #[test]
+    fn test_find_run() {
+        let hay = b"a\n\nb\n\n\n\nc\n\n\n\n\n";
+        assert_eq!(WsMatcher::find_run(hay, 1), Some(1..3));
+        assert_eq!(WsMatcher::find_run(hay, 3), Some(4..8));
+        assert_eq!(WsMatcher::find_run(hay, 5), Some(9..14));
+        assert_eq!(WsMatcher::find_run(hay, 6), None);
+        assert_eq!(WsMatcher::find_run(b"", 0), Some(0..0));
+        // A run spanning three chunks.
+        let mut long = vec![b'x'; 60];
+        long[10..50].fill(b'\t');
+        assert_eq!(WsMatcher::find_run(&long, 40), Some(10..50));
+        assert_eq!(WsMatcher::find_run(&long, 41), None);
+    }
+
+    #[test]
+    fn test_gap_runs() {
+        let hay = b"a \t\tbc\n";
+        assert_eq!(WsMatcher::gap_runs(hay).collect::<Vec<_>>(), [0..2, 4..6]);
+        assert_eq!(WsMatcher::gap_runs(b"").next(), None);
+        for len in 0..70 {
+            for (lo, hi) in [(0, len), (3, 40), (15, 17), (16, 32), (10, 64)] {
+                let hay: Vec<u8> = (0..len)
+                    .map(|i| {
+                        if (lo..hi).contains(&i) || i % 23 == 0 {
+                            b'\n'
+                        } else {
+                            b'x'
+                        }
+                    })
+                    .collect();
+                let expected = scalar_runs(&hay, |b| b != b'\n');
+                assert_eq!(WsMatcher::gap_runs(&hay).collect::<Vec<_>>(), expected);
+                // Gaps match the non-empty tokens.
+                assert!(
+                    WsMatcher::gap_runs(&hay)
+                        .map(|r| &hay[r])
+                        .eq(WsMatcher::tokens(&hay))
+                );
+            }
+        }
+        // Zero padding past a short tail is not reported as a gap.
+        let mut runs = NulMatcher::gap_runs(b"abc");
+        assert_eq!((runs.next(), runs.next()), (Some(0..3), None));
+    }
+
+    #[test]
+    fn test_find_iter_located() {
+        struct Colon;
+        impl ShuftiMatch for Colon {
+            const SET: &'static str = ":\n";
+            const NEEDLE_COUNT: usize = 2;
+            const BUCKET_OF: [u8; 256] = fast_table(b":\n").bucket_map();
+            fn table() -> ShuftiTable {
+                fast_table(b":\n")
+            }
+        }
+        let hay = b"a:b\n\nlong line spanning the chunk: x\n:";
+        let got: Vec<(usize, usize, usize)> = Colon::find_iter_located(hay)
+            .map(|l| (l.offset, l.line, l.column))
+            .collect();
+        assert_eq!(
+            got,
+            [
+                (1, 1, 2),
+                (3, 1, 4),
+                (4, 2, 1),
+                (33, 3, 29),
+                (36, 3, 32),
+                (37, 4, 1)
+            ]
+        );
+        // Cross-check lines and columns against a scalar walk.
+        for l in Colon::find_iter_located(hay) {
+            let before = &hay[..l.offset];
+            let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+            let start = before
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |p| p + 1);
+            assert_eq!((l.line, l.column), (line, l.offset - start + 1));
+        }
+        assert_eq!(WsMatcher::find_iter_located(b"").next(), None);
+    }
+
+    #[test]
+    fn test_find_from() {
+        let hay = b"a\tb\r..............\n..\t";
+        assert_eq!(WsMatcher::find_from(hay, 0), Some(1));
+        assert_eq!(WsMatcher::find_from(hay, 2), Some(3));
+        assert_eq!(WsMatcher::find_from(hay, 4), Some(18));
+        assert_eq!(WsMatcher::find_from(hay, 19), Some(21));
+        assert_eq!(WsMatcher::find_from(hay, 22), None);
+        assert_eq!(WsMatcher::find_from(hay, hay.len()), None);
+        assert_eq!(WsMatcher::find_from(hay, usize::MAX), None);
+        // Resuming from every match visits the same positions as find_iter.
+        let mut resumed = Vec::new();
+        let mut at = 0;
+        while let Some(pos) = WsMatcher::find_from(hay, at) {
+            resumed.push(pos);
+            at = pos + 1;
+        }
+        assert_eq!(resumed, WsMatcher::find_iter(hay).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_find_in() {
+        let hay = b"\t..............abc\n..\t";
+        assert_eq!(WsMatcher::find_in(hay, 0..hay.len()), Some(0));
+        assert_eq!(WsMatcher::find_in(hay, 1..hay.len()), Some(18));
+        // A match just past the end of the window is not reported.
+        assert_eq!(WsMatcher::find_in(hay, 1..18), None);
+        assert_eq!(WsMatcher::find_in(hay, 19..21), None);
+        assert_eq!(WsMatcher::find_in(hay, 19..22), Some(21));
+        assert_eq!(WsMatcher::find_in(hay, 5..5), None);
+        assert_eq!(WsMatcher::find_in(hay, 5..100), None);
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 6..5;
+        assert_eq!(WsMatcher::find_in(hay, reversed), None);
+        // A `\0` needle does not see zero padding past a short window.
+        assert_eq!(NulMatcher::find_in(b"ab\0", 0..2), None);
+        assert_eq!(NulMatcher::find_in(b"ab\0", 0..3), Some(2));
+    }
+
+    #[test]
+    fn test_prefix_len() {
+        assert_eq!(WsMatcher::prefix_len(b"\t\t x"), 2);
+        assert_eq!(WsMatcher::skip_prefix(b"\t\t x"), b" x");
+        assert_eq!(WsMatcher::prefix_len(b"x\t"), 0);
+        assert_eq!(WsMatcher::prefix_len(b""), 0);
+        assert_eq!(WsMatcher::prefix_len(&[b'\n'; 35]), 35);
+        assert_eq!(WsMatcher::skip_prefix(&[b'\n'; 35]), b"");
+        let mut hay = vec![b'\r'; 17];
+        hay.push(b'=');
+        assert_eq!(WsMatcher::skip_prefix(&hay), b"=");
+    }
+
+    #[test]
+    fn test_suffix_len() {
+        assert_eq!(WsMatcher::suffix_len(b"x \r\n"), 2);
+        assert_eq!(WsMatcher::suffix_len(b"\r\nx"), 0);
+        assert_eq!(WsMatcher::suffix_len(b""), 0);
+        assert_eq!(WsMatcher::suffix_len(&[b'\t'; 35]), 35);
+        for len in 0..40 {
+            for run in 0..=len {
+                let mut hay = vec![b'x'; len - run];
+                hay.resize(len, b'\n');
+                assert_eq!(WsMatcher::suffix_len(&hay), run);
+            }
+        }
+    }
+
+    #[test]
+    fn test_trim() {
+        let hay = b"\r\n\t  value \t\r\n";
+        assert_eq!(WsMatcher::trim_start(hay), b"  value \t\r\n");
+        assert_eq!(WsMatcher::trim_end(hay), b"\r\n\t  value ");
+        assert_eq!(WsMatcher::trim(hay), b"  value ");
+        assert_eq!(WsMatcher::trim(b"\n\n\n"), b"");
+        assert_eq!(WsMatcher::trim_start(b""), b"");
+        assert_eq!(WsMatcher::trim_end(b"x"), b"x");
+        let mut long = vec![b'\n'; 20];
+        long.extend_from_slice(b"..................");
+        long.extend_from_slice(&[b'\t'; 33]);
+        assert_eq!(WsMatcher::trim(&long), &long[20..38]);
+    }
+
+    #[test]
+    fn test_set_predicates() {
+        assert!(WsMatcher::contains_any(b"ab\tc"));
+        assert!(!WsMatcher::none_in_set(b"ab\tc"));
+        assert!(!WsMatcher::contains_any(b"abc"));
+        assert!(WsMatcher::none_in_set(b""));
+        assert!(WsMatcher::all_in_set(b""));
+        assert!(WsMatcher::all_in_set(b"\r\n\t"));
+        assert!(!NulMatcher::all_in_set(b"\0\0a"));
+        for len in 1..50 {
+            let mut hay = vec![b'\n'; len];
+            assert!(WsMatcher::all_in_set(&hay), "len {len}");
+            for at in 0..len {
+                hay[at] = b'x';
+                assert!(!WsMatcher::all_in_set(&hay), "len {len} at {at}");
+                hay[at] = b'\n';
+            }
+        }
+        // Zero padding of a short tail does not count as set bytes.
+        assert!(NulMatcher::all_in_set(&[0u8; 3]));
+    }
+
+    #[test]
+    fn test_eq_ignoring_set() {
+        assert!(WsMatcher::eq_ignoring_set(b"key=\tvalue\n", b"key\t=value"));
+        assert!(WsMatcher::eq_ignoring_set(b"", b"\n\t\r"));
+        assert!(WsMatcher::eq_ignoring_set(b"abc", "a\nb\nc"));
+        assert!(!WsMatcher::eq_ignoring_set(b"abc", b"ab"));
+        assert!(!WsMatcher::eq_ignoring_set(b"a\tb", b"a\tc"));
+        assert!(!WsMatcher::eq_ignoring_set(b"", b"x"));
+        let long: Vec<u8> = (0..100)
+            .map(|i| {
+                if i % 7 == 0 {
+                    b'\n'
+                } else {
+                    b'a' + (i % 26) as u8
+                }
+            })
+            .collect();
+        let dense: Vec<u8> = long.iter().copied().filter(|&b| b != b'\n').collect();
+        assert!(WsMatcher::eq_ignoring_set(&long, &dense));
+        assert!(WsMatcher::eq_ignoring_set(&dense, &long));
+        assert!(!WsMatcher::eq_ignoring_set(&long, &dense[1..]));
+    }
+
+    #[test]
+    fn test_replace_all() {
+        assert_eq!(WsMatcher::replace_all(b"a\tb\r\nc", b"?"), b"a?b??c");
+        assert_eq!(WsMatcher::replace_all(b"a\tb", b"\\t"), b"a\\tb");
+        assert_eq!(WsMatcher::replace_all(b"\ta\n", b""), b"a");
+        assert_eq!(WsMatcher::replace_all(b"", b"?"), b"");
+        let hay = b"................\n................";
+        assert_eq!(
+            WsMatcher::replace_all(hay, b" "),
+            b"................ ................"
+        );
+        assert_eq!(NulMatcher::replace_all(b"ab", b"!"), b"ab");
+    }
+
+    #[test]
+    fn test_escape_into() {
+        let json_escape = |b: u8, out: &mut Vec<u8>| match b {
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b'\t' => out.extend_from_slice(b"\\t"),
+            _ => out.extend_from_slice(format!("\\u{b:04x}").as_bytes()),
+        };
+        let mut out = b"\"".to_vec();
+        WsMatcher::escape_into(b"a\tb\r\n................c", &mut out, json_escape);
+        assert_eq!(out, b"\"a\\tb\\u000d\\n................c");
+        // The closure only sees set bytes.
+        let mut seen = Vec::new();
+        let mut out = Vec::new();
+        WsMatcher::escape_into(b"x\ny\tz", &mut out, |b, out| {
+            seen.push(b);
+            out.push(b'_');
+        });
+        assert_eq!(
+            (out.as_slice(), seen.as_slice()),
+            (&b"x_y_z"[..], &b"\n\t"[..])
+        );
+    }
+
+    #[test]
+    fn test_remove_all() {
+        let blob = b"aGVs\r\nbG8g\r\nd29y\r\nbGQ=\n";
+        assert_eq!(WsMatcher::remove_all(blob), b"aGVsbG8gd29ybGQ=");
+        assert_eq!(WsMatcher::remove_all(b""), b"");
+        assert_eq!(WsMatcher::remove_all(&[b'\n'; 40]), b"");
+        assert_eq!(NulMatcher::remove_all(b"a\0b"), b"ab");
+        for len in 0..70 {
+            let hay: Vec<u8> = (0..len)
+                .map(|i| if i % 3 == 1 { b'\t' } else { i as u8 + b'A' })
+                .collect();
+            let expected: Vec<u8> = hay.iter().copied().filter(|&b| b != b'\t').collect();
+            assert_eq!(WsMatcher::remove_all(&hay), expected, "len {len}");
+            let mut buf = hay.clone();
+            WsMatcher::remove_all_in_place(&mut buf);
+            assert_eq!(buf, expected, "len {len}");
+        }
+    }
+
+    #[test]
+    fn test_find_first_vectored() {
+        let bufs: [&[u8]; 4] = [b"GET", b"", b"................ /", b"\r\n"];
+        assert_eq!(WsMatcher::find_first_vectored(&bufs), Some((3, 0)));
+        let io = [std::io::IoSlice::new(b"ab"), std::io::IoSlice::new(b"c\nd")];
+        assert_eq!(WsMatcher::find_first_vectored(&io), Some((1, 1)));
+        assert_eq!(WsMatcher::find_first_vectored::<&[u8]>(&[]), None);
+        assert_eq!(WsMatcher::find_first_vectored(&[b"ab", b"cd"]), None);
+    }
+
+    #[test]
+    fn test_find_first_str() {
+        assert_eq!(WsMatcher::find_first_str("héllo\twörld"), Some(6));
+        assert_eq!(
+            WsMatcher::find_iter_str("a\né\t").collect::<Vec<_>>(),
+            [(1, '\n'), (4, '\t')]
+        );
+        assert_eq!(WsMatcher::find_first_str(""), None);
+        // A set holding both a lead byte (0xc3) and a continuation byte
+        // (0xa9) of 'é' only reports the character start.
+        struct Bytes;
+        impl ShuftiMatch for Bytes {
+            const SET: &'static str = "";
+            const NEEDLE_COUNT: usize = 2;
+            const BUCKET_OF: [u8; 256] = fast_table(b"\xc3\xa9").bucket_map();
+            fn table() -> ShuftiTable {
+                fast_table(b"\xc3\xa9")
+            }
+        }
+        assert_eq!(Bytes::find_iter("xé").collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(Bytes::find_iter_str("xé").collect::<Vec<_>>(), [(1, 'é')]);
+        assert_eq!(Bytes::find_first_str("\u{e9}"), Some(0));
+        assert_eq!(Bytes::find_first_str("\u{29}\u{a9}"), None);
+    }
+
+    #[test]
+    fn test_find_first_not() {
+        assert_eq!(WsMatcher::find_first_not(b"\t\r\nx"), Some(3));
+        assert_eq!(WsMatcher::find_first_not(b"x"), Some(0));
+        // The padded epilogue lanes are not reported as non-members.
+        assert_eq!(WsMatcher::find_first_not(b"\n\n\n"), None);
+        assert_eq!(WsMatcher::find_first_not(&[b'\t'; 16]), None);
+        let mut hay = [b'\n'; 40];
+        hay[37] = 0;
+        assert_eq!(WsMatcher::find_first_not(&hay), Some(37));
+        assert_eq!(WsMatcher::find_first_not(b""), None);
+    }
+
+    #[test]
+    fn test_find_last_not() {
+        assert_eq!(WsMatcher::find_last_not(b"ab \t\r\n"), Some(2));
+        assert_eq!(WsMatcher::find_last_not(b"\n\n\n"), None);
+        assert_eq!(WsMatcher::find_last_not(b""), None);
+        // Every length and position, against a scalar scan.
+        for len in 0..50 {
+            for at in 0..len {
+                let mut hay = vec![b'\n'; len];
+                hay[at] = b'x';
+                assert_eq!(WsMatcher::find_last_not(&hay), Some(at), "len {len}");
+                hay[len - 1] = b'y';
+                assert_eq!(WsMatcher::find_last_not(&hay), Some(len - 1));
+            }
+            assert_eq!(WsMatcher::find_last_not(&vec![b'\t'; len]), None);
+        }
+    }
+
+    #[test]
+    fn test_split_at_first_match() {
+        assert_eq!(
+            WsMatcher::split_at_first_match(b"key\tvalue\n"),
+            Some((&b"key"[..], b'\t', &b"value\n"[..]))
+        );
+        assert_eq!(
+            WsMatcher::split_at_first_match(b"\n"),
+            Some((&b""[..], b'\n', &b""[..]))
+        );
+        assert_eq!(WsMatcher::split_at_first_match(b"none"), None);
+    }
+
+    #[test]
+    fn test_strided() {
+        // Three 5-byte records; column 2 holds whitespace in records 0 and 2.
+        let hay = b"ab\ncdefghijk\tlm";
+        assert_eq!(WsMatcher::find_first_strided(hay, 5, 2), Some(2));
+        assert_eq!(
+            WsMatcher::find_iter_strided(hay, 5, 2).collect::<Vec<_>>(),
+            [2, 12]
+        );
+        assert_eq!(WsMatcher::count_strided(hay, 5, 1), 0);
+        assert_eq!(WsMatcher::find_first_strided(hay, 1, 3), Some(12));
+
+        // Cross-check every stride/phase against a scalar filter.
+        let mut hay = vec![b'.'; 100];
+        for i in (0..100).step_by(3) {
+            hay[i] = b'\n';
+        }
+        for stride in 1..40 {
+            for phase in [0, 1, 7, 16, 33, 99, 100, 150] {
+                let expected: Vec<usize> = (phase..hay.len())
+                    .step_by(stride)
+                    .filter(|&i| hay[i] == b'\n')
+                    .collect();
+                let got: Vec<usize> = WsMatcher::find_iter_strided(&hay, stride, phase).collect();
+                assert_eq!(got, expected, "stride {stride} phase {phase}");
+            }
+        }
+        assert_eq!(WsMatcher::count_strided(&hay, 0, 0), 0);
+    }
+
+    #[test]
+    fn test_find_iter_source() {
+        let hay = b"a\tb\r..............\n..\t";
+        let got: Vec<usize> = WsMatcher::find_iter_source(hay.iter().copied()).collect();
+        assert_eq!(got, [1, 3, 18, 21]);
+
+        // Sources that end on a block boundary, and NUL padding.
+        let zeros = core::iter::repeat_n(b'x', 32);
+        assert_eq!(NulMatcher::find_iter_source(zeros).count(), 0);
+        let source = (0..40u8).map(|i| if i % 13 == 0 { 0 } else { i });
+        assert_eq!(
+            NulMatcher::find_iter_source(source).collect::<Vec<_>>(),
+            [0, 13, 26, 39]
+        );
+        assert_eq!(WsMatcher::find_iter_source([]).next(), None);
+    }
+
+    #[test]
+    fn test_bitmap() {
+        let mut hay = vec![b'a'; 130];
+        for i in [0, 17, 63, 64, 100, 129] {
+            hay[i] = b'\n';
+        }
+        let mut out = vec![u64::MAX; 7];
+        WsMatcher::bitmap(&hay, &mut out);
+        assert_eq!(out, [1 | 1 << 17 | 1 << 63, 1 | 1 << 36, 1 << 1]);
+        WsMatcher::bitmap(b"", &mut out);
+        assert!(out.is_empty());
+    }
+
+    #[cfg(feature = "fixedbitset")]
+    #[test]
+    fn test_fill_bitset() {
+        let mut hay = vec![b'a'; 130];
+        for i in [0, 17, 63, 64, 100, 129] {
+            hay[i] = b'\n';
+        }
+        let mut set = fixedbitset::FixedBitSet::with_capacity(500);
+        set.insert_range(..);
+        WsMatcher::fill_bitset(&hay, &mut set);
+        assert_eq!(set.len(), 130);
+        assert_eq!(set.ones().collect::<Vec<_>>(), [0, 17, 63, 64, 100, 129]);
+        WsMatcher::fill_bitset(b"\t", &mut set);
+        assert_eq!((set.len(), set.count_ones(..)), (1, 1));
+    }
+
+    #[cfg(feature = "bitvec")]
+    #[test]
+    fn test_fill_bitvec() {
+        let mut hay = vec![b'a'; 70];
+        for i in [3, 16, 69] {
+            hay[i] = b'\r';
+        }
+        let mut bits = bitvec::bitvec![1; 200];
+        WsMatcher::fill_bitvec(&hay, &mut bits);
+        assert_eq!(bits.len(), 70);
+        assert_eq!(bits.iter_ones().collect::<Vec<_>>(), [3, 16, 69]);
+    }
+
+    #[test]
+    fn test_search_chunks() {
+        let mut chunks = [*b"abcdefghijklmnop"; 3];
+        assert_eq!(WsMatcher::search_chunks(&chunks), None);
+        chunks[1][4] = b'\n';
+        chunks[2][0] = b'\t';
+        assert_eq!(WsMatcher::search_chunks(&chunks), Some(20));
+        let masks: Vec<u16> = WsMatcher::chunk_masks(&chunks).map(Mask16::bits).collect();
+        assert_eq!(masks, [0, 1 << 4, 1]);
+        assert_eq!(WsMatcher::search_chunks(&[]), None);
+    }
+
+    // Compiles only if the capacity assertions hold.
+    crate::shufti_assert_buckets!("\t\r\n", 3);
+    crate::shufti_assert_buckets!("abcdefghijklmno", 1);
+
+    #[test]
+    fn test_active_backend() {
+        let expected = if cfg!(all(target_arch = "x86_64", target_feature = "ssse3")) {
+            Backend::Ssse3
+        } else if cfg!(all(target_arch = "aarch64", target_feature = "neon")) {
+            Backend::Neon
+        } else {
+            Backend::Scalar
+        };
+        let expected = if cfg!(feature = "no-unsafe") {
+            Backend::Scalar
+        } else {
+            expected
+        };
+        assert_eq!(active_backend(), expected);
+        assert_eq!(expected.to_string(), expected.name());
+    }
+
+    #[test]
+    fn test_public_api_never_panics() {
+        use std::time::Instant;
+        let past = Instant::now();
+        let finders = [Finder::new(b"\t\r\n"), !Finder::new(b"a")];
+        let truffle = TruffleTable::new(b"\t\n\xff");
+        let mut out = Vec::new();
+        let mut words = Vec::new();
+        for len in 0..70 {
+            for fill in [0u8, b'\n', b'a', 0xff] {
+                let hay = vec![fill; len];
+                let _ = WsMatcher::find_first(&hay);
+                let _ = NulMatcher::find_first(&hay);
+                let _ = WsMatcher::squeeze(&hay, b' ');
+                WsMatcher::squeeze_in_place(&mut hay.clone(), b' ');
+                WsMatcher::bitmap(&hay, &mut words);
+                bitmask::shift_up(&mut words, len * 3);
+                let _ = bitmask::iter_ones(&words).count();
+                WsMatcher::positions_into(&hay, &mut out);
+                NulMatcher::collect_matched_bytes(&hay, &mut Vec::new());
+                for (stride, phase) in [(0, 0), (1, len), (3, usize::MAX), (usize::MAX, 1)] {
+                    let _ = WsMatcher::count_strided(&hay, stride, phase);
+                }
+                for block_size in [0, 1, 5, 16] {
+                    let _ = WsMatcher::block_prefix_counts(&hay, block_size);
+                }
+                let _ = WsMatcher::find_iter_located(&hay).count();
+                let _ = NulMatcher::highlight(&hay, Style::RED);
+                let _ = NulMatcher::find_last_not(&hay);
+                let _ = NulMatcher::trim(&hay);
+                let _ = NulMatcher::skip_prefix(&hay);
+                let _ = NulMatcher::suffix_len(&hay);
+                let _ = NulMatcher::all_in_set(&hay);
+                let _ = NulMatcher::replace_all(&hay, b"..");
+                NulMatcher::remove_all_in_place(&mut hay.clone());
+                WsMatcher::collapse_runs(&hay, b'\n', &mut Vec::new());
+                let _ = NulMatcher::count(&hay);
+                let _ = NulMatcher::split(&hay).count();
+                let _ = NulMatcher::match_runs(&hay).count();
+                let _ = NulMatcher::gap_runs(&hay).count();
+                let _ = NulMatcher::find_run(&hay, len / 2);
+                let _ = NulMatcher::tokens(&hay).count();
+                let _ = NulMatcher::split_inclusive(&hay).count();
+                let _ = NulMatcher::splitn(&hay, len / 3).count();
+                let _ = NulMatcher::find_nth(&hay, len / 2);
+                let _ = NulMatcher::find_n_into(&hay, &mut [0; 5]);
+                let _ = NulMatcher::field_range(&hay, len / 4);
+                let _ = looks_binary(&hay);
+                let index = LineIndex::build(&hay);
+                let _ = (index.lookup(len + 5), index.line_range(len + 5));
+                let _ = find_first_of_any(&[&finders[0], &finders[1]], &hay);
+                let _ = truffle.find_first(&hay);
+                let _ = truffle.find_iter(&hay).count();
+                for finder in &finders {
+                    let _ = (finder.rfind(&hay), finder.count(&hay));
+                    let _ = finder.find_iter(&hay).count();
+                }
+                for start in [0, len / 2, len, len + 1, usize::MAX] {
+                    let _ = NulMatcher::find_from(&hay, start);
+                    let _ = NulMatcher::find_first_within(&hay, start);
+                    let _ = NulMatcher::find_in(&hay, start..len);
+                    let _ = NulMatcher::find_in(&hay, len / 2..start);
+                    let _ = WsMatcher::find_first_until(&hay, start, past);
+                    let _ = WsMatcher::positions_into_until(&hay, start, &mut out, past);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_const_expr() {
+        let table = fast_table(b"ab");
+        let expr = table.to_const_expr();
+        assert_eq!(
+            expr,
+            "::shufti_matcher::ShuftiTable { low_tab: [0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], \
+             high_tab: [0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0], bit_mask: 0x03 }"
+        );
+        // The same literal, compiled.
+        let rebuilt = ::shufti_matcher::ShuftiTable {
+            low_tab: [0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            high_tab: [0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            bit_mask: 0x03,
+        };
+        assert_eq!(rebuilt.bucket_map(), table.bucket_map());
+    }
+
+    #[test]
+    fn test_bucket_of() {
+        assert_eq!(WsMatcher::bucket_of(b'\t'), 1);
+        assert_eq!(WsMatcher::bucket_of(b'\r'), 2);
+        assert_eq!(WsMatcher::bucket_of(b'\n'), 4);
+        assert_eq!(WsMatcher::bucket_of(b' '), 0);
+        assert_eq!(WsMatcher::BUCKET_OF, WsMatcher::table().bucket_map());
+    }
+
+    #[test]
+    fn test_find_followed_by() {
+        struct Cr;
+        impl ShuftiMatch for Cr {
+            const SET: &'static str = "\r";
+            const NEEDLE_COUNT: usize = 1;
+            const BUCKET_OF: [u8; 256] = fast_table(b"\r").bucket_map();
+            fn table() -> ShuftiTable {
+                fast_table(b"\r")
+            }
+        }
+        struct Lf;
+        impl ShuftiMatch for Lf {
+            const SET: &'static str = "\n";
+            const NEEDLE_COUNT: usize = 1;
+            const BUCKET_OF: [u8; 256] = fast_table(b"\n").bucket_map();
+            fn table() -> ShuftiTable {
+                fast_table(b"\n")
+            }
+        }
+        assert_eq!(Cr::find_followed_by::<Lf, _>(b"a\r b\n\r\nc"), Some(5));
+        assert_eq!(Cr::find_followed_by::<Lf, _>(b"\n\r"), None);
+        assert_eq!(Cr::find_followed_by::<Lf, _>(b""), None);
+        // At every position, including across chunk boundaries.
+        for len in 2..70 {
+            for at in 0..len - 1 {
+                let mut hay = vec![b'\r'; len];
+                hay[at + 1] = b'\n';
+                assert_eq!(Cr::find_followed_by::<Lf, _>(&hay), Some(at), "len {len}");
+            }
+        }
+    }
+
+    #[cfg(not(feature = "no-unsafe"))]
+    #[test]
+    fn test_find_first_raw() {
+        let hay = b"................a\tb";
+        let range = hay.as_ptr_range();
+        // SAFETY: `range` covers `hay`.
+        unsafe {
+            let hit = WsMatcher::find_first_raw(range.start, range.end);
+            assert_eq!(hit, hay[17..].as_ptr());
+            assert!(WsMatcher::find_first_raw(range.start, range.start.add(17)).is_null());
+            assert!(WsMatcher::find_first_raw(range.end, range.end).is_null());
+        }
+    }
+
+    #[test]
+    fn test_table_introspection() {
+        let ws = fast_table(b"\t\r\n");
+        assert_eq!(ws.members(), b"\t\n\r");
+        let buckets = ws.buckets();
+        assert_eq!(buckets[..3], [vec![b'\t'], vec![b'\r'], vec![b'\n']]);
+        assert!(buckets[3..].iter().all(Vec::is_empty));
+
+        let digits = ShuftiTable::try_new(b"0123456789").unwrap();
+        assert_eq!(digits.members(), b"0123456789");
+        assert_eq!(digits.buckets()[0], b"0123456789");
+    }
+
+    #[test]
+    fn test_verify_derived_tables() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(set = "{}[]:,\" \t\r\n0123456789")]
+        struct Json;
+        assert_eq!(Json::table().verify(Json::SET.as_bytes()), Ok(()));
+        assert_eq!(WsMatcher::table().verify(b"\n\t\r\n"), Ok(()));
+        let err = WsMatcher::table().verify(b"\t ").unwrap_err();
+        assert_eq!(
+            (err.false_positives, err.false_negatives),
+            (vec![b'\n', b'\r'], vec![b' '])
+        );
+    }
+
+    #[test]
+    fn test_is_member_bitmap() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(set = "\x00\x7f~")]
+        struct Edges;
+        const NUL: bool = Edges::is_member(0);
+        assert!(NUL && Edges::is_member(0x7f) && !Edges::is_member(b'a'));
+        assert_eq!(Edges::BITMAP, bitmap_of(&Edges::BUCKET_OF));
+        assert_eq!(Edges::BITMAP, [1, 0b11u64 << 62, 0, 0]);
+        for b in 0..=255u8 {
+            assert_eq!(WsMatcher::is_member(b), WsMatcher::bucket_of(b) != 0);
+            assert_eq!(<Edges as ShuftiMatch>::is_member(b), Edges::is_member(b));
+        }
+    }
+
+    #[test]
+    fn test_cmpeq_specialization() {
+        struct CrLf;
+        impl ShuftiMatch for CrLf {
+            const SET: &'static str = "\r\n";
+            const NEEDLE_COUNT: usize = 2;
+            const BUCKET_OF: [u8; 256] = fast_table(b"\r\n").bucket_map();
+            const CMPEQ: Option<[u8; 2]> = Some([b'\r', b'\n']);
+            fn table() -> ShuftiTable {
+                fast_table(b"\r\n")
+            }
+        }
+        struct Nul;
+        impl ShuftiMatch for Nul {
+            const SET: &'static str = "\0";
+            const NEEDLE_COUNT: usize = 1;
+            const BUCKET_OF: [u8; 256] = fast_table(b"\0").bucket_map();
+            const CMPEQ: Option<[u8; 2]> = Some([0, 0]);
+            fn table() -> ShuftiTable {
+                fast_table(b"\0")
+            }
+        }
+
+        let all: Vec<u8> = (0..=255u8).collect();
+        for chunk in all.as_chunks::<16>().0 {
+            assert_eq!(
+                CrLf::match_16b(chunk).bits(),
+                kernel!(CrLf::table().bitmask_16b(chunk))
+            );
+            assert_eq!(
+                Nul::match_16b(chunk).bits(),
+                kernel!(Nul::table().bitmask_16b(chunk))
+            );
+        }
+        assert_eq!(CrLf::first_match_16b(b"abc\n\r..........."), Some(3));
+        assert_eq!(CrLf::first_match_16b(&[b'x'; 16]), None);
+        assert_eq!(CrLf::find_iter("a\r\nb\n").collect::<Vec<_>>(), [1, 2, 4]);
+        // The zero padding of the epilogue never matches a NUL needle.
+        assert_eq!(Nul::find_first(b"abc"), None);
+        assert_eq!(Nul::find_first(b"abc\0"), Some(3));
+    }
+
+    #[test]
+    fn test_derived_algorithm() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(set = "\x00\x11\x22\x33\x44\x55\x66\x77\x08")]
+        struct Spread;
+        #[derive(ShuftiMatcher)]
+        #[shufti(set = "\n")]
+        struct Newline;
+        assert_eq!(Spread::ALGORITHM, Algorithm::WideShufti);
+        assert!(Spread::WIDE.is_some() && Spread::TRUFFLE.is_none());
+        assert_eq!(Newline::ALGORITHM, Algorithm::CmpEq);
+        assert_eq!(WsMatcher::ALGORITHM, Algorithm::Shufti);
+
+        let hay: Vec<u8> = (0..=255u8).cycle().take(300).collect();
+        let expected: Vec<usize> = (0..hay.len())
+            .filter(|&i| Spread::SET.as_bytes().contains(&hay[i]))
+            .collect();
+        assert_eq!(Spread::find_iter(&hay).collect::<Vec<_>>(), expected);
+        assert_eq!(Spread::find_first(&hay[1..]), Some(7));
+        assert_eq!(Spread::count(&hay), expected.len());
+        let chunk: &[u8; 64] = hay[..64].try_into().unwrap();
+        assert_eq!(
+            Spread::match_64b(chunk).bits(),
+            1 | 1 << 8 | 1 << 0x11 | 1 << 0x22 | 1 << 0x33
+        );
+        assert!(Spread::is_member(0x08) && !Spread::is_member(0x88));
+    }
+
+    #[test]
+    fn test_byte_string_derive() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(set = b"\x00\x7f\xff")]
+        struct Binary;
+        assert_eq!(Binary::SET_BYTES, b"\x00\x7f\xff");
+        assert_eq!(Binary::SET, "\0\x7fÿ");
+        assert_eq!(Binary::NEEDLE_COUNT, 3);
+        assert_eq!(
+            Binary::find_iter(b"a\xffb\x7f\xc3\xbf").collect::<Vec<_>>(),
+            [1, 3]
+        );
+        assert!(Binary::is_member(0xff) && !Binary::is_member(0xc3));
+        assert_eq!(WsMatcher::SET_BYTES, WsMatcher::SET.as_bytes());
+    }
+
+    #[test]
+    fn test_ranges_derive() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(ranges = "0x00-0x1f0x7f")]
+        struct Controls;
+        assert_eq!(Controls::NEEDLE_COUNT, 33);
+        assert_eq!(Controls::find_first(b"plain text\x7f"), Some(10));
+        assert!((0..=255u8).all(|b| Controls::is_member(b) == b.is_ascii_control()));
+    }
+
+    #[test]
+    fn test_class_derive() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(class = "ascii_whitespace", class = "json_structural")]
+        struct JsonSkip;
+        assert_eq!(JsonSkip::SET, "\t\n\x0c\r ,:[]{}");
+        assert_eq!(JsonSkip::find_first(b"true, 1"), Some(4));
+    }
+
+    #[test]
+    fn test_case_insensitive_derive() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(set = "e.", ascii_case_insensitive)]
+        struct Exponent;
+        assert_eq!(
+            Exponent::find_iter(b"1E5 2e3 4.0").collect::<Vec<_>>(),
+            [1, 5, 9]
+        );
+        assert!(!Exponent::is_member(b'>'));
+    }
+
+    #[test]
+    fn test_dedup_derive() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(set = "\t \n", class = "ascii_whitespace", dedup)]
+        struct Blank;
+        assert_eq!(Blank::SET, "\t \n\x0c\r");
+        assert_eq!(Blank::count(b"a b\tc\r\n"), 4);
+    }
+
+    #[test]
+    fn test_named_sets_derive() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(set = ",")]
+        #[shufti(name = "ws", set = "\t\r\n ")]
+        #[shufti(name = "digits", ranges = "0-9")]
+        struct Lexer;
+
+        let hay = b"id = 42,\tx";
+        assert_eq!(Lexer::find_first(hay), Some(7));
+        assert_eq!(Lexer::find_first_ws(hay), Some(2));
+        assert_eq!(Lexer::find_first_digits(hay), Some(5));
+        assert_eq!(
+            Lexer::find_iter_ws(hay).rev().collect::<Vec<_>>(),
+            [8, 4, 2]
+        );
+        assert_eq!(Lexer::count_digits(hay), 2);
+        assert!(Lexer::contains_ws(hay) && !Lexer::contains_digits(b"id"));
+        const TAB: bool = Lexer::is_ws(b'\t');
+        assert!(TAB && !Lexer::is_digits(b','));
+    }
+
+    #[test]
+    fn test_enum_derive() {
+        #[derive(ShuftiMatcher, Debug, PartialEq)]
+        enum Token {
+            #[shufti(set = "\t\r\n ")]
+            Space,
+            #[shufti(ranges = "0-9")]
+            Digit,
+            #[shufti(set = "+-*/")]
+            Operator,
+            #[shufti(set = "é")]
+            Accent,
+        }
+
+        assert_eq!(Token::NEEDLE_COUNT, 4 + 10 + 4 + 2);
+        assert_eq!(Token::classify(b'7'), Some(Token::Digit));
+        assert_eq!(Token::classify(0xa9), Some(Token::Accent));
+        assert_eq!(Token::classify(b'x'), None);
+        const SPACE: Option<Token> = Token::classify(b' ');
+        assert_eq!(SPACE, Some(Token::Space));
+
+        assert_eq!(
+            Token::find_first_classified("x+1"),
+            Some((1, Token::Operator))
+        );
+        assert_eq!(Token::find_first_classified(b"xyz"), None);
+        assert_eq!(
+            Token::find_iter_classified("a 2é").collect::<Vec<_>>(),
+            [
+                (1, Token::Space),
+                (2, Token::Digit),
+                (3, Token::Accent),
+                (4, Token::Accent)
+            ]
+        );
+        assert_eq!(
+            Token::find_iter_classified(b"1*").next_back(),
+            Some((1, Token::Operator))
+        );
+    }
+
+    #[test]
+    fn test_also_negated_derive() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(set = "\t\r\n ", also_negated = "NotBlank")]
+        #[shufti(name = "quote", set = "\"'", also_negated = "NotQuote")]
+        struct Blank;
+
+        assert_eq!(NotBlank::NEEDLE_COUNT, 252);
+        assert_eq!(NotBlank::find_first(b"  \tkey"), Some(3));
+        assert_eq!(Blank::find_first(b"key ="), Some(3));
+        for b in 0..=255u8 {
+            assert_ne!(NotBlank::is_member(b), Blank::is_member(b));
+            assert_ne!(NotQuote::is_member(b), Blank::is_quote(b));
+        }
+        assert_eq!(NotQuote::find_first(b"''x"), Some(2));
+
+        #[derive(ShuftiMatcher)]
+        #[shufti(class = "ascii_digit", negate, also_negated = "Digit")]
+        struct NotDigit;
+        assert_eq!((Digit::SET, Digit::NEEDLE_COUNT), ("0123456789", 10));
+        assert_eq!(NotDigit::find_first(b"42a"), Some(2));
+    }
+
+    #[test]
+    fn test_negate_derive() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(class = "ascii_alnum", set = "_", negate)]
+        struct NotIdent;
+        assert_eq!(NotIdent::NEEDLE_COUNT, 256 - 63);
+        assert_eq!(NotIdent::find_first(b"user_42"), None);
+        assert_eq!(NotIdent::find_first(b"user-42"), Some(4));
+        assert_eq!(NotIdent::find_first("naïve"), Some(2));
+        assert_eq!(NotIdent::SET_BYTES.len(), NotIdent::NEEDLE_COUNT);
+    }
+
+    #[test]
+    fn test_approximate_derive() {
+        #[derive(ShuftiMatcher)]
+        #[shufti(set = "\x00\x11\x22\x33\x44\x55\x66\x77é", approximate)]
+        struct Spread;
+        assert!(Spread::APPROXIMATE && Spread::ALGORITHM == Algorithm::Shufti);
+        let table = Spread::table();
+        let members = table.members();
+        assert!(members.len() > Spread::NEEDLE_COUNT);
+
+        let hay: Vec<u8> = (0..=255u8).cycle().take(300).collect();
+        let expected: Vec<usize> = (0..hay.len())
+            .filter(|&i| Spread::is_member(hay[i]))
+            .collect();
+        assert_eq!(Spread::find_iter(&hay).collect::<Vec<_>>(), expected);
+        assert_eq!(Spread::count(&hay), expected.len());
+        // The first false positive of the table is skipped.
+        let fp = *members.iter().find(|&&b| !Spread::is_member(b)).unwrap();
+        assert_eq!(Spread::find_first(&[fp, fp, 0x44]), Some(2));
+        assert_ne!(table.bucket_map()[fp as usize], 0);
+        assert_eq!(Spread::bucket_of(fp), 0);
+        let chunk: &[u8; 32] = hay[..32].try_into().unwrap();
+        assert_eq!(Spread::match_32b(chunk).bits(), 1 | 1 << 0x11);
+        for b in 0..=255u8 {
+            assert_eq!(Spread::bucket_of(b) != 0, Spread::is_member(b));
+        }
+    }
+
+    #[test]
+    fn test_truffle_table() {
+        let spread: Vec<u8> = (0..=255u8).filter(|b| b >> 4 != b & 0x0f).collect();
+        let sets: [&[u8]; 4] = [&spread, b"\x00\x7f\x80\xff", b"\t\r\n", &[]];
+        let all: Vec<u8> = (0..=255u8).collect();
+        for set in sets {
+            let table = TruffleTable::new(set);
+            for chunk in all.as_chunks::<16>().0 {
+                let mask = kernel!(table.bitmask_16b(chunk));
+                for (i, &b) in chunk.iter().enumerate() {
+                    assert_eq!(mask >> i & 1 == 1, set.contains(&b), "byte 0x{b:02x}");
+                    assert_eq!(table.is_member(b), set.contains(&b));
+                }
+            }
+            let expected: Vec<usize> = (0..256).filter(|&i| set.contains(&(i as u8))).collect();
+            assert_eq!(table.find_iter(&all[..]).collect::<Vec<_>>(), expected);
+            assert_eq!(table.find_first(&all[..]), expected.first().copied());
+        }
+        let high = TruffleTable::new(b"\xc3");
+        assert_eq!(high.find_first("abcé"), Some(3));
+        assert_eq!(high.find_iter("").next(), None);
+    }
+
+    #[test]
+    fn test_table_union_intersection() {
+        let structural = ShuftiTable::try_new(b"{}[]:,").unwrap();
+        let ws = ShuftiTable::try_new(b" \t\r\n").unwrap();
+        let json = structural.union(&ws).unwrap();
+        for b in 0..=255u8 {
+            let expected = b"{}[]:, \t\r\n".contains(&b);
+            assert_eq!(json.bucket_of(b) != 0, expected, "byte 0x{b:02x}");
+        }
+        let both = json
+            .intersection(&ShuftiTable::try_new(b",;\n").unwrap())
+            .unwrap();
+        assert_eq!(both.members(), b"\n,");
+        assert_eq!(structural.intersection(&ws).unwrap_err(), BuildError::Empty);
+        let spread = ShuftiTable::try_new(b"\x00\x11\x22\x33\x44").unwrap();
+        let more = ShuftiTable::try_new(b"\x55\x66\x77\x88\x99").unwrap();
+        assert_eq!(spread.union(&more).unwrap_err(), BuildError::TooManyBuckets);
+    }
+
+    #[test]
+    fn test_table_complement() {
+        let high = ShuftiTable::try_new(&(0x80..=0xffu8).collect::<Vec<_>>()).unwrap();
+        let ascii = high.complement().unwrap();
+        assert_eq!(ascii.members(), (0..0x80u8).collect::<Vec<_>>());
+        assert_eq!(ascii.complement().unwrap().members(), high.members());
+        let all = ShuftiTable::try_new(&(0..=255u8).collect::<Vec<_>>()).unwrap();
+        assert_eq!(all.complement().unwrap_err(), BuildError::Empty);
+        let err = ShuftiTable::try_new(b"\x00\x11\x22\x33\x44\x55\x66\x77")
+            .unwrap()
+            .complement();
+        assert_eq!(err.unwrap_err(), BuildError::TooManyBuckets);
+    }
+
+    #[test]
+    fn test_find_first_const() {
+        const CLEAN: Option<usize> = find_first_const(&WsMatcher::BUCKET_OF, b"no control bytes");
+        const DIRTY: Option<usize> = find_first_const(&WsMatcher::BUCKET_OF, b"tab\there");
+        assert_eq!((CLEAN, DIRTY), (None, Some(3)));
+        const _: () = assert!(find_first_const(&NulMatcher::BUCKET_OF, b"").is_none());
+        let hay = b"................\r";
+        assert_eq!(
+            find_first_const(&WsMatcher::BUCKET_OF, hay),
+            WsMatcher::find_first(hay)
+        );
+    }
+
+    #[test]
+    fn test_find_first_within() {
+        let hay = b"................\t...";
+        assert_eq!(WsMatcher::find_first_within(hay, 100), Ok(Some(16)));
+        assert_eq!(WsMatcher::find_first_within(hay, 17), Ok(Some(16)));
+        assert_eq!(
+            WsMatcher::find_first_within(hay, 16),
+            Err(LimitReached { resume_at: 16 })
+        );
+        assert_eq!(WsMatcher::find_first_within(b"abc", 3), Ok(None));
+        assert_eq!(
+            WsMatcher::find_first_within(b"abc", 2),
+            Err(LimitReached { resume_at: 2 })
+        );
+        assert_eq!(WsMatcher::find_first_within(b"", 0), Ok(None));
+    }
+
+    #[test]
+    fn test_find_first_with_bucket() {
+        assert_eq!(WsMatcher::find_first_with_bucket(b"ab\rc\t"), Some((2, 2)));
+        assert_eq!(
+            WsMatcher::find_first_with_bucket(b"................\n"),
+            Some((16, 4))
+        );
+        assert_eq!(WsMatcher::find_first_with_bucket(b"abc"), None);
+        assert_eq!(NulMatcher::find_first_with_bucket(b"a\0"), Some((1, 1)));
+    }
+
+    #[test]
+    fn test_find_first_nul_needle_ignores_padding() {
+        assert_eq!(NulMatcher::find_first(b"abc"), None);
+        assert_eq!(NulMatcher::find_first(b"abcdefghijklmnopq\0"), Some(17));
     }
 }
@@ -0,0 +1,89 @@
+//! Offset → (line, column) lookups over a newline table.
+
+use core::ops::Range;
+
+use crate::search::{eq_mask, positions_into};
+
+/// Sorted table of the `\n` positions of a buffer.
+///
+/// Lines and columns are 1-based, matching [`Located`](crate::Located).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    newlines: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    /// Index the newlines of `haystack` with one SIMD pass.
+    pub fn build(haystack: &[u8]) -> Self {
+        let mut newlines = Vec::new();
+        positions_into(haystack, &mut newlines, |c| eq_mask(c, b'\n'));
+        Self {
+            newlines,
+            len: haystack.len(),
+        }
+    }
+
+    /// Number of lines; a trailing `\n` starts a final, empty line.
+    pub fn line_count(&self) -> usize {
+        self.newlines.len() + 1
+    }
+
+    /// Line and column of the byte at `offset`. A `\n` belongs to the line it
+    /// terminates. Offsets up to and including the buffer length are accepted.
+    pub fn lookup(&self, offset: usize) -> Option<(usize, usize)> {
+        if offset > self.len {
+            return None;
+        }
+        let line = self.newlines.partition_point(|&nl| nl < offset);
+        let start = if line == 0 {
+            0
+        } else {
+            self.newlines[line - 1] + 1
+        };
+        Some((line + 1, offset - start + 1))
+    }
+
+    /// Byte range of line `line` (1-based), excluding its `\n`.
+    pub fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        let idx = line.checked_sub(1)?;
+        let start = match idx {
+            0 => 0,
+            _ => *self.newlines.get(idx - 1)? + 1,
+        };
+        let end = self.newlines.get(idx).copied().unwrap_or(self.len);
+        Some(start..end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let hay = b"ab\ncde\n\nlast line that crosses a chunk";
+        let index = LineIndex::build(hay);
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.lookup(0), Some((1, 1)));
+        assert_eq!(index.lookup(2), Some((1, 3)));
+        assert_eq!(index.lookup(3), Some((2, 1)));
+        assert_eq!(index.lookup(7), Some((3, 1)));
+        assert_eq!(index.lookup(30), Some((4, 23)));
+        assert_eq!(index.lookup(hay.len()), Some((4, 31)));
+        assert_eq!(index.lookup(hay.len() + 1), None);
+    }
+
+    #[test]
+    fn test_line_range() {
+        let hay = b"ab\ncde\n\nxyz";
+        let index = LineIndex::build(hay);
+        assert_eq!(index.line_range(1), Some(0..2));
+        assert_eq!(index.line_range(2), Some(3..6));
+        assert_eq!(index.line_range(3), Some(7..7));
+        assert_eq!(index.line_range(4), Some(8..11));
+        assert_eq!(index.line_range(0), None);
+        assert_eq!(index.line_range(5), None);
+        assert_eq!(LineIndex::build(b"").line_range(1), Some(0..0));
+    }
+}
@@ -0,0 +1,162 @@
+//! Typed chunk masks: bit *i* stands for lane (byte) *i* of the chunk.
+
+macro_rules! chunk_mask_type {
+    ($(#[$doc:meta])* $name:ident, $bits:ty) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+        pub struct $name(pub $bits);
+
+        impl $name {
+            /// Number of lanes the mask covers.
+            pub const LANES: usize = <$bits>::BITS as usize;
+
+            /// The raw bits; bit *i* is lane *i*.
+            #[inline(always)]
+            pub const fn bits(self) -> $bits {
+                self.0
+            }
+
+            /// Whether no lane is set.
+            #[inline(always)]
+            pub const fn is_empty(self) -> bool {
+                self.0 == 0
+            }
+
+            /// Number of set lanes.
+            #[inline(always)]
+            pub const fn count(self) -> usize {
+                self.0.count_ones() as usize
+            }
+
+            /// Lowest set lane.
+            #[inline(always)]
+            pub const fn first(self) -> Option<usize> {
+                if self.0 == 0 { None } else { Some(self.0.trailing_zeros() as usize) }
+            }
+
+            /// Highest set lane.
+            #[inline(always)]
+            pub const fn last(self) -> Option<usize> {
+                if self.0 == 0 {
+                    None
+                } else {
+                    Some(Self::LANES - 1 - self.0.leading_zeros() as usize)
+                }
+            }
+
+            /// Iterate the set lanes in increasing order.
+            #[inline(always)]
+            pub fn iter_ones(self) -> MaskOnes {
+                self.positions(0)
+            }
+
+            /// Iterate `offset + lane` for every set lane: positions in the
+            /// haystack when the chunk starts at `offset`.
+            #[inline(always)]
+            pub fn positions(self, offset: usize) -> MaskOnes {
+                MaskOnes {
+                    bits: self.0 as u64,
+                    offset,
+                }
+            }
+        }
+
+        impl From<$bits> for $name {
+            #[inline(always)]
+            fn from(bits: $bits) -> Self {
+                Self(bits)
+            }
+        }
+
+        impl From<$name> for $bits {
+            #[inline(always)]
+            fn from(mask: $name) -> Self {
+                mask.0
+            }
+        }
+
+        impl IntoIterator for $name {
+            type Item = usize;
+            type IntoIter = MaskOnes;
+
+            #[inline(always)]
+            fn into_iter(self) -> MaskOnes {
+                self.iter_ones()
+            }
+        }
+    };
+}
+
+chunk_mask_type!(
+    /// Mask of a 16-byte chunk, as returned by
+    /// [`ShuftiMatch::match_16b`](crate::ShuftiMatch::match_16b).
+    Mask16,
+    u16
+);
+chunk_mask_type!(
+    /// Mask of a 32-byte chunk, as returned by
+    /// [`ShuftiMatch::match_32b`](crate::ShuftiMatch::match_32b).
+    Mask32,
+    u32
+);
+chunk_mask_type!(
+    /// Mask of a 64-byte chunk, as returned by
+    /// [`ShuftiMatch::match_64b`](crate::ShuftiMatch::match_64b).
+    Mask64,
+    u64
+);
+
+/// Iterator over the set lanes of a [`Mask16`], [`Mask32`] or [`Mask64`],
+/// shifted by an offset.
+#[derive(Debug, Clone)]
+pub struct MaskOnes {
+    bits: u64,
+    offset: usize,
+}
+
+impl Iterator for MaskOnes {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        if self.bits == 0 {
+            return None;
+        }
+        let lane = self.bits.trailing_zeros() as usize;
+        self.bits &= self.bits - 1;
+        Some(self.offset + lane)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.bits.count_ones() as usize;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for MaskOnes {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_helpers() {
+        let mask = Mask16(0b1000_0000_0010_0100);
+        assert_eq!(mask.iter_ones().collect::<Vec<_>>(), [2, 5, 15]);
+        assert_eq!(mask.positions(32).collect::<Vec<_>>(), [34, 37, 47]);
+        assert_eq!(
+            (mask.first(), mask.last(), mask.count()),
+            (Some(2), Some(15), 3)
+        );
+        assert_eq!(Mask16::default().first(), None);
+        assert_eq!(Mask16::default().last(), None);
+        assert!(Mask32::default().is_empty());
+
+        let wide = Mask64(1 | 1 << 63);
+        assert_eq!(wide.into_iter().len(), 2);
+        assert_eq!(wide.last(), Some(63));
+        assert_eq!(Mask32(1 << 31).last(), Some(31));
+        assert_eq!(u32::from(Mask32::from(7)), 7);
+    }
+}
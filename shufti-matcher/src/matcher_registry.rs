@@ -0,0 +1,218 @@
+//! Matchers looked up by name, for sets that come from plugins or
+//! configuration.
+
+use std::collections::HashMap;
+
+use crate::{DynShuftiMatcher, Finder, Haystack, ShuftiMatch, ShuftiTable};
+
+/// A matcher held by a [`MatcherRegistry`]: either a compile-time matcher
+/// behind `dyn`, or a runtime-built [`Finder`]. Both search the same way.
+pub enum Registered {
+    /// A [`ShuftiMatch`] type, e.g. a derived matcher handed over by a plugin.
+    Dyn(Box<dyn DynShuftiMatcher + Send + Sync>),
+    /// A set only known at runtime.
+    Finder(Finder),
+}
+
+impl Registered {
+    /// Whether `b` is in the set.
+    pub fn is_member(&self, b: u8) -> bool {
+        match self {
+            Registered::Dyn(m) => m.dyn_is_member(b),
+            Registered::Finder(f) => f.is_member(b),
+        }
+    }
+
+    /// Search `haystack` for the first byte that belongs to the set.
+    pub fn find<H: Haystack + ?Sized>(&self, haystack: &H) -> Option<usize> {
+        match self {
+            Registered::Dyn(m) => m.dyn_find_first(haystack.as_bytes()),
+            Registered::Finder(f) => f.find(haystack),
+        }
+    }
+
+    /// Search `haystack` for the last byte that belongs to the set.
+    pub fn rfind<H: Haystack + ?Sized>(&self, haystack: &H) -> Option<usize> {
+        match self {
+            Registered::Dyn(m) => m.dyn_find_last(haystack.as_bytes()),
+            Registered::Finder(f) => f.rfind(haystack),
+        }
+    }
+
+    /// Number of bytes of `haystack` that belong to the set.
+    pub fn count<H: Haystack + ?Sized>(&self, haystack: &H) -> usize {
+        match self {
+            Registered::Dyn(m) => m.dyn_count(haystack.as_bytes()),
+            Registered::Finder(f) => f.count(haystack),
+        }
+    }
+
+    /// Iterate the positions of every byte of `haystack` in the set.
+    pub fn find_iter<'a, H: Haystack + ?Sized>(
+        &'a self,
+        haystack: &'a H,
+    ) -> Box<dyn Iterator<Item = usize> + 'a> {
+        match self {
+            Registered::Dyn(m) => m.dyn_find_iter(haystack.as_bytes()),
+            Registered::Finder(f) => Box::new(f.find_iter(haystack)),
+        }
+    }
+}
+
+impl core::fmt::Debug for Registered {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Registered::Dyn(m) => f.debug_tuple("Dyn").field(&m.dyn_set()).finish(),
+            Registered::Finder(finder) => f.debug_tuple("Finder").field(finder).finish(),
+        }
+    }
+}
+
+/// Named matchers, e.g. the delimiters of every log format a pipeline
+/// ingests. Registering a name again replaces its matcher.
+///
+/// With the `registry` feature, the maps loaded by
+/// [`registry::from_json`](crate::registry::from_json) and
+/// [`registry::from_toml`](crate::registry::from_toml) collect straight
+/// into a `MatcherRegistry`.
+#[derive(Debug, Default)]
+pub struct MatcherRegistry {
+    entries: HashMap<String, Registered>,
+}
+
+impl MatcherRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the matcher type `M` under `name`, returning the matcher it
+    /// replaces.
+    pub fn register<M>(&mut self, name: impl Into<String>, matcher: M) -> Option<Registered>
+    where
+        M: ShuftiMatch + Send + Sync + 'static,
+    {
+        self.register_dyn(name, Box::new(matcher))
+    }
+
+    /// Register an already boxed matcher, e.g. one returned by a plugin.
+    pub fn register_dyn(
+        &mut self,
+        name: impl Into<String>,
+        matcher: Box<dyn DynShuftiMatcher + Send + Sync>,
+    ) -> Option<Registered> {
+        self.entries.insert(name.into(), Registered::Dyn(matcher))
+    }
+
+    /// Register a runtime-built matcher.
+    pub fn register_finder(
+        &mut self,
+        name: impl Into<String>,
+        finder: Finder,
+    ) -> Option<Registered> {
+        self.entries.insert(name.into(), Registered::Finder(finder))
+    }
+
+    /// Register the bytes `table` accepts, as a [`Finder`].
+    pub fn register_table(
+        &mut self,
+        name: impl Into<String>,
+        table: &ShuftiTable,
+    ) -> Option<Registered> {
+        self.register_finder(name, table.members().into_iter().collect())
+    }
+
+    /// The matcher registered under `name`.
+    pub fn get(&self, name: &str) -> Option<&Registered> {
+        self.entries.get(name)
+    }
+
+    /// Whether a matcher is registered under `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Unregister `name`, returning its matcher.
+    pub fn remove(&mut self, name: &str) -> Option<Registered> {
+        self.entries.remove(name)
+    }
+
+    /// Number of registered matchers.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no matcher is registered.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate `(name, matcher)` pairs, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Registered)> {
+        self.entries.iter().map(|(name, m)| (name.as_str(), m))
+    }
+}
+
+impl Extend<(String, Finder)> for MatcherRegistry {
+    fn extend<I: IntoIterator<Item = (String, Finder)>>(&mut self, finders: I) {
+        for (name, finder) in finders {
+            self.register_finder(name, finder);
+        }
+    }
+}
+
+impl FromIterator<(String, Finder)> for MatcherRegistry {
+    fn from_iter<I: IntoIterator<Item = (String, Finder)>>(finders: I) -> Self {
+        let mut registry = Self::new();
+        registry.extend(finders);
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{WsMatcher, fast_table};
+
+    #[test]
+    fn test_register_and_lookup() {
+        let mut registry = MatcherRegistry::new();
+        assert!(registry.is_empty());
+        registry.register("ws", WsMatcher);
+        registry.register_finder("pipe", Finder::new(b"|"));
+        registry.register_table("comma", &fast_table(b","));
+        assert_eq!(registry.len(), 3);
+
+        let hay = b"a|b,c\td|";
+        assert_eq!(registry.get("ws").unwrap().find(hay), Some(5));
+        let pipe = registry.get("pipe").unwrap();
+        assert_eq!(
+            (pipe.find(hay), pipe.rfind(hay), pipe.count(hay)),
+            (Some(1), Some(7), 2)
+        );
+        let comma = registry.get("comma").unwrap();
+        assert_eq!(comma.find_iter(hay).collect::<Vec<_>>(), [3]);
+        assert!(comma.is_member(b',') && !comma.is_member(b'|'));
+        assert!(registry.get("tab").is_none());
+
+        assert!(registry.register_finder("ws", Finder::new(b" ")).is_some());
+        assert_eq!(registry.get("ws").unwrap().find(hay), None);
+        assert!(matches!(registry.remove("ws"), Some(Registered::Finder(_))));
+        assert!(!registry.contains("ws"));
+        let mut names: Vec<_> = registry.iter().map(|(name, _)| name).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["comma", "pipe"]);
+    }
+
+    #[test]
+    fn test_from_finders() {
+        let registry: MatcherRegistry = [("digits".to_string(), Finder::new(b"0123456789"))]
+            .into_iter()
+            .collect();
+        assert_eq!(registry.get("digits").unwrap().find(b"ab7"), Some(2));
+        assert_eq!(
+            format!("{:?}", MatcherRegistry::new()),
+            "MatcherRegistry { entries: {} }"
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Named [`Finder`]s loaded from a JSON or TOML document (feature `registry`).
+//!
+//! The document maps names to set specifications. A spec is either a string,
+//! whose bytes form the set, or a table with any of these keys:
+//!
+//! - `bytes`: a string of member bytes;
+//! - `ranges`: inclusive byte ranges, written `"a-z"` or `[97, 122]`;
+//! - `classes`: ASCII classes (`digit`, `alpha`, `alnum`, `upper`, `lower`,
+//!   `hex`, `space`, `punct`, `control`, `graphic`, `ascii`);
+//! - `negate`: when `true`, match every byte *not* described by the other keys.
+//!
+//! ```toml
+//! ws = " \t\r\n"
+//! ident = { ranges = ["a-z", "A-Z", "0-9"], bytes = "_" }
+//! not_ident = { ranges = ["a-z", "A-Z", "0-9"], bytes = "_", negate = true }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::{Finder, FinderBuilder};
+
+/// Error from loading a registry, naming the offending entry if there is one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryError {
+    entry: Option<String>,
+    message: String,
+}
+
+impl RegistryError {
+    /// Name of the entry that failed validation; `None` for syntax errors or a
+    /// document that is not a table.
+    pub fn entry(&self) -> Option<&str> {
+        self.entry.as_deref()
+    }
+
+    fn document(message: impl fmt::Display) -> Self {
+        Self {
+            entry: None,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.entry {
+            Some(entry) => write!(f, "entry `{entry}`: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// Parse a JSON registry document.
+pub fn from_json(doc: &str) -> Result<HashMap<String, Finder>, RegistryError> {
+    from_value(serde_json::from_str(doc).map_err(RegistryError::document)?)
+}
+
+/// Parse a TOML registry document.
+pub fn from_toml(doc: &str) -> Result<HashMap<String, Finder>, RegistryError> {
+    let table: toml::Table = doc.parse().map_err(RegistryError::document)?;
+    from_value(serde_json::to_value(table).map_err(RegistryError::document)?)
+}
+
+fn from_value(doc: Value) -> Result<HashMap<String, Finder>, RegistryError> {
+    let Value::Object(entries) = doc else {
+        return Err(RegistryError::document(
+            "registry must be a table of named sets",
+        ));
+    };
+    entries
+        .into_iter()
+        .map(|(name, spec)| match parse_spec(&spec) {
+            Ok(finder) => Ok((name, finder)),
+            Err(message) => Err(RegistryError {
+                entry: Some(name),
+                message,
+            }),
+        })
+        .collect()
+}
+
+fn parse_spec(spec: &Value) -> Result<Finder, String> {
+    let mut builder = FinderBuilder::new();
+    let fields = match spec {
+        Value::String(bytes) => {
+            builder.bytes(bytes.as_bytes());
+            return Ok(builder.build());
+        }
+        Value::Object(fields) => fields,
+        _ => return Err("spec must be a string or a table".into()),
+    };
+
+    let mut negate = false;
+    for (key, value) in fields {
+        match key.as_str() {
+            "bytes" => {
+                let bytes = value.as_str().ok_or("`bytes` must be a string")?;
+                builder.bytes(bytes.as_bytes());
+            }
+            "ranges" => {
+                for range in list(value, "ranges")? {
+                    let (lo, hi) = parse_range(range)?;
+                    builder.range(lo..=hi);
+                }
+            }
+            "classes" => {
+                for class in list(value, "classes")? {
+                    let name = class.as_str().ok_or("class names must be strings")?;
+                    let pred = class_predicate(name).ok_or(format!("unknown class `{name}`"))?;
+                    builder.extend((0..=255u8).filter(|&b| pred(&b)));
+                }
+            }
+            "negate" => negate = value.as_bool().ok_or("`negate` must be a boolean")?,
+            _ => return Err(format!("unknown key `{key}`")),
+        }
+    }
+    let finder = builder.build();
+    Ok(if negate { !finder } else { finder })
+}
+
+fn list<'a>(value: &'a Value, key: &str) -> Result<&'a [Value], String> {
+    value
+        .as_array()
+        .map(Vec::as_slice)
+        .ok_or(format!("`{key}` must be an array"))
+}
+
+/// `"a-z"` (single-byte endpoints) or `[lo, hi]`.
+fn parse_range(range: &Value) -> Result<(u8, u8), String> {
+    let (lo, hi) = match range {
+        Value::String(text) => match text.as_bytes() {
+            &[lo, b'-', hi] => (lo, hi),
+            _ => return Err(format!("range `{text}` is not of the form `a-z`")),
+        },
+        Value::Array(pair) => match pair.as_slice() {
+            [lo, hi] => (byte(lo)?, byte(hi)?),
+            _ => return Err("range arrays must be [lo, hi]".into()),
+        },
+        _ => return Err("ranges must be strings or [lo, hi] arrays".into()),
+    };
+    if lo > hi {
+        return Err(format!("range {lo:#04x}-{hi:#04x} is reversed"));
+    }
+    Ok((lo, hi))
+}
+
+fn byte(value: &Value) -> Result<u8, String> {
+    value
+        .as_u64()
+        .and_then(|n| u8::try_from(n).ok())
+        .ok_or(format!("range endpoint {value} is not a byte"))
+}
+
+fn class_predicate(name: &str) -> Option<fn(&u8) -> bool> {
+    Some(match name {
+        "digit" => u8::is_ascii_digit,
+        "alpha" => u8::is_ascii_alphabetic,
+        "alnum" => u8::is_ascii_alphanumeric,
+        "upper" => u8::is_ascii_uppercase,
+        "lower" => u8::is_ascii_lowercase,
+        "hex" => u8::is_ascii_hexdigit,
+        "space" => u8::is_ascii_whitespace,
+        "punct" => u8::is_ascii_punctuation,
+        "control" => u8::is_ascii_control,
+        "graphic" => u8::is_ascii_graphic,
+        "ascii" => u8::is_ascii,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_toml() {
+        let sets = from_toml(
+            r#"
+            ws = " \t\r\n"
+            ident = { ranges = ["a-z", "A-Z", [48, 57]], bytes = "_" }
+            not_ident = { ranges = ["a-z", "A-Z", "0-9"], bytes = "_", negate = true }
+            hex = { classes = ["hex"] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(sets.len(), 4);
+        assert_eq!(sets["ws"].find(b"ab\tc"), Some(2));
+        assert_eq!(sets["ident"].find(b"+-_x"), Some(2));
+        assert_eq!(sets["ident"].find(b"++9"), Some(2));
+        assert_eq!(sets["not_ident"].find(b"abc_9 x"), Some(5));
+        assert_eq!(sets["hex"].find(b"xyzF"), Some(3));
+    }
+
+    #[test]
+    fn test_from_json() {
+        let sets = from_json(r#"{"delims": ",;", "digits": {"classes": ["digit"]}}"#).unwrap();
+        assert_eq!(sets["delims"].find(b"a;b"), Some(1));
+        assert_eq!(sets["digits"].find(b"ab7"), Some(2));
+    }
+
+    #[test]
+    fn test_into_matcher_registry() {
+        let registry: crate::MatcherRegistry =
+            from_toml(r#"delims = ",;""#).unwrap().into_iter().collect();
+        assert_eq!(registry.get("delims").unwrap().find(b"a;b"), Some(1));
+    }
+
+    #[test]
+    fn test_errors_name_the_entry() {
+        let cases = [
+            (
+                r#"{"a": {"classes": ["digits"]}}"#,
+                "entry `a`: unknown class `digits`",
+            ),
+            (
+                r#"{"b": {"ranges": ["z-a"]}}"#,
+                "entry `b`: range 0x7a-0x61 is reversed",
+            ),
+            (
+                r#"{"c": {"ranges": ["abc"]}}"#,
+                "entry `c`: range `abc` is not of the form `a-z`",
+            ),
+            (
+                r#"{"d": {"ranges": [[0, 300]]}}"#,
+                "entry `d`: range endpoint 300 is not a byte",
+            ),
+            (r#"{"e": {"bites": "x"}}"#, "entry `e`: unknown key `bites`"),
+            (r#"{"f": 3}"#, "entry `f`: spec must be a string or a table"),
+        ];
+        for (doc, message) in cases {
+            let err = from_json(doc).unwrap_err();
+            assert_eq!(err.to_string(), message);
+            assert!(err.entry().is_some());
+        }
+
+        let err = from_json("[1]").unwrap_err();
+        assert_eq!(err.entry(), None);
+        assert!(from_toml("x = ").unwrap_err().entry().is_none());
+    }
+}
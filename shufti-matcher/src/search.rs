@@ -0,0 +1,712 @@
+//! Mask-driven search loops shared by every entry point.
+//!
+//! Each routine is generic over the 16-byte chunk kernel (`Fn(&[u8; 16]) -> u16`)
+//! so the trait defaults, tables and higher-level modules all run the same loop.
+
+use std::borrow::Cow;
+use std::ops::{ControlFlow, Range};
+
+/// Bitmask selecting the lowest `len` lanes of a 16-lane chunk mask; `len`
+/// saturates at 16.
+#[inline(always)]
+pub(crate) fn lane_mask(len: usize) -> u16 {
+    ((1u32 << len.min(16)) - 1) as u16
+}
+
+/// Bitmask of the lanes of `chunk` equal to `byte`.
+#[inline(always)]
+pub(crate) fn eq_mask(chunk: &[u8; 16], byte: u8) -> u16 {
+    let mut mask = 0u16;
+    for (i, &b) in chunk.iter().enumerate() {
+        mask |= ((b == byte) as u16) << i;
+    }
+    mask
+}
+
+/// The (possibly short) chunk of `haystack` starting at `offset`, zero-padded
+/// to 16 bytes, and the number of real bytes in it.
+#[inline(always)]
+pub(crate) fn load_chunk(haystack: &[u8], offset: usize) -> ([u8; 16], usize) {
+    let rest = &haystack[offset..];
+    let len = rest.len().min(16);
+    let mut buf = [0u8; 16];
+    buf[..len].copy_from_slice(&rest[..len]);
+    (buf, len)
+}
+
+/// Run `mask_16b` over the (possibly short) chunk starting at `offset`.
+///
+/// Short chunks are zero-padded; lanes past the end of `haystack` are cleared
+/// so a `\0` needle never reports a padding byte, and an `offset` past the end
+/// yields an empty mask.
+#[inline(always)]
+pub(crate) fn chunk_mask<M>(haystack: &[u8], offset: usize, mask_16b: &M) -> u16
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let rest = haystack.get(offset..).unwrap_or_default();
+    if let Some(chunk) = rest.first_chunk() {
+        mask_16b(chunk)
+    } else {
+        let mut buf = [0u8; 16];
+        buf[..rest.len()].copy_from_slice(rest);
+        mask_16b(&buf) & lane_mask(rest.len())
+    }
+}
+
+/// Position of the first member byte at or after `start`.
+///
+/// The loop body handles full chunks only; the short epilogue is outlined into
+/// [`find_in_tail`] so the hot loop stays small.
+#[inline]
+pub(crate) fn find_from<M>(haystack: &[u8], start: usize, mask_16b: M) -> Option<usize>
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let rest = haystack.get(start..)?;
+    let mut chunks = rest.chunks_exact(16);
+    for (i, chunk) in chunks.by_ref().enumerate() {
+        let mask = mask_16b(chunk.try_into().unwrap());
+        if mask != 0 {
+            return Some(start + i * 16 + mask.trailing_zeros() as usize);
+        }
+    }
+    let tail = chunks.remainder();
+    find_in_tail(tail, &mask_16b).map(|pos| haystack.len() - tail.len() + pos)
+}
+
+/// Position of the last member byte of `haystack`.
+///
+/// Scans 16-byte chunks backwards from the end; the short remainder is at the
+/// front and handled last.
+#[inline]
+pub(crate) fn rfind<M>(haystack: &[u8], mask_16b: M) -> Option<usize>
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut chunks = haystack.rchunks_exact(16);
+    let mut end = haystack.len();
+    for chunk in chunks.by_ref() {
+        end -= 16;
+        let mask = mask_16b(chunk.try_into().unwrap());
+        if mask != 0 {
+            return Some(end + 15 - mask.leading_zeros() as usize);
+        }
+    }
+    if chunks.remainder().is_empty() {
+        return None;
+    }
+    let mask = chunk_mask(chunks.remainder(), 0, &mask_16b);
+    (mask != 0).then(|| 15 - mask.leading_zeros() as usize)
+}
+
+/// First member byte of a short (< 16 bytes) epilogue.
+#[cold]
+#[inline(never)]
+fn find_in_tail<M>(tail: &[u8], mask_16b: &M) -> Option<usize>
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    if tail.is_empty() {
+        return None;
+    }
+    let mask = chunk_mask(tail, 0, mask_16b);
+    (mask != 0).then(|| mask.trailing_zeros() as usize)
+}
+
+/// [`find_from`] from 0 that asks `first_16b` for the first lane of every
+/// full chunk and only uses `mask_16b` for the padded epilogue.
+#[inline]
+pub(crate) fn find_first_lane<F, M>(haystack: &[u8], first_16b: F, mask_16b: M) -> Option<usize>
+where
+    F: Fn(&[u8; 16]) -> Option<u32>,
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut chunks = haystack.chunks_exact(16);
+    for (i, chunk) in chunks.by_ref().enumerate() {
+        let chunk: &[u8; 16] = chunk.try_into().unwrap();
+        if let Some(lane) = first_16b(chunk) {
+            return Some(i * 16 + lane as usize);
+        }
+    }
+    let tail = chunks.remainder();
+    find_in_tail(tail, &mask_16b).map(|pos| haystack.len() - tail.len() + pos)
+}
+
+/// Lanes of the chunk starting at `offset` that lie on the grid
+/// `phase + k * stride`, given that `offset - phase` is a multiple of 16.
+///
+/// A zero `stride` is treated as 1 and an `offset` before `phase` as `phase`.
+#[inline(always)]
+pub(crate) fn stride_mask(offset: usize, phase: usize, stride: usize) -> u16 {
+    let stride = stride.max(1);
+    let mut lane = (stride - offset.saturating_sub(phase) % stride) % stride;
+    let mut mask = 0u16;
+    while lane < 16 {
+        mask |= 1 << lane;
+        lane += stride;
+    }
+    mask
+}
+
+/// First occurrence of any of the first `len` of `needles` via `memchr`;
+/// `len` is clamped to 1..=3.
+#[cfg(feature = "memchr-fallback")]
+#[inline(always)]
+pub(crate) fn memchr_find(needles: [u8; 3], len: usize, haystack: &[u8]) -> Option<usize> {
+    let [a, b, c] = needles;
+    match len {
+        0 | 1 => memchr::memchr(a, haystack),
+        2 => memchr::memchr2(a, b, haystack),
+        _ => memchr::memchr3(a, b, c, haystack),
+    }
+}
+
+/// Position of the first member byte before the NUL terminator at `ptr`.
+///
+/// Bytes are copied one at a time into a 16-byte block and each full block is
+/// classified at once, so nothing past the terminator is ever read.
+///
+/// # Safety
+/// `ptr` must point to a readable, NUL-terminated byte sequence.
+#[cfg(not(feature = "no-unsafe"))]
+#[inline(always)]
+pub(crate) unsafe fn find_nul_terminated<M>(ptr: *const u8, mask_16b: M) -> Option<usize>
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut buf = [0u8; 16];
+    let mut offset = 0;
+    loop {
+        for (i, slot) in buf.iter_mut().enumerate() {
+            // SAFETY: every byte up to and including the terminator is
+            // readable, and the scan stops at the terminator.
+            let b = unsafe { *ptr.add(offset + i) };
+            if b == 0 {
+                let mask = mask_16b(&buf) & lane_mask(i);
+                return (mask != 0).then(|| offset + mask.trailing_zeros() as usize);
+            }
+            *slot = b;
+        }
+        let mask = mask_16b(&buf);
+        if mask != 0 {
+            return Some(offset + mask.trailing_zeros() as usize);
+        }
+        offset += 16;
+    }
+}
+
+/// Replace the contents of `out` with one bit per byte of `haystack`, packed
+/// little-endian into 64-bit words. Bits past the end of the input are zero.
+pub(crate) fn bitmap_into<M>(haystack: &[u8], out: &mut Vec<u64>, mask_16b: M)
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    out.clear();
+    out.reserve(haystack.len().div_ceil(64));
+    let mut offset = 0;
+    while offset < haystack.len() {
+        let mut word = 0u64;
+        for lane in 0..4 {
+            let at = offset + lane * 16;
+            if at >= haystack.len() {
+                break;
+            }
+            word |= (chunk_mask(haystack, at, &mask_16b) as u64) << (lane * 16);
+        }
+        out.push(word);
+        offset += 64;
+    }
+}
+
+/// Append `offset + i` to `out` for every set bit `i` of `mask`, in order.
+///
+/// With BMI2 each position is decoded independently as
+/// `tzcnt(pdep(1 << k, mask))`, so dense masks don't serialize on the
+/// clear-lowest-bit dependency chain.
+#[inline(always)]
+pub(crate) fn push_positions(out: &mut Vec<usize>, offset: usize, mask: u16) {
+    cfg_if::cfg_if! {
+        if #[cfg(all(target_arch = "x86_64", target_feature = "bmi2", not(feature = "no-unsafe")))] {
+            use core::arch::x86_64::{_pdep_u32, _tzcnt_u32};
+            let n = mask.count_ones() as usize;
+            out.reserve(n);
+            let spare = &mut out.spare_capacity_mut()[..n];
+            for (k, slot) in spare.iter_mut().enumerate() {
+                // SAFETY: bmi1/bmi2 are enabled for this target.
+                let bit = unsafe { _tzcnt_u32(_pdep_u32(1 << k, mask as u32)) };
+                slot.write(offset + bit as usize);
+            }
+            // SAFETY: the first `n` spare slots were initialized above.
+            unsafe { out.set_len(out.len() + n) };
+        } else {
+            push_positions_portable(out, offset, mask);
+        }
+    }
+}
+
+#[inline(always)]
+#[allow(dead_code)]
+fn push_positions_portable(out: &mut Vec<usize>, offset: usize, mut mask: u16) {
+    while mask != 0 {
+        out.push(offset + mask.trailing_zeros() as usize);
+        mask &= mask - 1;
+    }
+}
+
+/// OR one bit per byte of `haystack` into `words` (at least
+/// `haystack.len().div_ceil(usize::BITS)` words, least significant bit
+/// first). Chunk masks never straddle a word since both widths are multiples
+/// of 16.
+#[cfg(any(feature = "fixedbitset", feature = "bitvec"))]
+pub(crate) fn fill_usize_words<M>(haystack: &[u8], words: &mut [usize], mask_16b: M)
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    const BITS: usize = usize::BITS as usize;
+    let mut offset = 0;
+    while offset < haystack.len() {
+        let mask = chunk_mask(haystack, offset, &mask_16b) as usize;
+        words[offset / BITS] |= mask << (offset % BITS);
+        offset += 16;
+    }
+}
+
+/// Number of member bytes in `haystack`: the popcounts of every chunk mask,
+/// summed without an early exit.
+#[inline]
+pub(crate) fn count<M>(haystack: &[u8], mask_16b: M) -> usize
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut chunks = haystack.chunks_exact(16);
+    let mut total = 0;
+    for chunk in chunks.by_ref() {
+        total += mask_16b(chunk.try_into().unwrap()).count_ones() as usize;
+    }
+    if !chunks.remainder().is_empty() {
+        total += chunk_mask(chunks.remainder(), 0, &mask_16b).count_ones() as usize;
+    }
+    total
+}
+
+/// Position of the `n`th (0-based) member byte. Whole chunks are skipped by
+/// popcount; only the chunk holding the answer is bit-iterated.
+#[inline]
+pub(crate) fn find_nth<M>(haystack: &[u8], mut n: usize, mask_16b: M) -> Option<usize>
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut offset = 0;
+    while offset < haystack.len() {
+        let mut mask = chunk_mask(haystack, offset, &mask_16b);
+        let ones = mask.count_ones() as usize;
+        if n < ones {
+            for _ in 0..n {
+                mask &= mask - 1;
+            }
+            return Some(offset + mask.trailing_zeros() as usize);
+        }
+        n -= ones;
+        offset += 16;
+    }
+    None
+}
+
+/// Whether every byte of `haystack` is a member, by AND-reducing the chunk
+/// masks over the whole input (no branch per chunk).
+#[inline]
+pub(crate) fn all<M>(haystack: &[u8], mask_16b: M) -> bool
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut chunks = haystack.chunks_exact(16);
+    let mut acc = u16::MAX;
+    for chunk in chunks.by_ref() {
+        acc &= mask_16b(chunk.try_into().unwrap());
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        acc &= chunk_mask(tail, 0, &mask_16b) | !lane_mask(tail.len());
+    }
+    acc == u16::MAX
+}
+
+/// Write the positions of the first `out.len()` member bytes into `out`;
+/// returns how many were written. Stops scanning once `out` is full.
+pub(crate) fn find_n_into<M>(haystack: &[u8], out: &mut [usize], mask_16b: M) -> usize
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut found = 0;
+    let mut offset = 0;
+    while offset < haystack.len() && found < out.len() {
+        let mut mask = chunk_mask(haystack, offset, &mask_16b);
+        while mask != 0 && found < out.len() {
+            out[found] = offset + mask.trailing_zeros() as usize;
+            found += 1;
+            mask &= mask - 1;
+        }
+        offset += 16;
+    }
+    found
+}
+
+/// Hand the 64-bit mask of every 64-byte block of `haystack` to `visit`,
+/// with the block's offset, until it breaks. Lanes past the end of a short
+/// last block are clear.
+pub(crate) fn for_each_block<M, B, F>(haystack: &[u8], mask_16b: M, mut visit: F) -> ControlFlow<B>
+where
+    M: Fn(&[u8; 16]) -> u16,
+    F: FnMut(usize, u64) -> ControlFlow<B>,
+{
+    let mut offset = 0;
+    while offset < haystack.len() {
+        let mut mask = 0u64;
+        for lane in (0..64)
+            .step_by(16)
+            .take_while(|lane| offset + lane < haystack.len())
+        {
+            mask |= (chunk_mask(haystack, offset + lane, &mask_16b) as u64) << lane;
+        }
+        visit(offset, mask)?;
+        offset += 64;
+    }
+    ControlFlow::Continue(())
+}
+
+/// First `i` where `haystack[i]` is in set A and `haystack[i + 1]` in set B.
+///
+/// Within a chunk the B mask is shifted down one lane and AND-ed with the A
+/// mask; an A byte in the top lane is carried over to lane 0 of the next
+/// chunk's B mask.
+pub(crate) fn find_pair<A, B>(haystack: &[u8], mask_a: A, mask_b: B) -> Option<usize>
+where
+    A: Fn(&[u8; 16]) -> u16,
+    B: Fn(&[u8; 16]) -> u16,
+{
+    let mut carry = false;
+    let mut offset = 0;
+    while offset < haystack.len() {
+        let a = chunk_mask(haystack, offset, &mask_a);
+        let b = chunk_mask(haystack, offset, &mask_b);
+        if carry && b & 1 != 0 {
+            return Some(offset - 1);
+        }
+        let hits = a & (b >> 1);
+        if hits != 0 {
+            return Some(offset + hits.trailing_zeros() as usize);
+        }
+        carry = a >> 15 != 0;
+        offset += 16;
+    }
+    None
+}
+
+/// Append the position of every member byte of `haystack` to `out`.
+pub(crate) fn positions_into<M>(haystack: &[u8], out: &mut Vec<usize>, mask_16b: M)
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut offset = 0;
+    while offset < haystack.len() {
+        push_positions(out, offset, chunk_mask(haystack, offset, &mask_16b));
+        offset += 16;
+    }
+}
+
+/// Append every member byte of `haystack` to `out`, in order.
+pub(crate) fn matched_bytes_into<M>(haystack: &[u8], out: &mut Vec<u8>, mask_16b: M)
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut offset = 0;
+    while offset < haystack.len() {
+        let (chunk, _) = load_chunk(haystack, offset);
+        let mut mask = chunk_mask(haystack, offset, &mask_16b);
+        out.reserve(mask.count_ones() as usize);
+        while mask != 0 {
+            out.push(chunk[mask.trailing_zeros() as usize]);
+            mask &= mask - 1;
+        }
+        offset += 16;
+    }
+}
+
+/// Number of member bytes in each `block_size`-byte block of `haystack` (the
+/// last block may be short). A `block_size` of zero yields no blocks.
+pub(crate) fn block_counts<M>(haystack: &[u8], block_size: usize, mask_16b: M) -> Vec<u32>
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    if block_size == 0 {
+        return Vec::new();
+    }
+    let mut counts = vec![0u32; haystack.len().div_ceil(block_size)];
+    let mut offset = 0;
+    while offset < haystack.len() {
+        let mask = chunk_mask(haystack, offset, &mask_16b);
+        let len = (haystack.len() - offset).min(16);
+        // Split the chunk at block boundaries.
+        let mut lane = 0;
+        while lane < len && mask >> lane != 0 {
+            let pos = offset + lane;
+            let take = (len - lane).min(block_size - pos % block_size);
+            counts[pos / block_size] += ((mask >> lane) & lane_mask(take)).count_ones();
+            lane += take;
+        }
+        offset += 16;
+    }
+    counts
+}
+
+/// The first maximal run of member bytes starting at or after `start`.
+#[inline(always)]
+pub(crate) fn run_from<M>(haystack: &[u8], start: usize, mask_16b: &M) -> Option<Range<usize>>
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let begin = find_from(haystack, start, mask_16b)?;
+    let end = find_from(haystack, begin, |c: &[u8; 16]| !mask_16b(c)).unwrap_or(haystack.len());
+    Some(begin..end)
+}
+
+/// Replace each maximal run of member bytes with a single `with`, borrowing
+/// when nothing changes.
+pub(crate) fn squeeze<'a, M>(haystack: &'a [u8], with: u8, mask_16b: M) -> Cow<'a, [u8]>
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut out: Option<Vec<u8>> = None;
+    let mut copied = 0;
+    let mut pos = 0;
+    while let Some(run) = run_from(haystack, pos, &mask_16b) {
+        pos = run.end;
+        if run.len() == 1 && haystack[run.start] == with {
+            continue;
+        }
+        let buf = out.get_or_insert_with(|| Vec::with_capacity(haystack.len()));
+        buf.extend_from_slice(&haystack[copied..run.start]);
+        buf.push(with);
+        copied = run.end;
+    }
+    match out {
+        None => Cow::Borrowed(haystack),
+        Some(mut buf) => {
+            buf.extend_from_slice(&haystack[copied..]);
+            Cow::Owned(buf)
+        }
+    }
+}
+
+/// Append `haystack` to `out` with each maximal run of member bytes replaced
+/// by a single `with`.
+pub(crate) fn collapse_into<M>(haystack: &[u8], out: &mut Vec<u8>, with: u8, mask_16b: M)
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    out.reserve(haystack.len());
+    let mut copied = 0;
+    while let Some(run) = run_from(haystack, copied, &mask_16b) {
+        out.extend_from_slice(&haystack[copied..run.start]);
+        out.push(with);
+        copied = run.end;
+    }
+    out.extend_from_slice(&haystack[copied..]);
+}
+
+/// In-place [`squeeze`].
+pub(crate) fn squeeze_in_place<M>(buf: &mut Vec<u8>, with: u8, mask_16b: M)
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut write = 0;
+    let mut read = 0;
+    while let Some(run) = run_from(buf, read, &mask_16b) {
+        buf.copy_within(read..run.start, write);
+        write += run.start - read;
+        buf[write] = with;
+        write += 1;
+        read = run.end;
+    }
+    let len = buf.len();
+    buf.copy_within(read..len, write);
+    buf.truncate(write + len - read);
+}
+
+/// Copy `haystack` to `out`, bulk-copying the spans between member bytes
+/// and handing each member byte to `emit` instead.
+pub(crate) fn rewrite_into<M, E>(haystack: &[u8], out: &mut Vec<u8>, mask_16b: M, mut emit: E)
+where
+    M: Fn(&[u8; 16]) -> u16,
+    E: FnMut(u8, &mut Vec<u8>),
+{
+    out.reserve(haystack.len());
+    let mut copied = 0;
+    while let Some(pos) = find_from(haystack, copied, &mask_16b) {
+        out.extend_from_slice(&haystack[copied..pos]);
+        emit(haystack[pos], out);
+        copied = pos + 1;
+    }
+    out.extend_from_slice(&haystack[copied..]);
+}
+
+/// For every 8-bit lane mask, the indices of its set lanes packed into the
+/// low bytes of a `u64` (a `pshufb`/`tbl` control for an 8-byte half).
+const COMPRESS_8: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut mask = 0;
+    while mask < 256 {
+        let (mut packed, mut n, mut lane) = (0u64, 0, 0);
+        while lane < 8 {
+            if mask >> lane & 1 != 0 {
+                packed |= (lane as u64) << (8 * n);
+                n += 1;
+            }
+            lane += 1;
+        }
+        table[mask] = packed;
+        mask += 1;
+    }
+    table
+};
+
+/// Move the lanes of `chunk` selected by `keep` to the front of `out`, in
+/// order; returns how many there are. Each 8-byte half is compressed with one
+/// shuffle driven by [`COMPRESS_8`], and the halves are then joined.
+#[inline(always)]
+pub(crate) fn compress_chunk(chunk: &[u8; 16], keep: u16, out: &mut [u8; 16]) -> usize {
+    let (lo, hi) = (keep as u8, (keep >> 8) as u8);
+    let halves = compress_halves(chunk, lo, hi);
+    let n_lo = lo.count_ones() as usize;
+    out[..8].copy_from_slice(&halves[..8]);
+    out[n_lo..n_lo + 8].copy_from_slice(&halves[8..]);
+    n_lo + hi.count_ones() as usize
+}
+
+/// Compress each 8-byte half of `chunk` by its lane mask (`lo`, `hi`); the
+/// kept bytes of each half land at the front of that half.
+#[inline(always)]
+fn compress_halves(chunk: &[u8; 16], lo: u8, hi: u8) -> [u8; 16] {
+    cfg_if::cfg_if! {
+        if #[cfg(all(target_arch = "x86_64", target_feature = "ssse3", not(feature = "no-unsafe")))] {
+            use core::arch::x86_64::*;
+            let control = COMPRESS_8[lo as usize] as i64;
+            let control_hi = (COMPRESS_8[hi as usize] + 0x0808_0808_0808_0808) as i64;
+            // SAFETY: ssse3 is enabled for this target; the load is an
+            // unaligned 16-byte read of a 16-byte array.
+            unsafe {
+                let v = _mm_loadu_si128(chunk.as_ptr().cast());
+                let v = _mm_shuffle_epi8(v, _mm_set_epi64x(control_hi, control));
+                core::mem::transmute::<__m128i, [u8; 16]>(v)
+            }
+        } else {
+            compress_halves_portable(chunk, lo, hi)
+        }
+    }
+}
+
+#[inline(always)]
+#[allow(dead_code)]
+fn compress_halves_portable(chunk: &[u8; 16], lo: u8, hi: u8) -> [u8; 16] {
+    let (control, control_hi) = (COMPRESS_8[lo as usize], COMPRESS_8[hi as usize]);
+    let mut halves = [0u8; 16];
+    for i in 0..8 {
+        halves[i] = chunk[(control >> (8 * i)) as usize & 7];
+        halves[8 + i] = chunk[8 + ((control_hi >> (8 * i)) as usize & 7)];
+    }
+    halves
+}
+
+/// Append the non-member bytes of `haystack` to `out`, compressing each chunk
+/// with [`compress_chunk`].
+pub(crate) fn remove_into<M>(haystack: &[u8], out: &mut Vec<u8>, mask_16b: M)
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    out.reserve(haystack.len());
+    let mut kept = [0u8; 16];
+    let mut offset = 0;
+    while offset < haystack.len() {
+        let (chunk, len) = load_chunk(haystack, offset);
+        let keep = !mask_16b(&chunk) & lane_mask(len);
+        let n = compress_chunk(&chunk, keep, &mut kept);
+        out.extend_from_slice(&kept[..n]);
+        offset += 16;
+    }
+}
+
+/// In-place [`remove_into`]: the write cursor never passes the read cursor,
+/// and each chunk is copied out before it is overwritten.
+pub(crate) fn remove_in_place<M>(buf: &mut Vec<u8>, mask_16b: M)
+where
+    M: Fn(&[u8; 16]) -> u16,
+{
+    let mut kept = [0u8; 16];
+    let mut write = 0;
+    let mut read = 0;
+    while read < buf.len() {
+        let (chunk, len) = load_chunk(buf, read);
+        let keep = !mask_16b(&chunk) & lane_mask(len);
+        let n = compress_chunk(&chunk, keep, &mut kept);
+        buf[write..write + n].copy_from_slice(&kept[..n]);
+        write += n;
+        read += 16;
+    }
+    buf.truncate(write);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_positions() {
+        for mask in [0u16, 1, 0x8000, 0xffff, 0xa5a5, 0x0f0f, 0x1234] {
+            let (mut fast, mut portable) = (vec![7], vec![7]);
+            push_positions(&mut fast, 32, mask);
+            push_positions_portable(&mut portable, 32, mask);
+            assert_eq!(fast, portable, "mask {mask:#06x}");
+        }
+        let mut out = Vec::new();
+        push_positions(&mut out, 16, 0b1001);
+        assert_eq!(out, [16, 19]);
+    }
+
+    #[test]
+    fn test_compress_chunk() {
+        let chunk: [u8; 16] = core::array::from_fn(|i| b'a' + i as u8);
+        for keep in [
+            0u16, 1, 0x8000, 0xffff, 0xa5a5, 0x00ff, 0xff00, 0x1234, 0x0180,
+        ] {
+            let mut out = [0u8; 16];
+            let n = compress_chunk(&chunk, keep, &mut out);
+            let expected: Vec<u8> = (0..16)
+                .filter(|i| keep >> i & 1 != 0)
+                .map(|i| chunk[i])
+                .collect();
+            assert_eq!(&out[..n], expected, "keep {keep:#06x}");
+            let halves = compress_halves_portable(&chunk, keep as u8, (keep >> 8) as u8);
+            let n_lo = (keep as u8).count_ones() as usize;
+            assert_eq!(halves[..n_lo], expected[..n_lo]);
+            assert_eq!(halves[8..8 + n - n_lo], expected[n_lo..]);
+        }
+    }
+
+    #[cfg(feature = "memchr-fallback")]
+    #[test]
+    fn test_memchr_find_matches_find_from() {
+        let haystack = b"the quick brown fox jumps over the lazy dog\0";
+        for (needles, len) in [(*b"z\0\0", 1), (*b"\0zz", 1), (*b"qxx", 2), (*b"yz\0", 3)] {
+            let scan = find_from(haystack, 0, |c: &[u8; 16]| {
+                needles[..len].iter().fold(0, |m, &n| m | eq_mask(c, n))
+            });
+            assert_eq!(
+                memchr_find(needles, len, haystack),
+                scan,
+                "needles {needles:?}"
+            );
+        }
+        assert_eq!(memchr_find(*b"!!!", 1, haystack), None);
+    }
+}
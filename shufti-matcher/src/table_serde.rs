@@ -0,0 +1,100 @@
+//! `Serialize`/`Deserialize` for [`ShuftiTable`] (feature `serde`).
+//!
+//! Tables are written as `{ version, low_tab, high_tab, bit_mask }`.
+//! Deserialization rejects other versions and tables that cannot have come
+//! from a builder: an empty `bit_mask`, or a bucket in `bit_mask` missing
+//! from either nibble table.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::ShuftiTable;
+
+/// Version of the serialized layout; bumped on incompatible changes.
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TableRepr {
+    version: u32,
+    low_tab: [u8; 16],
+    high_tab: [u8; 16],
+    bit_mask: u8,
+}
+
+impl Serialize for ShuftiTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TableRepr {
+            version: FORMAT_VERSION,
+            low_tab: self.low_tab,
+            high_tab: self.high_tab,
+            bit_mask: self.bit_mask,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ShuftiTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = TableRepr::deserialize(deserializer)?;
+        if repr.version != FORMAT_VERSION {
+            return Err(D::Error::custom(format_args!(
+                "unsupported shufti table version {} (expected {FORMAT_VERSION})",
+                repr.version
+            )));
+        }
+        if repr.bit_mask == 0 {
+            return Err(D::Error::custom("shufti table has an empty bit_mask"));
+        }
+        let used = |tab: &[u8; 16]| tab.iter().fold(0, |acc, &b| acc | b);
+        let dead = repr.bit_mask & !(used(&repr.low_tab) & used(&repr.high_tab));
+        if dead != 0 {
+            return Err(D::Error::custom(format_args!(
+                "shufti table bucket mask {dead:#04x} is missing from its nibble tables"
+            )));
+        }
+        Ok(ShuftiTable {
+            low_tab: repr.low_tab,
+            high_tab: repr.high_tab,
+            bit_mask: repr.bit_mask,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ShuftiTable;
+
+    #[test]
+    fn test_round_trip() {
+        let table = ShuftiTable::try_new(b"{}[]:, \t\r\n").unwrap();
+        let json = serde_json::to_string(&table).unwrap();
+        assert!(json.starts_with(r#"{"version":1,"low_tab":["#));
+        let back: ShuftiTable = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.verify(&table.members()), Ok(()));
+        assert_eq!(back.bit_mask, table.bit_mask);
+    }
+
+    #[test]
+    fn test_rejects_invalid_tables() {
+        let zeros = "[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]";
+        let one = "[0,1,0,0,0,0,0,0,0,0,0,0,0,0,0,0]";
+        let doc = |version: u32, high: &str, mask: u8| {
+            format!(
+                r#"{{"version":{version},"low_tab":{one},"high_tab":{high},"bit_mask":{mask}}}"#
+            )
+        };
+        let parse = |doc: String| {
+            serde_json::from_str::<ShuftiTable>(&doc)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert!(serde_json::from_str::<ShuftiTable>(&doc(1, one, 1)).is_ok());
+        assert!(parse(doc(2, one, 1)).contains("unsupported shufti table version 2"));
+        assert!(parse(doc(1, one, 0)).contains("empty bit_mask"));
+        assert!(parse(doc(1, zeros, 1)).contains("mask 0x01 is missing"));
+        assert!(parse(doc(1, one, 3)).contains("mask 0x02 is missing"));
+        assert!(parse(r#"{"low_tab":[]}"#.to_string()).contains("invalid length"));
+    }
+}
@@ -0,0 +1,120 @@
+//! Link-time check that the non-allocating search entry points cannot panic.
+//!
+//! Every call runs under a guard whose drop glue, reached only while
+//! unwinding, calls a symbol that is never defined. Built with
+//! `cargo test -p shufti-matcher --profile no-panic --test no_panic`, the
+//! optimizer drops that landing pad exactly when no panic is reachable, so a
+//! panicking path in any entry point below turns into a link error naming the
+//! call. Debug builds keep the guard out and just run the calls.
+
+use shufti_matcher::{Finder, ShuftiMatch, ShuftiMatcher, TruffleTable};
+
+#[derive(ShuftiMatcher)]
+#[shufti(set = "\t\r\n")]
+struct Ws;
+
+#[derive(ShuftiMatcher)]
+#[shufti(set = "\0")]
+struct Nul;
+
+#[derive(ShuftiMatcher)]
+#[shufti(set = "0123456789abcdefghijklmnopqrstuvwxyz")]
+struct Wide;
+
+/// Evaluate `$call`; in optimized builds, fail to link, naming the call, if it
+/// can unwind.
+macro_rules! no_panic {
+    ($call:expr) => {{
+        #[cfg(not(debug_assertions))]
+        struct Guard;
+        #[cfg(not(debug_assertions))]
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                unsafe extern "C" {
+                    #[link_name = concat!("\n\nshufti-matcher: `", stringify!($call), "` can panic\n\n")]
+                    fn panic_is_reachable() -> !;
+                }
+                unsafe { panic_is_reachable() }
+            }
+        }
+        #[cfg(not(debug_assertions))]
+        let guard = Guard;
+        let out = $call;
+        #[cfg(not(debug_assertions))]
+        core::mem::forget(guard);
+        out
+    }};
+}
+
+#[inline(never)]
+fn derived<M: ShuftiMatch>(hay: &[u8], n: usize) -> usize {
+    let mut acc = 0;
+    acc += no_panic!(M::find_first(hay)).unwrap_or(0);
+    acc += no_panic!(M::find_from(hay, n)).unwrap_or(0);
+    acc += no_panic!(M::find_in(hay, n..hay.len())).unwrap_or(0);
+    acc += no_panic!(M::find_in(hay, hay.len()..n)).unwrap_or(0);
+    acc += no_panic!(M::find_first_with_bucket(hay)).map_or(0, |(p, b)| p + b as usize);
+    acc += no_panic!(M::contains_any(hay)) as usize;
+    acc += no_panic!(M::none_in_set(hay)) as usize;
+    acc += no_panic!(M::all_in_set(hay)) as usize;
+    acc += no_panic!(M::find_first_not(hay)).unwrap_or(0);
+    acc += no_panic!(M::find_last_not(hay)).unwrap_or(0);
+    acc += no_panic!(M::prefix_len(hay));
+    acc += no_panic!(M::suffix_len(hay));
+    acc += no_panic!(M::skip_prefix(hay)).len();
+    acc += no_panic!(M::trim(hay)).len();
+    acc += no_panic!(M::split_at_first_match(hay)).map_or(0, |(a, _, _)| a.len());
+    acc += no_panic!(M::count(hay));
+    acc += no_panic!(M::find_nth(hay, n)).unwrap_or(0);
+    acc += no_panic!(M::field_range(hay, n)).map_or(0, |r| r.end);
+    acc += no_panic!(M::find_n_into(hay, &mut [0; 5]));
+    acc += no_panic!(M::find_run(hay, n)).map_or(0, |r| r.end);
+    acc += no_panic!(M::count_strided(hay, n, hay.len()));
+    acc += no_panic!(M::find_first_strided(hay, n, 1)).unwrap_or(0);
+    acc += no_panic!(M::find_first_within(hay, n)).map_or(1, |r| r.unwrap_or(0));
+    acc += no_panic!(M::find_iter(hay).count());
+    acc += no_panic!(M::find_iter(hay).next_back()).unwrap_or(0);
+    acc += no_panic!(M::split(hay).count());
+    acc += no_panic!(M::splitn(hay, n).count());
+    acc += no_panic!(M::split_inclusive(hay).count());
+    acc += no_panic!(M::tokens(hay).count());
+    acc += no_panic!(M::match_runs(hay).count());
+    acc += no_panic!(M::gap_runs(hay).count());
+    acc += no_panic!(M::find_iter_located(hay).count());
+    acc += no_panic!(M::find_iter_strided(hay, n, 1).count());
+    acc
+}
+
+#[inline(never)]
+fn dynamic(finder: &Finder, truffle: &TruffleTable, hay: &[u8]) -> usize {
+    let mut acc = 0;
+    acc += no_panic!(finder.find(hay)).unwrap_or(0);
+    acc += no_panic!(finder.rfind(hay)).unwrap_or(0);
+    acc += no_panic!(finder.count(hay));
+    acc += no_panic!(finder.find_iter(hay).count());
+    acc += no_panic!(truffle.find_first(hay)).unwrap_or(0);
+    acc
+}
+
+#[test]
+fn test_search_entry_points_link_without_panics() {
+    let finders = [
+        Finder::new(b"\t\r\n"),
+        Finder::new(b"a"),
+        !Finder::new(b"a"),
+    ];
+    let truffle = TruffleTable::new(b"\t\n\xff");
+    let mut acc = 0;
+    for len in [0, 1, 15, 16, 17, 64, 100] {
+        for fill in [0u8, b'\n', b'a', 0xff] {
+            let hay = vec![fill; len];
+            for n in [0, len / 2, len, len + 1, usize::MAX] {
+                acc += derived::<Ws>(&hay, n) + derived::<Nul>(&hay, n) + derived::<Wide>(&hay, n);
+            }
+            for finder in &finders {
+                acc += dynamic(finder, &truffle, &hay);
+            }
+        }
+    }
+    assert!(acc > 0);
+}